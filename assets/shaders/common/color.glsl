@@ -0,0 +1,34 @@
+// Output of the ortho and perspective fragment shaders, textured with TEXTURED
+out vec4 OutColor;
+
+#ifdef TEXTURED
+in vec2 TCoord;
+
+#ifdef TEXTURE_ARRAY
+uniform sampler2DArray texture1;
+// Layer to sample, e.g. an animation frame
+uniform int layer;
+#else
+uniform sampler2D texture1;
+#endif
+#else
+in vec4 Color;
+#endif
+
+vec4 getColor()
+{
+#if defined(TEXTURED) && defined(TEXTURE_ARRAY)
+    vec4 color = texture(texture1, vec3(TCoord, float(layer)));
+#elif defined(TEXTURED)
+    vec4 color = texture(texture1, TCoord);
+#else
+    vec4 color = Color;
+#endif
+
+#ifdef ALPHA_TEST
+    if (color.a < 0.5) {
+        discard;
+    }
+#endif
+    return color;
+}
@@ -0,0 +1,15 @@
+// Per frame data shared by all programs, must match FrameBlock in graphics/frame_uniforms.rs
+#include "common/light.glsl"
+
+layout (std140) uniform Frame {
+    mat4 view;
+    // Perspective projection, 2D draws use orthoProjection
+    mat4 projection;
+    mat4 orthoProjection;
+    vec3 viewPosition;
+    float time;
+    vec3 ambientLight;
+    int lightCount;
+    int blinn;
+    Light lights[MAX_LIGHTS];
+};
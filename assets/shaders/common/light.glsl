@@ -0,0 +1,22 @@
+// Must match MAX_LIGHTS in graphics/light.rs
+#define MAX_LIGHTS 16
+
+#define LIGHT_DIRECTIONAL 0
+#define LIGHT_POINT 1
+#define LIGHT_SPOT 2
+
+struct Light {
+    int type;
+    vec3 position;
+    vec3 direction;
+    vec3 color;
+
+    // Attenuation
+    float constant;
+    float linear;
+    float quadratic;
+
+    // Spot cone, cosines of the inner and outer angle
+    float cutOff;
+    float outerCutOff;
+};
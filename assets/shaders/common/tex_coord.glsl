@@ -0,0 +1,10 @@
+// Repeats the texture along the width of stretched quads, e.g. walls
+uniform float texWidthScale;
+
+vec2 scaleTexCoord(vec2 texCoord)
+{
+    if (texWidthScale > 0.0) {
+        return vec2(texCoord.x * texWidthScale, texCoord.y);
+    }
+    return texCoord;
+}
@@ -0,0 +1,79 @@
+#version 330 core
+out vec4 OutColor;
+
+in vec3 FragPosition;
+in vec3 Normal;
+in vec2 TCoord;
+
+#include "common/frame.glsl"
+
+struct Material {
+    vec3 ambient;
+    vec3 diffuse;
+    vec3 specular;
+    float shininess;
+};
+
+uniform Material material;
+
+uniform bool useTexture;
+uniform sampler2D texture1;
+
+vec3 calculateLight(Light light, vec3 normal, vec3 viewDirection, vec3 diffuseColor)
+{
+    vec3 lightDirection;
+    float attenuation = 1.0;
+
+    if (light.type == LIGHT_DIRECTIONAL) {
+        lightDirection = normalize(-light.direction);
+    } else {
+        vec3 toLight = light.position - FragPosition;
+        float distance = length(toLight);
+        lightDirection = toLight / distance;
+        attenuation = 1.0 / (light.constant + light.linear * distance + light.quadratic * distance * distance);
+
+        if (light.type == LIGHT_SPOT) {
+            float theta = dot(lightDirection, normalize(-light.direction));
+            // Equal cutoffs give a hard edge, MIN_SPOT_EPSILON in light.rs
+            float epsilon = max(light.cutOff - light.outerCutOff, 0.0001);
+            attenuation *= clamp((theta - light.outerCutOff) / epsilon, 0.0, 1.0);
+        }
+    }
+
+    // Diffuse
+    float diffuseStrength = max(dot(normal, lightDirection), 0.0);
+
+    // Specular
+    float specularStrength = 0.0;
+    if (diffuseStrength > 0.0) {
+        if (blinn == 1) {
+            vec3 halfway = normalize(lightDirection + viewDirection);
+            specularStrength = pow(max(dot(normal, halfway), 0.0), material.shininess);
+        } else {
+            vec3 reflectDirection = reflect(-lightDirection, normal);
+            specularStrength = pow(max(dot(viewDirection, reflectDirection), 0.0), material.shininess);
+        }
+    }
+
+    vec3 diffuse = light.color * diffuseStrength * diffuseColor;
+    vec3 specular = light.color * specularStrength * material.specular;
+    return (diffuse + specular) * attenuation;
+}
+
+void main()
+{
+    vec4 baseColor = vec4(material.diffuse, 1.0);
+    if (useTexture) {
+        baseColor *= texture(texture1, TCoord);
+    }
+
+    vec3 normal = normalize(Normal);
+    vec3 viewDirection = normalize(viewPosition - FragPosition);
+
+    vec3 color = ambientLight * (material.ambient + baseColor.rgb);
+    for (int i = 0; i < min(lightCount, MAX_LIGHTS); i++) {
+        color += calculateLight(lights[i], normal, viewDirection, baseColor.rgb);
+    }
+
+    OutColor = vec4(color, baseColor.a);
+}
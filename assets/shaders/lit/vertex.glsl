@@ -0,0 +1,29 @@
+#version 330 core
+
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec2 textCoord;
+layout (location = 2) in vec3 normal;
+
+#include "common/frame.glsl"
+
+uniform mat4 model;
+// Inverse transpose of the model matrix, keeps normals perpendicular under non-uniform scale
+uniform mat3 normalMatrix;
+
+#include "common/tex_coord.glsl"
+
+out vec3 FragPosition;
+out vec3 Normal;
+out vec2 TCoord;
+
+void main()
+{
+    vec4 worldPosition = model * vec4(position, 1.0);
+    gl_Position = projection * view * worldPosition;
+
+    // Lighting is done in world space
+    FragPosition = worldPosition.xyz;
+    Normal = normalMatrix * normal;
+
+    TCoord = scaleTexCoord(textCoord);
+}
@@ -1,9 +1,8 @@
 #version 330 core
-out vec4 OutColor;
 
-in vec4 Color;
+#include "common/color.glsl"
 
 void main()
 {
-    OutColor = Color;
-}
\ No newline at end of file
+    OutColor = getColor();
+}
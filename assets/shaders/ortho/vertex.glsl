@@ -2,14 +2,29 @@
 
 layout (location = 0) in vec3 position;
 
+#include "common/frame.glsl"
+
 uniform mat4 model;
-uniform mat4 projection;
+
+#ifdef TEXTURED
+layout (location = 1) in vec2 textCoord;
+
+#include "common/tex_coord.glsl"
+
+out vec2 TCoord;
+#else
 uniform vec4 diffuse;
 
 out vec4 Color;
+#endif
 
 void main()
 {
-    gl_Position = projection * model * vec4(position, 1.0);
+    gl_Position = orthoProjection * model * vec4(position, 1.0);
+
+#ifdef TEXTURED
+    TCoord = scaleTexCoord(textCoord);
+#else
     Color = diffuse;
-}
\ No newline at end of file
+#endif
+}
@@ -1,19 +1,30 @@
 #version 330 core
 
 layout (location = 0) in vec3 position;
-//layout (location = 1) in vec2 textCoord;
+
+#include "common/frame.glsl"
 
 uniform mat4 model;
-uniform mat4 view;
-uniform mat4 projection;
+
+#ifdef TEXTURED
+layout (location = 1) in vec2 textCoord;
+
+#include "common/tex_coord.glsl"
+
+out vec2 TCoord;
+#else
 uniform vec4 diffuse;
 
-//out vec4 Color;
 out vec4 Color;
+#endif
 
 void main()
 {
     gl_Position = projection * view * model * vec4(position, 1.0);
+
+#ifdef TEXTURED
+    TCoord = scaleTexCoord(textCoord);
+#else
     Color = diffuse;
-//    TextCoord = textCoord;
-}
\ No newline at end of file
+#endif
+}
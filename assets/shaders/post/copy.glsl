@@ -0,0 +1,11 @@
+#version 330 core
+out vec4 OutColor;
+
+in vec2 TCoord;
+
+uniform sampler2D screen;
+
+void main()
+{
+    OutColor = texture(screen, TCoord);
+}
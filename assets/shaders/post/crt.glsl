@@ -0,0 +1,33 @@
+#version 330 core
+out vec4 OutColor;
+
+in vec2 TCoord;
+
+uniform sampler2D screen;
+uniform vec2 resolution;
+// Darkness of every other line
+uniform float scanlineIntensity;
+// Barrel distortion, 0 is flat
+uniform float curvature;
+
+void main()
+{
+    // Bend the screen around the center
+    vec2 centered = TCoord * 2.0 - 1.0;
+    centered *= 1.0 + curvature * dot(centered, centered);
+    vec2 coord = centered * 0.5 + 0.5;
+
+    if (coord.x < 0.0 || coord.x > 1.0 || coord.y < 0.0 || coord.y > 1.0) {
+        OutColor = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    vec4 color = texture(screen, coord);
+
+    float scanline = mod(floor(coord.y * resolution.y), 2.0);
+    color.rgb *= 1.0 - scanlineIntensity * scanline;
+
+    // Darken the corners a little
+    float vignette = 1.0 - 0.3 * dot(centered, centered) * curvature * 4.0;
+    OutColor = vec4(color.rgb * clamp(vignette, 0.0, 1.0), color.a);
+}
@@ -0,0 +1,14 @@
+#version 330 core
+out vec4 OutColor;
+
+in vec2 TCoord;
+
+uniform sampler2D screen;
+uniform vec3 tintColor;
+uniform float strength;
+
+void main()
+{
+    vec4 color = texture(screen, TCoord);
+    OutColor = vec4(mix(color.rgb, tintColor, clamp(strength, 0.0, 1.0)), color.a);
+}
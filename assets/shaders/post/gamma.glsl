@@ -0,0 +1,13 @@
+#version 330 core
+out vec4 OutColor;
+
+in vec2 TCoord;
+
+uniform sampler2D screen;
+uniform float gamma;
+
+void main()
+{
+    vec4 color = texture(screen, TCoord);
+    OutColor = vec4(pow(color.rgb, vec3(1.0 / gamma)), color.a);
+}
@@ -0,0 +1,18 @@
+#version 330 core
+out vec4 OutColor;
+
+in vec2 TCoord;
+
+uniform sampler2D screen;
+// Maps a color to the nearest palette color
+uniform sampler3D paletteLut;
+uniform float lutSize;
+
+void main()
+{
+    vec4 color = texture(screen, TCoord);
+
+    // Sample texel centers
+    vec3 coord = clamp(color.rgb, 0.0, 1.0) * (lutSize - 1.0) / lutSize + 0.5 / lutSize;
+    OutColor = vec4(texture(paletteLut, coord).rgb, color.a);
+}
@@ -0,0 +1,13 @@
+#version 330 core
+
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec2 textCoord;
+
+out vec2 TCoord;
+
+void main()
+{
+    // Unit quad scaled to cover the whole screen
+    gl_Position = vec4(position.xy * 2.0, 0.0, 1.0);
+    TCoord = textCoord;
+}
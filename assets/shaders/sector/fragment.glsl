@@ -0,0 +1,57 @@
+#version 330 core
+out vec4 OutColor;
+
+in vec2 TCoord;
+in float FragLight;
+in float Depth;
+
+// Must match graphics/colormap.rs
+#define LIGHT_LEVELS 16.0
+#define NUM_COLORMAPS 32.0
+
+#define LIGHT_MODE_PALETTE 0
+#define LIGHT_MODE_SMOOTH 1
+
+uniform sampler2D texture1;
+
+// Color -> palette index, palette index + light row -> palette index, palette index -> color
+uniform sampler3D paletteIndexLut;
+uniform sampler2D colormap;
+uniform sampler2D palette;
+uniform float lutSize;
+
+uniform int lightMode;
+// -1 or +1 for walls along the x or z axis
+uniform int lightContrast;
+// Map units per world unit
+uniform float lightDistanceScale;
+
+// Same as the zlight table of the original renderer
+float colormapRow()
+{
+    float lightIndex = clamp(floor(FragLight / 16.0) + float(lightContrast), 0.0, LIGHT_LEVELS - 1.0);
+    float startMap = (LIGHT_LEVELS - 1.0 - lightIndex) * 2.0 * NUM_COLORMAPS / LIGHT_LEVELS;
+    float distance = max(Depth * lightDistanceScale, 1.0);
+    return clamp(startMap - 1280.0 / distance, 0.0, NUM_COLORMAPS - 1.0);
+}
+
+void main()
+{
+    vec4 color = texture(texture1, TCoord);
+
+    // Sprites and masked textures
+    if (color.a < 0.5) {
+        discard;
+    }
+
+    float row = colormapRow();
+
+    if (lightMode == LIGHT_MODE_PALETTE) {
+        vec3 coord = clamp(color.rgb, 0.0, 1.0) * (lutSize - 1.0) / lutSize + 0.5 / lutSize;
+        int index = int(texture(paletteIndexLut, coord).r * 255.0 + 0.5);
+        int mapped = int(texelFetch(colormap, ivec2(index, int(row)), 0).r * 255.0 + 0.5);
+        OutColor = vec4(texelFetch(palette, ivec2(mapped, 0), 0).rgb, 1.0);
+    } else {
+        OutColor = vec4(color.rgb * (1.0 - row / NUM_COLORMAPS), 1.0);
+    }
+}
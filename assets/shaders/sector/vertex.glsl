@@ -0,0 +1,36 @@
+#version 330 core
+
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec2 textCoord;
+layout (location = 5) in float lightLevel;
+
+#include "common/frame.glsl"
+
+uniform mat4 model;
+
+#include "common/tex_coord.glsl"
+
+// Sector light, 0-255, either per draw or baked into the vertices
+uniform float sectorLight;
+uniform bool useVertexLight;
+
+out vec2 TCoord;
+out float FragLight;
+out float Depth;
+
+void main()
+{
+    vec4 viewPosition = view * model * vec4(position, 1.0);
+    gl_Position = projection * viewPosition;
+
+    // Distance along the view direction, like Doom's z
+    Depth = -viewPosition.z;
+
+    if (useVertexLight) {
+        FragLight = lightLevel;
+    } else {
+        FragLight = sectorLight;
+    }
+
+    TCoord = scaleTexCoord(textCoord);
+}
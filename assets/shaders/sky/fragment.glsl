@@ -0,0 +1,27 @@
+#version 330 core
+out vec4 OutColor;
+
+in vec3 Direction;
+
+uniform sampler2D sky;
+// Times the texture wraps around per revolution, Doom's 256 wide SKY1 wraps 4 times
+uniform float skyRepeat;
+// Angle above the horizon covered by the texture height, in radians
+uniform float skyHeight;
+
+const float PI = 3.14159265;
+
+void main()
+{
+    // Only the direction matters, like Doom the sky is infinitely far away
+    vec3 direction = normalize(Direction);
+
+    float yaw = atan(direction.z, direction.x);
+    float u = yaw / (2.0 * PI) * skyRepeat;
+
+    // Bottom of the texture sits on the horizon, the bottom row is stretched below it
+    float elevation = asin(clamp(direction.y, -1.0, 1.0));
+    float v = clamp(elevation / skyHeight, 0.0, 0.999);
+
+    OutColor = texture(sky, vec2(u, v));
+}
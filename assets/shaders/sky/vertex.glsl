@@ -0,0 +1,25 @@
+#version 330 core
+
+layout (location = 0) in vec3 position;
+
+#include "common/frame.glsl"
+
+uniform mat4 model;
+// Background draws are pushed to the far plane, sky ceilings keep their depth
+uniform bool background;
+
+out vec3 Direction;
+
+void main()
+{
+    vec4 worldPosition = model * vec4(position, 1.0);
+
+    if (background) {
+        // Centered on the camera, without translation
+        Direction = worldPosition.xyz;
+        gl_Position = (projection * mat4(mat3(view)) * worldPosition).xyww;
+    } else {
+        Direction = worldPosition.xyz - viewPosition;
+        gl_Position = projection * view * worldPosition;
+    }
+}
@@ -0,0 +1,11 @@
+#version 330 core
+out vec4 OutColor;
+
+in vec3 Direction;
+
+uniform samplerCube skybox;
+
+void main()
+{
+    OutColor = texture(skybox, Direction);
+}
@@ -0,0 +1,19 @@
+#version 330 core
+
+layout (location = 0) in vec3 position;
+
+#include "common/frame.glsl"
+
+out vec3 Direction;
+
+void main()
+{
+    Direction = position;
+
+    // View without translation, so the sky stays centered on the camera
+    mat4 viewRotation = mat4(mat3(view));
+
+    // Force depth to the far plane, so the sky sits behind everything
+    vec4 clipPosition = projection * viewRotation * vec4(position, 1.0);
+    gl_Position = clipPosition.xyww;
+}
@@ -1,12 +0,0 @@
-#version 330 core
-out vec4 OutColor;
-
-//in vec4 Color;
-in vec2 TCoord;
-
-uniform sampler2D texture1;
-
-void main()
-{
-    OutColor = texture(texture1, TCoord);
-}
\ No newline at end of file
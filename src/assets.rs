@@ -1 +1,5 @@
-pub mod shader_loader;
\ No newline at end of file
+pub mod asset_root;
+pub mod manifest;
+pub mod shader_loader;
+pub mod vfs;
+pub mod worker_pool;
@@ -0,0 +1,120 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::Lazy;
+
+// `--assets <dir>` on the command line
+pub const ASSET_ROOT_FLAG: &str = "--assets";
+pub const ASSET_ROOT_ENV: &str = "POCKET_DIMENSION_ASSETS";
+// Folder name looked for next to the executable and in the working directory
+const ASSET_FOLDER: &str = "assets";
+// A folder only counts as an asset root when it has a manifest
+pub const MANIFEST_FILE: &str = "manifest.toml";
+
+// Resolved on first use, force it at startup so the root is logged before anything loads
+pub static ASSET_ROOT: Lazy<PathBuf> = Lazy::new(|| {
+    let exe_dir = std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf));
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let (root, source) = resolve_asset_root(std::env::args().skip(1),
+                                            std::env::var_os(ASSET_ROOT_ENV).map(PathBuf::from),
+                                            exe_dir.as_deref(),
+                                            &cwd);
+    println!("Asset root: {} ({})", root.display(), source);
+    root
+});
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AssetRootSource {
+    Flag,
+    Env,
+    Executable,
+    WorkingDirectory,
+}
+
+impl fmt::Display for AssetRootSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetRootSource::Flag => write!(f, "from {}", ASSET_ROOT_FLAG),
+            AssetRootSource::Env => write!(f, "from {}", ASSET_ROOT_ENV),
+            AssetRootSource::Executable => write!(f, "next to the executable"),
+            AssetRootSource::WorkingDirectory => write!(f, "in the working directory"),
+        }
+    }
+}
+
+// Command line flag, then env var, then an assets folder with a manifest next to the executable,
+// then the one in the working directory. An explicit root is used even if it looks wrong,
+// loading the manifest then fails with the path in the error.
+pub fn resolve_asset_root(args: impl IntoIterator<Item = String>,
+                          env: Option<PathBuf>,
+                          exe_dir: Option<&Path>,
+                          cwd: &Path) -> (PathBuf, AssetRootSource) {
+    if let Some(root) = parse_flag(args) {
+        return (root, AssetRootSource::Flag);
+    }
+    if let Some(root) = env.filter(|root| !root.as_os_str().is_empty()) {
+        return (root, AssetRootSource::Env);
+    }
+    if let Some(root) = exe_dir.map(|dir| dir.join(ASSET_FOLDER)) {
+        if root.join(MANIFEST_FILE).is_file() {
+            return (root, AssetRootSource::Executable);
+        }
+    }
+    (cwd.join(ASSET_FOLDER), AssetRootSource::WorkingDirectory)
+}
+
+// Accepts `--assets dir` and `--assets=dir`
+fn parse_flag(args: impl IntoIterator<Item = String>) -> Option<PathBuf> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == ASSET_ROOT_FLAG {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(root) = arg.strip_prefix(ASSET_ROOT_FLAG).and_then(|rest| rest.strip_prefix('=')) {
+            return Some(PathBuf::from(root));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn prefers_flag_then_env() {
+        let cwd = Path::new("/game");
+        let env = Some(PathBuf::from("/env/assets"));
+
+        assert_eq!(resolve_asset_root(args(&["--assets", "/flag"]), env.clone(), None, cwd),
+                   (PathBuf::from("/flag"), AssetRootSource::Flag));
+        assert_eq!(resolve_asset_root(args(&["--assets=/flag"]), env.clone(), None, cwd),
+                   (PathBuf::from("/flag"), AssetRootSource::Flag));
+        assert_eq!(resolve_asset_root(args(&["--fullscreen"]), env, None, cwd),
+                   (PathBuf::from("/env/assets"), AssetRootSource::Env));
+        assert_eq!(resolve_asset_root(args(&[]), None, None, cwd),
+                   (PathBuf::from("/game/assets"), AssetRootSource::WorkingDirectory));
+    }
+
+    #[test]
+    fn uses_assets_next_to_executable_with_manifest() {
+        let exe_dir = std::env::temp_dir().join(format!("asset_root_{}", std::process::id()));
+        let cwd = Path::new("/game");
+        fs::create_dir_all(exe_dir.join(ASSET_FOLDER)).unwrap();
+
+        // Without a manifest the folder is ignored
+        assert_eq!(resolve_asset_root(args(&[]), None, Some(&exe_dir), cwd).1, AssetRootSource::WorkingDirectory);
+
+        fs::write(exe_dir.join(ASSET_FOLDER).join(MANIFEST_FILE), "").unwrap();
+        assert_eq!(resolve_asset_root(args(&[]), None, Some(&exe_dir), cwd),
+                   (exe_dir.join(ASSET_FOLDER), AssetRootSource::Executable));
+
+        fs::remove_dir_all(exe_dir).unwrap();
+    }
+}
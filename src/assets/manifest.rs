@@ -0,0 +1,496 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::assets::shader_loader::ShaderDefine;
+use crate::assets::vfs::{Vfs, VfsError};
+use crate::graphics::texture::{SamplerSettings, TextureFilter, TextureWrap};
+use crate::registry::asset_store::AssetScope;
+use crate::registry::mesh_registry::{MeshId, MeshShape};
+use crate::registry::model_registry::ModelId;
+use crate::registry::shader_registry::{ShaderId, ShaderProgramId};
+use crate::registry::texture_registry::TextureId;
+
+#[derive(Debug)]
+pub enum ManifestError {
+    Read(VfsError),
+    // Bad syntax, unknown fields and duplicate keys
+    Parse {
+        path: PathBuf,
+        error: toml::de::Error,
+    },
+    // An entry refers to a key its section doesn't have
+    UnknownKey {
+        section: &'static str,
+        key: String,
+        referenced_by: String,
+    },
+    MissingFile {
+        section: &'static str,
+        key: String,
+        path: PathBuf,
+    },
+    // Fields that don't go together
+    InvalidEntry {
+        section: &'static str,
+        key: String,
+        message: &'static str,
+    },
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::Read(error) => {
+                write!(f, "Failed to read asset manifest: {}", error)
+            }
+            ManifestError::Parse { path, error } => {
+                write!(f, "Failed to parse asset manifest {}:\n{}", path.display(), error)
+            }
+            ManifestError::UnknownKey { section, key, referenced_by } => {
+                write!(f, "{} refers to '{}', which is not in [{}]", referenced_by, key, section)
+            }
+            ManifestError::MissingFile { section, key, path } => {
+                write!(f, "{}.{}: {} does not exist", section, key, path.display())
+            }
+            ManifestError::InvalidEntry { section, key, message } => {
+                write!(f, "{}.{}: {}", section, key, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+// Manifest file as written, entries refer to each other by key
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestFile {
+    #[serde(default)]
+    shaders: BTreeMap<String, PathBuf>,
+    #[serde(default)]
+    programs: BTreeMap<String, ProgramFile>,
+    #[serde(default)]
+    textures: BTreeMap<String, TextureFile>,
+    #[serde(default)]
+    meshes: BTreeMap<String, MeshFile>,
+    #[serde(default)]
+    models: BTreeMap<String, ModelFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProgramFile {
+    vertex: String,
+    fragment: String,
+    #[serde(default)]
+    defines: Vec<ShaderDefine>,
+    #[serde(default)]
+    scope: AssetScope,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TextureFile {
+    path: Option<PathBuf>,
+    #[serde(default)]
+    layers: Vec<PathBuf>,
+    #[serde(default)]
+    filter: TextureFilter,
+    #[serde(default)]
+    wrap: TextureWrap,
+    #[serde(default)]
+    mipmaps: bool,
+    anisotropy: Option<f32>,
+    #[serde(default)]
+    srgb: bool,
+    #[serde(default)]
+    cubemap: bool,
+    #[serde(default)]
+    scope: AssetScope,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MeshFile {
+    shape: MeshShape,
+    program: String,
+    texture: Option<String>,
+    #[serde(default)]
+    scope: AssetScope,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelFile {
+    path: PathBuf,
+    program: String,
+    #[serde(default)]
+    scope: AssetScope,
+}
+
+pub struct ProgramEntry {
+    pub vertex: ShaderId,
+    pub fragment: ShaderId,
+    pub defines: Vec<ShaderDefine>,
+    pub scope: AssetScope,
+}
+
+#[derive(Clone)]
+pub enum TextureSource {
+    Image(PathBuf),
+    // Folder of six faces
+    Cubemap(PathBuf),
+    // One array layer per image, e.g. animation frames
+    Layers(Vec<PathBuf>),
+}
+
+impl TextureSource {
+    // Every file the texture is read from, a cubemap's folder for cubemaps
+    pub fn get_paths(&self) -> &[PathBuf] {
+        match self {
+            TextureSource::Image(path) | TextureSource::Cubemap(path) => std::slice::from_ref(path),
+            TextureSource::Layers(paths) => paths,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TextureEntry {
+    pub source: TextureSource,
+    pub sampler: SamplerSettings,
+    pub scope: AssetScope,
+}
+
+#[derive(Clone, Copy)]
+pub struct MeshEntry {
+    pub shape: MeshShape,
+    pub program: ShaderProgramId,
+    pub texture: Option<TextureId>,
+    pub scope: AssetScope,
+}
+
+pub struct ModelEntry {
+    pub path: PathBuf,
+    pub program: ShaderProgramId,
+    pub scope: AssetScope,
+}
+
+// Entries of one section in key order, handles index into it
+struct Section<T> {
+    name: &'static str,
+    keys: Vec<String>,
+    entries: Vec<T>,
+}
+
+impl<T> Section<T> {
+    fn new<F>(name: &'static str, entries: BTreeMap<String, F>, mut resolve: impl FnMut(&str, F) -> Result<T, ManifestError>) -> Result<Self, ManifestError> {
+        let mut section = Section {
+            name,
+            keys: Vec::with_capacity(entries.len()),
+            entries: Vec::with_capacity(entries.len()),
+        };
+        for (key, entry) in entries {
+            section.entries.push(resolve(&key, entry)?);
+            section.keys.push(key);
+        }
+        Ok(section)
+    }
+
+    // Index of the key, `referenced_by` names the entry asking for the error message
+    fn find(&self, key: &str, referenced_by: &str) -> Result<usize, ManifestError> {
+        self.keys.iter().position(|k| k == key).ok_or_else(|| ManifestError::UnknownKey {
+            section: self.name,
+            key: key.to_string(),
+            referenced_by: referenced_by.to_string(),
+        })
+    }
+
+    fn get(&self, key: &str) -> usize {
+        self.keys.iter()
+            .position(|k| k == key)
+            .unwrap_or_else(|| panic!("'{}' is not in [{}] of the asset manifest", key, self.name))
+    }
+}
+
+fn invalid_texture(key: &str, message: &'static str) -> ManifestError {
+    ManifestError::InvalidEntry {
+        section: "textures",
+        key: key.to_string(),
+        message,
+    }
+}
+
+// Every asset by key, with the references between entries resolved to typed handles
+pub struct Manifest {
+    root: PathBuf,
+    shaders: Section<PathBuf>,
+    programs: Section<ProgramEntry>,
+    textures: Section<TextureEntry>,
+    meshes: Section<MeshEntry>,
+    models: Section<ModelEntry>,
+}
+
+impl Manifest {
+    // Parses the manifest and checks that every file it lists is in the VFS
+    pub fn load(vfs: &Vfs, path: impl AsRef<Path>) -> Result<Manifest, ManifestError> {
+        let path = path.as_ref();
+        let text = vfs.read_to_string(path).map_err(ManifestError::Read)?;
+        let manifest = Manifest::parse(path, &text)?;
+        manifest.check_files(vfs)?;
+        Ok(manifest)
+    }
+
+    // Paths in the text are VFS paths relative to the folder of `path`
+    pub fn parse(path: &Path, text: &str) -> Result<Manifest, ManifestError> {
+        let file: ManifestFile = toml::from_str(text).map_err(|error| ManifestError::Parse {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        let root = path.parent().unwrap_or(Path::new("")).to_path_buf();
+
+        let shaders = Section::new("shaders", file.shaders, |_, shader| Ok(root.join(shader)))?;
+
+        let programs = Section::new("programs", file.programs, |key, program| {
+            let referenced_by = format!("programs.{}", key);
+            Ok(ProgramEntry {
+                vertex: ShaderId(shaders.find(&program.vertex, &referenced_by)?),
+                fragment: ShaderId(shaders.find(&program.fragment, &referenced_by)?),
+                defines: program.defines,
+                scope: program.scope,
+            })
+        })?;
+
+        let textures = Section::new("textures", file.textures, |key, texture| Ok(TextureEntry {
+            source: match (texture.path, texture.layers.is_empty(), texture.cubemap) {
+                (Some(path), true, false) => TextureSource::Image(root.join(path)),
+                (Some(path), true, true) => TextureSource::Cubemap(root.join(path)),
+                (None, false, false) => TextureSource::Layers(texture.layers.iter().map(|layer| root.join(layer)).collect()),
+                (None, false, true) => return Err(invalid_texture(key, "layers can't be a cubemap")),
+                _ => return Err(invalid_texture(key, "needs either a path or layers")),
+            },
+            sampler: SamplerSettings {
+                filter: texture.filter,
+                wrap: texture.wrap,
+                mipmaps: texture.mipmaps,
+                anisotropy: texture.anisotropy.unwrap_or(1.0),
+                srgb: texture.srgb,
+            },
+            scope: texture.scope,
+        }))?;
+
+        let meshes = Section::new("meshes", file.meshes, |key, mesh| {
+            let referenced_by = format!("meshes.{}", key);
+            Ok(MeshEntry {
+                shape: mesh.shape,
+                program: ShaderProgramId(programs.find(&mesh.program, &referenced_by)?),
+                texture: match mesh.texture {
+                    Some(texture) => Some(TextureId(textures.find(&texture, &referenced_by)?)),
+                    None => None,
+                },
+                scope: mesh.scope,
+            })
+        })?;
+
+        let models = Section::new("models", file.models, |key, model| Ok(ModelEntry {
+            path: root.join(model.path),
+            program: ShaderProgramId(programs.find(&model.program, &format!("models.{}", key))?),
+            scope: model.scope,
+        }))?;
+
+        Ok(Manifest {
+            root,
+            shaders,
+            programs,
+            textures,
+            meshes,
+            models,
+        })
+    }
+
+    fn check_files(&self, vfs: &Vfs) -> Result<(), ManifestError> {
+        let files = self.shaders.keys.iter().zip(&self.shaders.entries).map(|(key, path)| ("shaders", key, path))
+            .chain(self.textures.keys.iter().zip(&self.textures.entries).flat_map(|(key, texture)| {
+                texture.source.get_paths().iter().map(move |path| ("textures", key, path))
+            }))
+            .chain(self.models.keys.iter().zip(&self.models.entries).map(|(key, model)| ("models", key, &model.path)));
+
+        for (section, key, path) in files {
+            if !vfs.exists(path) {
+                return Err(ManifestError::MissingFile {
+                    section,
+                    key: key.clone(),
+                    path: path.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // VFS folder the manifest is in
+    pub fn get_root(&self) -> &Path {
+        &self.root
+    }
+
+    // Lookups by key panic when the key is missing, do them once at startup
+
+    pub fn get_program_id(&self, key: &str) -> ShaderProgramId {
+        ShaderProgramId(self.programs.get(key))
+    }
+
+    pub fn get_texture_id(&self, key: &str) -> TextureId {
+        TextureId(self.textures.get(key))
+    }
+
+    pub fn get_mesh_id(&self, key: &str) -> MeshId {
+        MeshId(self.meshes.get(key))
+    }
+
+    pub fn get_model_id(&self, key: &str) -> ModelId {
+        ModelId(self.models.get(key))
+    }
+
+    pub fn get_shader_path(&self, id: ShaderId) -> &Path {
+        &self.shaders.entries[id.0]
+    }
+
+    pub fn get_program(&self, id: ShaderProgramId) -> &ProgramEntry {
+        &self.programs.entries[id.0]
+    }
+
+    pub fn get_program_key(&self, id: ShaderProgramId) -> &str {
+        &self.programs.keys[id.0]
+    }
+
+    pub fn get_texture(&self, id: TextureId) -> &TextureEntry {
+        &self.textures.entries[id.0]
+    }
+
+    pub fn get_texture_key(&self, id: TextureId) -> &str {
+        &self.textures.keys[id.0]
+    }
+
+    pub fn get_mesh(&self, id: MeshId) -> &MeshEntry {
+        &self.meshes.entries[id.0]
+    }
+
+    pub fn get_mesh_key(&self, id: MeshId) -> &str {
+        &self.meshes.keys[id.0]
+    }
+
+    pub fn get_model(&self, id: ModelId) -> &ModelEntry {
+        &self.models.entries[id.0]
+    }
+
+    pub fn get_model_key(&self, id: ModelId) -> &str {
+        &self.models.keys[id.0]
+    }
+
+    pub fn program_ids(&self) -> impl Iterator<Item = ShaderProgramId> {
+        (0..self.programs.entries.len()).map(ShaderProgramId)
+    }
+
+    pub fn texture_ids(&self) -> impl Iterator<Item = TextureId> {
+        (0..self.textures.entries.len()).map(TextureId)
+    }
+
+    pub fn mesh_ids(&self) -> impl Iterator<Item = MeshId> {
+        (0..self.meshes.entries.len()).map(MeshId)
+    }
+
+    pub fn model_ids(&self) -> impl Iterator<Item = ModelId> {
+        (0..self.models.entries.len()).map(ModelId)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+        [shaders]
+        vertex = "shaders/vertex.glsl"
+        fragment = "shaders/fragment.glsl"
+
+        [programs]
+        textured = { vertex = "vertex", fragment = "fragment", defines = ["TEXTURED"] }
+
+        [textures]
+        wall = { path = "textures/wall.png", filter = "linear", wrap = "clamp", mipmaps = true, scope = "level" }
+
+        [meshes]
+        wall = { shape = "wall", program = "textured", texture = "wall" }
+    "#;
+
+    #[test]
+    fn resolves_references() {
+        let manifest = Manifest::parse(Path::new("assets/manifest.toml"), MANIFEST).unwrap();
+
+        let mesh = manifest.get_mesh(manifest.get_mesh_id("wall"));
+        assert_eq!(mesh.shape, MeshShape::Wall);
+        assert_eq!(mesh.program, manifest.get_program_id("textured"));
+
+        let texture = manifest.get_texture(mesh.texture.unwrap());
+        assert!(matches!(&texture.source, TextureSource::Image(path) if path == Path::new("assets/textures/wall.png")));
+        assert_eq!(texture.sampler, SamplerSettings {
+            filter: TextureFilter::Linear,
+            wrap: TextureWrap::Clamp,
+            mipmaps: true,
+            ..SamplerSettings::default()
+        });
+        assert_eq!((texture.scope, mesh.scope), (AssetScope::Level, AssetScope::Global));
+
+        let program = manifest.get_program(mesh.program);
+        assert_eq!(manifest.get_shader_path(program.fragment), Path::new("assets/shaders/fragment.glsl"));
+        assert_eq!(program.defines, vec![ShaderDefine::Textured]);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let text = MANIFEST.replace("texture = \"wall\"", "texture = \"brick\"");
+        match Manifest::parse(Path::new("manifest.toml"), &text) {
+            Err(error @ ManifestError::UnknownKey { .. }) => {
+                assert_eq!(error.to_string(), "meshes.wall refers to 'brick', which is not in [textures]");
+            }
+            _ => panic!("Expected an unknown key error"),
+        }
+    }
+
+    #[test]
+    fn rejects_duplicate_keys() {
+        let text = "[textures]\nwall = { path = \"a.png\" }\nwall = { path = \"b.png\" }\n";
+        match Manifest::parse(Path::new("manifest.toml"), text) {
+            Err(error @ ManifestError::Parse { .. }) => {
+                assert!(error.to_string().contains("duplicate key"), "{}", error);
+            }
+            _ => panic!("Expected a duplicate key error"),
+        }
+    }
+
+    #[test]
+    fn reads_texture_layers() {
+        let text = "[textures]\nframes = { layers = [\"a.png\", \"b.png\"] }\n";
+        let manifest = Manifest::parse(Path::new("assets/manifest.toml"), text).unwrap();
+        let texture = manifest.get_texture(manifest.get_texture_id("frames"));
+        assert_eq!(texture.source.get_paths(), &[PathBuf::from("assets/a.png"), PathBuf::from("assets/b.png")]);
+
+        let text = "[textures]\nboth = { path = \"a.png\", layers = [\"b.png\"] }\n";
+        match Manifest::parse(Path::new("manifest.toml"), text) {
+            Err(error @ ManifestError::InvalidEntry { .. }) => {
+                assert_eq!(error.to_string(), "textures.both: needs either a path or layers");
+            }
+            _ => panic!("Expected an invalid entry error"),
+        }
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        let manifest = Manifest::parse(Path::new("manifest.toml"), MANIFEST).unwrap();
+        match manifest.check_files(&Vfs::new()) {
+            Err(ManifestError::MissingFile { section, key, .. }) => assert_eq!((section, key.as_str()), ("shaders", "fragment")),
+            _ => panic!("Expected a missing file error"),
+        }
+    }
+}
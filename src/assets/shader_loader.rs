@@ -1,9 +1,196 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-pub fn load_shader_source(location: impl AsRef<Path>) -> String {
-    let path = location.as_ref();
-    if !path.exists() {
-        panic!("Shader file not found: {:?}", path);
+use serde::Deserialize;
+
+use crate::assets::vfs::Vfs;
+use crate::graphics::shader_error::{ShaderError, SourceMap};
+
+// Permutations of one shader file, each adds a `#define` after `#version`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ShaderDefine {
+    Textured,
+    Lit,
+    AlphaTest,
+    // Samples a layer of a sampler2DArray, needs TEXTURED
+    TextureArray,
+}
+
+impl ShaderDefine {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            ShaderDefine::Textured => "TEXTURED",
+            ShaderDefine::Lit => "LIT",
+            ShaderDefine::AlphaTest => "ALPHA_TEST",
+            ShaderDefine::TextureArray => "TEXTURE_ARRAY",
+        }
+    }
+}
+
+// Preprocessed source, ready for glShaderSource
+pub struct ShaderSource {
+    pub source: String,
+    pub source_map: SourceMap,
+    // The shader and every file it includes, watched for hot reloading
+    pub files: Vec<PathBuf>,
+}
+
+// Resolves `#include "common/..."` relative to the shader root and injects the defines.
+// Every file is included once, including a file that is still being included is a cycle.
+// Paths are VFS paths.
+pub fn load_shader_source(vfs: &Vfs, root: impl AsRef<Path>, location: impl AsRef<Path>, defines: &[ShaderDefine]) -> Result<ShaderSource, ShaderError> {
+    let mut preprocessor = Preprocessor {
+        vfs,
+        root: root.as_ref(),
+        defines,
+        output: ShaderSource {
+            source: String::new(),
+            source_map: SourceMap::new(),
+            files: Vec::new(),
+        },
+        stack: Vec::new(),
+    };
+    preprocessor.include(location.as_ref())?;
+    Ok(preprocessor.output)
+}
+
+struct Preprocessor<'a> {
+    vfs: &'a Vfs,
+    root: &'a Path,
+    defines: &'a [ShaderDefine],
+    output: ShaderSource,
+    // Files currently being included, outermost first
+    stack: Vec<PathBuf>,
+}
+
+impl Preprocessor<'_> {
+    fn include(&mut self, path: &Path) -> Result<(), ShaderError> {
+        let text = self.vfs.read_to_string(path).map_err(ShaderError::Read)?;
+        self.output.files.push(path.to_path_buf());
+        self.stack.push(path.to_path_buf());
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let preprocess_error = |message: String| ShaderError::Preprocess {
+                path: path.to_path_buf(),
+                line: line_number,
+                message,
+            };
+
+            if let Some(include) = parse_include(line).map_err(preprocess_error)? {
+                let include_path = self.root.join(include);
+                if self.stack.contains(&include_path) {
+                    let chain: Vec<String> = self.stack.iter()
+                        .chain(std::iter::once(&include_path))
+                        .map(|path| path.display().to_string())
+                        .collect();
+                    return Err(preprocess_error(format!("include cycle: {}", chain.join(" -> "))));
+                }
+                if !self.output.files.contains(&include_path) {
+                    self.include(&include_path)?;
+                }
+                continue;
+            }
+
+            self.push_line(line, path, line_number);
+
+            // Defines have to follow #version, which must come first
+            if self.stack.len() == 1 && line.trim_start().starts_with("#version") {
+                for define in self.defines {
+                    self.push_line(&format!("#define {}", define.get_name()), path, line_number);
+                }
+            }
+        }
+
+        self.stack.pop();
+        Ok(())
+    }
+
+    fn push_line(&mut self, line: &str, path: &Path, line_number: usize) {
+        self.output.source.push_str(line);
+        self.output.source.push('\n');
+        self.output.source_map.push(path, line_number);
+    }
+}
+
+// The quoted path of an `#include "..."` line, None for any other line
+fn parse_include(line: &str) -> Result<Option<&str>, String> {
+    let Some(rest) = line.trim().strip_prefix("#include") else {
+        return Ok(None);
+    };
+
+    rest.trim()
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .filter(|path| !path.is_empty())
+        .map(Some)
+        .ok_or_else(|| format!("malformed include, expected #include \"path\": {}", line.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    // Writes the files to a fresh directory and returns its path with a VFS that mounts it at shaders/
+    fn write_shader_root(name: &str, files: &[(&str, &str)]) -> (PathBuf, Vfs) {
+        let root = std::env::temp_dir().join(format!("shader_loader_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (path, source) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+
+        let mut vfs = Vfs::new();
+        vfs.mount_dir("shaders", &root, 0).unwrap();
+        (root, vfs)
+    }
+
+    #[test]
+    fn resolves_includes_and_defines() {
+        let (root, vfs) = write_shader_root("includes", &[
+            ("main.glsl", "#version 330 core\n#include \"common/a.glsl\"\n#include \"common/b.glsl\"\nvoid main() {}\n"),
+            ("common/a.glsl", "#include \"common/b.glsl\"\nfloat a;\n"),
+            ("common/b.glsl", "float b;\n"),
+        ]);
+
+        let shader = load_shader_source(&vfs, "shaders", "shaders/main.glsl", &[ShaderDefine::Textured, ShaderDefine::AlphaTest]).unwrap();
+        assert_eq!(shader.source, "#version 330 core\n#define TEXTURED\n#define ALPHA_TEST\nfloat b;\nfloat a;\nvoid main() {}\n");
+        assert_eq!(shader.files, ["shaders/main.glsl", "shaders/common/a.glsl", "shaders/common/b.glsl"].map(PathBuf::from));
+
+        // Line 5 of the output is the second line of a.glsl
+        let (path, line) = shader.source_map.locate(5).unwrap();
+        assert_eq!((path, line), (Path::new("shaders/common/a.glsl"), 2));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn detects_include_cycles() {
+        let (root, vfs) = write_shader_root("cycle", &[
+            ("main.glsl", "#version 330 core\n#include \"common/a.glsl\"\n"),
+            ("common/a.glsl", "#include \"common/b.glsl\"\n"),
+            ("common/b.glsl", "\n#include \"common/a.glsl\"\n"),
+        ]);
+
+        match load_shader_source(&vfs, "shaders", "shaders/main.glsl", &[]) {
+            Err(ShaderError::Preprocess { path, line, message }) => {
+                assert_eq!(path, Path::new("shaders/common/b.glsl"));
+                assert_eq!(line, 2);
+                assert!(message.starts_with("include cycle"), "{}", message);
+            }
+            _ => panic!("Expected an include cycle error"),
+        }
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn rejects_malformed_includes() {
+        assert_eq!(parse_include("float a;"), Ok(None));
+        assert_eq!(parse_include("  #include \"common/a.glsl\""), Ok(Some("common/a.glsl")));
+        assert!(parse_include("#include <common/a.glsl>").is_err());
     }
-    std::fs::read_to_string(path).expect("Failed to read shader file")
-}
\ No newline at end of file
+}
@@ -0,0 +1,579 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use zip::ZipArchive;
+
+use crate::assets::asset_root::ASSET_ROOT;
+
+// Files from the command line are mounted over the asset root
+const FILE_PRIORITY: i32 = 1;
+
+#[derive(Debug)]
+pub enum VfsError {
+    NotFound {
+        path: String,
+    },
+    Io {
+        path: String,
+        error: io::Error,
+    },
+    // Unreadable WAD or zip
+    Archive {
+        path: PathBuf,
+        message: String,
+    },
+}
+
+impl fmt::Display for VfsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VfsError::NotFound { path } => write!(f, "{} is not in any mount", path),
+            VfsError::Io { path, error } => write!(f, "Failed to read {}: {}", path, error),
+            VfsError::Archive { path, message } => write!(f, "Failed to mount {}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for VfsError {}
+
+// Files of one mount, paths are relative to the mount point and use `/`
+trait MountSource: Send + Sync {
+    // None when the mount doesn't have the file
+    fn read(&self, path: &str) -> Option<io::Result<Vec<u8>>>;
+    fn is_file(&self, path: &str) -> bool;
+    fn is_dir(&self, path: &str) -> bool;
+    // Names of the files and folders directly in `dir`
+    fn list(&self, dir: &str) -> Vec<String>;
+
+    // Only loose files can change while running
+    fn get_modified(&self, _path: &str) -> Option<SystemTime> {
+        None
+    }
+}
+
+struct Mount {
+    // Prefix of the paths this mount serves, empty for the root
+    point: String,
+    priority: i32,
+    source: Box<dyn MountSource>,
+}
+
+impl Mount {
+    // Path inside the mount, None when the path is outside the mount point
+    fn get_local<'a>(&self, path: &'a str) -> Option<&'a str> {
+        if self.point.is_empty() {
+            return Some(path);
+        }
+        if path == self.point {
+            return Some("");
+        }
+        path.strip_prefix(self.point.as_str())?.strip_prefix('/')
+    }
+}
+
+// One namespace over directories and archives. Higher priority mounts hide the files of lower ones,
+// with equal priority the mount added last wins, like a PWAD loaded after the IWAD.
+pub struct Vfs {
+    // Highest priority first
+    mounts: Vec<Mount>,
+}
+
+impl Vfs {
+    // Loose files under the asset root, WADs and PK3s are mounted on top at runtime
+    pub fn with_asset_root() -> Self {
+        let mut vfs = Vfs::new();
+        vfs.mount_dir("", &*ASSET_ROOT, 0).unwrap_or_else(|error| panic!("{}", error));
+        vfs
+    }
+
+    // Asset root with folders, WADs and PK3s on top, later files win like Doom's -file
+    pub fn with_files(files: &[PathBuf]) -> Result<Self, VfsError> {
+        let mut vfs = Vfs::with_asset_root();
+        for file in files {
+            vfs.mount("", file, FILE_PRIORITY)?;
+            println!("Mounted {}", file.display());
+        }
+
+        let maps = vfs.list("maps");
+        if !maps.is_empty() {
+            let names: Vec<&str> = maps.iter().map(|map| map.trim_start_matches("maps/")).collect();
+            println!("Maps: {}", names.join(", "));
+        }
+        Ok(vfs)
+    }
+
+    pub fn new() -> Self {
+        Self {
+            mounts: Vec::new(),
+        }
+    }
+
+    fn add_mount(&mut self, point: &str, priority: i32, source: Box<dyn MountSource>) {
+        let index = self.mounts.iter().position(|mount| mount.priority <= priority).unwrap_or(self.mounts.len());
+        self.mounts.insert(index, Mount {
+            point: normalize(point),
+            priority,
+            source,
+        });
+    }
+
+    pub fn mount_dir(&mut self, point: &str, path: impl AsRef<Path>, priority: i32) -> Result<(), VfsError> {
+        let path = path.as_ref();
+        if !path.is_dir() {
+            return Err(VfsError::Archive {
+                path: path.to_path_buf(),
+                message: "not a directory".to_string(),
+            });
+        }
+        self.add_mount(point, priority, Box::new(DirMount { root: path.to_path_buf() }));
+        Ok(())
+    }
+
+    pub fn mount_wad(&mut self, point: &str, path: impl AsRef<Path>, priority: i32) -> Result<(), VfsError> {
+        let wad = WadMount::open(path.as_ref())?;
+        self.add_mount(point, priority, Box::new(wad));
+        Ok(())
+    }
+
+    pub fn mount_zip(&mut self, point: &str, path: impl AsRef<Path>, priority: i32) -> Result<(), VfsError> {
+        let zip = ZipMount::open(path.as_ref())?;
+        self.add_mount(point, priority, Box::new(zip));
+        Ok(())
+    }
+
+    // Picks the mount type from the path: folders, .wad, and .pk3 or .zip
+    pub fn mount(&mut self, point: &str, path: impl AsRef<Path>, priority: i32) -> Result<(), VfsError> {
+        let path = path.as_ref();
+        let extension = path.extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        match extension.as_str() {
+            _ if path.is_dir() => self.mount_dir(point, path, priority),
+            "wad" => self.mount_wad(point, path, priority),
+            "pk3" | "zip" => self.mount_zip(point, path, priority),
+            _ => Err(VfsError::Archive {
+                path: path.to_path_buf(),
+                message: "expected a folder, .wad, .pk3 or .zip".to_string(),
+            }),
+        }
+    }
+
+    // Mounts that can serve the path with the path inside each, highest priority first
+    fn find<'a>(&'a self, path: &'a str) -> impl Iterator<Item = (&'a Mount, &'a str)> {
+        self.mounts.iter().filter_map(move |mount| mount.get_local(path).map(|local| (mount, local)))
+    }
+
+    pub fn read(&self, path: impl AsRef<Path>) -> Result<Vec<u8>, VfsError> {
+        let path = normalize_path(path.as_ref());
+        for (mount, local) in self.find(&path) {
+            if let Some(result) = mount.source.read(local) {
+                return result.map_err(|error| VfsError::Io {
+                    path: path.clone(),
+                    error,
+                });
+            }
+        }
+        Err(VfsError::NotFound { path })
+    }
+
+    pub fn read_to_string(&self, path: impl AsRef<Path>) -> Result<String, VfsError> {
+        let bytes = self.read(path.as_ref())?;
+        String::from_utf8(bytes).map_err(|error| VfsError::Io {
+            path: normalize_path(path.as_ref()),
+            error: io::Error::new(io::ErrorKind::InvalidData, error),
+        })
+    }
+
+    // True for files and for folders with files in any mount
+    pub fn exists(&self, path: impl AsRef<Path>) -> bool {
+        let path = normalize_path(path.as_ref());
+        let mut mounts = self.find(&path);
+        mounts.any(|(mount, local)| mount.source.is_file(local) || mount.source.is_dir(local))
+    }
+
+    pub fn is_dir(&self, path: impl AsRef<Path>) -> bool {
+        let path = normalize_path(path.as_ref());
+        let mut mounts = self.find(&path);
+        mounts.any(|(mount, local)| mount.source.is_dir(local))
+    }
+
+    // Full paths of the files and folders directly in `dir` across all mounts, sorted
+    pub fn list(&self, dir: impl AsRef<Path>) -> Vec<String> {
+        let dir = normalize_path(dir.as_ref());
+        let mut names = BTreeSet::new();
+        for mount in &self.mounts {
+            match mount.get_local(&dir) {
+                Some(local) => names.extend(mount.source.list(local)),
+                // The mount point itself is a folder inside `dir`
+                None => {
+                    if let Some(child) = get_child(&dir, &mount.point) {
+                        names.insert(child.to_string());
+                    }
+                }
+            }
+        }
+        names.into_iter().map(|name| join(&dir, &name)).collect()
+    }
+
+    // Modification time of the file that would be read, None for files in archives
+    pub fn get_modified(&self, path: impl AsRef<Path>) -> Option<SystemTime> {
+        let path = normalize_path(path.as_ref());
+        let (mount, local) = self.find(&path).find(|(mount, local)| mount.source.is_file(local))?;
+        mount.source.get_modified(local)
+    }
+}
+
+// `a/./b/../c` -> `a/c`, absolute and relative paths map to the same namespace
+fn normalize_path(path: &Path) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            Component::ParentDir => {
+                parts.pop();
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    parts.join("/")
+}
+
+fn normalize(path: &str) -> String {
+    normalize_path(Path::new(path))
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+// First path segment of `path` below `dir`, e.g. `b` for `a` and `a/b/c`
+fn get_child<'a>(dir: &str, path: &'a str) -> Option<&'a str> {
+    let rest = if dir.is_empty() {
+        path
+    } else {
+        path.strip_prefix(dir)?.strip_prefix('/')?
+    };
+    rest.split('/').next().filter(|child| !child.is_empty())
+}
+
+// Loose files in a folder on disk
+struct DirMount {
+    root: PathBuf,
+}
+
+impl MountSource for DirMount {
+    fn read(&self, path: &str) -> Option<io::Result<Vec<u8>>> {
+        let path = self.root.join(path);
+        path.is_file().then(|| std::fs::read(path))
+    }
+
+    fn is_file(&self, path: &str) -> bool {
+        self.root.join(path).is_file()
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        self.root.join(path).is_dir()
+    }
+
+    fn list(&self, dir: &str) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(self.root.join(dir)) else {
+            return Vec::new();
+        };
+        entries.filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    fn get_modified(&self, path: &str) -> Option<SystemTime> {
+        std::fs::metadata(self.root.join(path)).and_then(|metadata| metadata.modified()).ok()
+    }
+}
+
+// Archive contents by path, shared by the WAD and zip mounts
+struct ArchiveIndex<T> {
+    files: BTreeMap<String, T>,
+}
+
+impl<T> ArchiveIndex<T> {
+    fn is_dir(&self, path: &str) -> bool {
+        path.is_empty() || self.files.keys().any(|file| get_child(path, file).is_some())
+    }
+
+    fn list(&self, dir: &str) -> Vec<String> {
+        let children: BTreeSet<&str> = self.files.keys().filter_map(|file| get_child(dir, file)).collect();
+        children.into_iter().map(str::to_string).collect()
+    }
+}
+
+// Doom WAD. Lumps between F_START/F_END, S_START/S_END and P_START/P_END are under flats/, sprites/
+// and patches/, map lumps under maps/<map>/, the rest at the top. A later lump with the same name wins.
+struct WadMount {
+    path: PathBuf,
+    // Offset and size of every lump
+    lumps: ArchiveIndex<(u64, usize)>,
+}
+
+const MAP_LUMPS: [&str; 11] = ["THINGS", "LINEDEFS", "SIDEDEFS", "VERTEXES", "SEGS", "SSECTORS", "NODES", "SECTORS", "REJECT", "BLOCKMAP", "BEHAVIOR"];
+
+impl WadMount {
+    fn open(path: &Path) -> Result<Self, VfsError> {
+        let archive_error = |message: String| VfsError::Archive {
+            path: path.to_path_buf(),
+            message,
+        };
+        let mut file = File::open(path).map_err(|error| archive_error(error.to_string()))?;
+
+        let mut header = [0u8; 12];
+        file.read_exact(&mut header).map_err(|error| archive_error(error.to_string()))?;
+        if &header[0..4] != b"IWAD" && &header[0..4] != b"PWAD" {
+            return Err(archive_error("not a WAD file".to_string()));
+        }
+        let lump_count = i32::from_le_bytes(header[4..8].try_into().unwrap()).max(0) as usize;
+        let directory_offset = i32::from_le_bytes(header[8..12].try_into().unwrap()).max(0) as u64;
+
+        let mut directory = vec![0u8; lump_count * 16];
+        file.seek(SeekFrom::Start(directory_offset))
+            .and_then(|_| file.read_exact(&mut directory))
+            .map_err(|error| archive_error(format!("truncated lump directory: {}", error)))?;
+
+        let mut files = BTreeMap::new();
+        let mut namespace: Option<&str> = None;
+        let mut map: Option<String> = None;
+        for entry in directory.chunks_exact(16) {
+            let offset = i32::from_le_bytes(entry[0..4].try_into().unwrap()).max(0) as u64;
+            let size = i32::from_le_bytes(entry[4..8].try_into().unwrap()).max(0) as usize;
+            let name: String = entry[8..16].iter()
+                .take_while(|byte| **byte != 0)
+                .map(|byte| byte.to_ascii_uppercase() as char)
+                .collect();
+
+            // Markers open and close namespaces, FF_START and friends are PWAD additions
+            match name.as_str() {
+                "F_START" | "FF_START" => namespace = Some("flats"),
+                "S_START" | "SS_START" => namespace = Some("sprites"),
+                "P_START" | "PP_START" => namespace = Some("patches"),
+                "F_END" | "FF_END" | "S_END" | "SS_END" | "P_END" | "PP_END" => namespace = None,
+                // Sub markers like P1_START inside a namespace
+                _ if name.ends_with("_START") || name.ends_with("_END") => {}
+                _ => {
+                    if is_map_marker(&name) {
+                        map = Some(name.clone());
+                    } else if !MAP_LUMPS.contains(&name.as_str()) {
+                        map = None;
+                    }
+
+                    let path = match (namespace, &map) {
+                        (Some(namespace), _) => format!("{}/{}", namespace, name),
+                        (None, Some(map)) if *map != name => format!("maps/{}/{}", map, name),
+                        _ => name,
+                    };
+                    files.insert(path, (offset, size));
+                }
+            }
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            lumps: ArchiveIndex { files },
+        })
+    }
+}
+
+// ExMy or MAPxx
+fn is_map_marker(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    let episode_map = bytes.len() == 4 && bytes[0] == b'E' && bytes[1].is_ascii_digit() && bytes[2] == b'M' && bytes[3].is_ascii_digit();
+    let map = bytes.len() == 5 && name.starts_with("MAP") && bytes[3..].iter().all(u8::is_ascii_digit);
+    episode_map || map
+}
+
+impl MountSource for WadMount {
+    fn read(&self, path: &str) -> Option<io::Result<Vec<u8>>> {
+        let (offset, size) = *self.lumps.files.get(path)?;
+        Some(File::open(&self.path).and_then(|mut file| {
+            file.seek(SeekFrom::Start(offset))?;
+            let mut data = vec![0; size];
+            file.read_exact(&mut data)?;
+            Ok(data)
+        }))
+    }
+
+    fn is_file(&self, path: &str) -> bool {
+        self.lumps.files.contains_key(path)
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        self.lumps.is_dir(path)
+    }
+
+    fn list(&self, dir: &str) -> Vec<String> {
+        self.lumps.list(dir)
+    }
+}
+
+// Zip archive, PK3 mods are zips laid out like the asset folder
+struct ZipMount {
+    archive: Mutex<ZipArchive<File>>,
+    // Index of every file in the archive
+    entries: ArchiveIndex<usize>,
+}
+
+impl ZipMount {
+    fn open(path: &Path) -> Result<Self, VfsError> {
+        let archive_error = |message: String| VfsError::Archive {
+            path: path.to_path_buf(),
+            message,
+        };
+        let file = File::open(path).map_err(|error| archive_error(error.to_string()))?;
+        let archive = ZipArchive::new(file).map_err(|error| archive_error(error.to_string()))?;
+
+        let files = (0..archive.len())
+            .filter_map(|index| {
+                let name = archive.name_for_index(index)?;
+                (!name.ends_with('/')).then(|| (normalize(name), index))
+            })
+            .collect();
+
+        Ok(Self {
+            archive: Mutex::new(archive),
+            entries: ArchiveIndex { files },
+        })
+    }
+}
+
+impl MountSource for ZipMount {
+    fn read(&self, path: &str) -> Option<io::Result<Vec<u8>>> {
+        let index = *self.entries.files.get(path)?;
+        let mut archive = self.archive.lock().unwrap();
+        Some(archive.by_index(index).map_err(io::Error::other).and_then(|mut file| {
+            let mut data = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut data)?;
+            Ok(data)
+        }))
+    }
+
+    fn is_file(&self, path: &str) -> bool {
+        self.entries.files.contains_key(path)
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        self.entries.is_dir(path)
+    }
+
+    fn list(&self, dir: &str) -> Vec<String> {
+        self.entries.list(dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+
+    use zip::write::SimpleFileOptions;
+
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("vfs_{}_{}", std::process::id(), name))
+    }
+
+    // Lumps in order, markers have no data
+    fn write_wad(path: &Path, lumps: &[(&str, &[u8])]) {
+        let mut data = Vec::new();
+        let mut directory = Vec::new();
+        for (name, lump) in lumps {
+            directory.extend((12 + data.len() as i32).to_le_bytes());
+            directory.extend((lump.len() as i32).to_le_bytes());
+            let mut padded = [0u8; 8];
+            padded[..name.len()].copy_from_slice(name.as_bytes());
+            directory.extend(padded);
+            data.extend_from_slice(lump);
+        }
+
+        let mut wad = b"PWAD".to_vec();
+        wad.extend((lumps.len() as i32).to_le_bytes());
+        wad.extend((12 + data.len() as i32).to_le_bytes());
+        wad.extend(data);
+        wad.extend(directory);
+        fs::write(path, wad).unwrap();
+    }
+
+    #[test]
+    fn higher_priority_and_later_mounts_win() {
+        let base = temp_path("base");
+        let patch = temp_path("patch");
+        for (dir, text) in [(&base, "base"), (&patch, "patch")] {
+            fs::create_dir_all(dir.join("shaders")).unwrap();
+            fs::write(dir.join("shaders/a.glsl"), text).unwrap();
+            fs::write(dir.join(format!("{}.txt", text)), text).unwrap();
+        }
+
+        let mut vfs = Vfs::new();
+        vfs.mount_dir("", &base, 0).unwrap();
+        vfs.mount_dir("", &patch, 0).unwrap();
+        assert_eq!(vfs.read_to_string("shaders/a.glsl").unwrap(), "patch");
+        assert_eq!(vfs.read_to_string("./shaders/../base.txt").unwrap(), "base");
+        assert_eq!(vfs.list(""), vec!["base.txt", "patch.txt", "shaders"]);
+
+        vfs.mount_dir("", &base, 1).unwrap();
+        assert_eq!(vfs.read_to_string("shaders/a.glsl").unwrap(), "base");
+        assert!(matches!(vfs.read("missing.txt"), Err(VfsError::NotFound { .. })));
+
+        fs::remove_dir_all(base).unwrap();
+        fs::remove_dir_all(patch).unwrap();
+    }
+
+    #[test]
+    fn mounts_wad_namespaces() {
+        let path = temp_path("test.wad");
+        write_wad(&path, &[
+            ("PLAYPAL", b"palette"),
+            ("MAP01", b""),
+            ("THINGS", b"things"),
+            ("F_START", b""),
+            ("FLOOR0_1", b"flat"),
+            ("F_END", b""),
+        ]);
+
+        let mut vfs = Vfs::new();
+        vfs.mount("doom", &path, 0).unwrap();
+        assert_eq!(vfs.read("doom/PLAYPAL").unwrap(), b"palette");
+        assert_eq!(vfs.read("doom/maps/MAP01/THINGS").unwrap(), b"things");
+        assert_eq!(vfs.read("doom/flats/FLOOR0_1").unwrap(), b"flat");
+        assert_eq!(vfs.list("doom"), vec!["doom/MAP01", "doom/PLAYPAL", "doom/flats", "doom/maps"]);
+        assert_eq!(vfs.list(""), vec!["doom"]);
+        assert!(vfs.is_dir("doom/maps/MAP01"));
+        assert!(vfs.get_modified("doom/PLAYPAL").is_none());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn mounts_pk3() {
+        let path = temp_path("test.pk3");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        zip.start_file("textures/wall.png", SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"png").unwrap();
+        zip.finish().unwrap();
+
+        let mut vfs = Vfs::new();
+        vfs.mount("", &path, 0).unwrap();
+        assert_eq!(vfs.read("textures/wall.png").unwrap(), b"png");
+        assert!(vfs.exists("textures"));
+        assert!(!vfs.exists("textures/floor.png"));
+        assert_eq!(vfs.list("textures"), vec!["textures/wall.png"]);
+
+        fs::remove_file(path).unwrap();
+    }
+}
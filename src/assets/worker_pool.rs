@@ -0,0 +1,123 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Job<T> = Box<dyn FnOnce() -> T + Send>;
+
+// Runs jobs on background threads and hands their results back in the order they finish.
+// A job that panics comes back as Err with the panic message.
+pub struct WorkerPool<T> {
+    jobs: Option<Sender<Job<T>>>,
+    results: Receiver<Result<T, String>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> WorkerPool<T> {
+    pub fn new(worker_count: usize) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<Job<T>>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let workers = (0..worker_count.max(1))
+            .map(|i| {
+                let job_receiver = job_receiver.clone();
+                let result_sender = result_sender.clone();
+                thread::Builder::new()
+                    .name(format!("asset worker {}", i))
+                    .spawn(move || loop {
+                        // Not locked while the job runs
+                        let job = job_receiver.lock().unwrap().recv();
+                        let Ok(job) = job else {
+                            // Pool dropped
+                            break;
+                        };
+                        let result = panic::catch_unwind(AssertUnwindSafe(job)).map_err(get_panic_message);
+                        if result_sender.send(result).is_err() {
+                            break;
+                        }
+                    })
+                    .expect("Failed to spawn asset worker")
+            })
+            .collect();
+
+        WorkerPool {
+            jobs: Some(jobs),
+            results,
+            workers,
+        }
+    }
+
+    // One worker per core, leaving one for the GL thread
+    pub fn with_available_parallelism() -> Self {
+        let cores = thread::available_parallelism().map(|cores| cores.get()).unwrap_or(2);
+        WorkerPool::new(cores.saturating_sub(1))
+    }
+
+    pub fn spawn(&self, job: impl FnOnce() -> T + Send + 'static) {
+        self.jobs.as_ref()
+            .expect("Worker pool is shut down")
+            .send(Box::new(job))
+            .expect("Asset workers stopped");
+    }
+
+    // Next finished result, None when nothing is finished yet
+    pub fn try_recv(&self) -> Option<Result<T, String>> {
+        match self.results.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => panic!("Asset workers stopped"),
+        }
+    }
+
+    // Waits for the next result, only call it with jobs in flight
+    pub fn recv(&self) -> Result<T, String> {
+        self.results.recv().expect("Asset workers stopped")
+    }
+}
+
+impl<T> Drop for WorkerPool<T> {
+    // Workers finish their current job and stop
+    fn drop(&mut self) {
+        self.jobs.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn get_panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    payload.downcast_ref::<String>()
+        .cloned()
+        .or_else(|| payload.downcast_ref::<&str>().map(|message| message.to_string()))
+        .unwrap_or_else(|| "Asset worker panicked".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_jobs_on_workers() {
+        let pool = WorkerPool::new(3);
+        for i in 0..10 {
+            pool.spawn(move || i * 2);
+        }
+
+        let mut results: Vec<i32> = (0..10).map(|_| pool.recv().unwrap()).collect();
+        results.sort();
+        assert_eq!(results, (0..10).map(|i| i * 2).collect::<Vec<_>>());
+        assert!(pool.try_recv().is_none());
+    }
+
+    #[test]
+    fn returns_panics_as_errors() {
+        let pool = WorkerPool::new(1);
+        pool.spawn(|| panic!("broken.png is not an image"));
+        pool.spawn(|| 1);
+
+        assert_eq!(pool.recv(), Err("broken.png is not an image".to_string()));
+        // The worker survives the panic
+        assert_eq!(pool.recv(), Ok(1));
+    }
+}
@@ -1,4 +1,5 @@
 pub mod scene;
 pub mod camera;
 pub mod key_listener;
-pub mod mouse_listener;
\ No newline at end of file
+pub mod mouse_listener;
+pub mod weapon;
\ No newline at end of file
@@ -29,6 +29,11 @@ impl Camera {
         Mat4::look_to_rh(self.position, self.front, Vec3::new(0.0, 1.0, 0.0))
     }
 
+    // Distance moved along an axis per update
+    pub fn get_movement_speed(&self) -> f32 {
+        self.movement_speed
+    }
+
     pub fn move_main_axis(&mut self, forward: bool) {
         let forward_ground = Vec3::new(self.front.x, 0.0, self.front.z).normalize();
         if forward {
@@ -1,49 +1,95 @@
 use glam::{Vec2, Vec3, Vec4};
 
+use crate::assets::manifest::Manifest;
 use crate::game::camera::Camera;
 use crate::game::key_listener::KeyListener;
 use crate::game::mouse_listener::MouseListener;
-use crate::graphics;
+use crate::game::weapon::{SHOTGUN, Weapon, WeaponInput};
+use crate::graphics::light::Light;
+use crate::graphics::render_backend::RenderBackend;
+use crate::graphics::sector_wall::SectorWall;
+use crate::graphics::sky::SKY_FLAT;
+use crate::registry::model_registry::ModelId;
 
 pub trait Scene {
     // 60 fps
     fn update_fixed(&mut self, camera: &mut Camera, key_listener: &KeyListener, mouse_listener: &mut MouseListener);
 
-    fn draw(&mut self, camera: &Camera, renderer: &mut graphics::render::Renderer);
+    fn draw(&mut self, camera: &Camera, renderer: &mut dyn RenderBackend);
+
+    // Builds level geometry once the level's assets are loaded, again after a restart
+    fn load_level(&mut self, _renderer: &mut dyn RenderBackend) {}
+
+    // Lights used by lit draws this frame
+    fn lights(&self) -> &[Light] {
+        &[]
+    }
 }
 
 
 pub struct MainScene {
     rotation: f32,
+    lights: Vec<Light>,
+    // Street props and where they stand
+    props: Vec<(ModelId, Vec3)>,
+    // Sector lit side walls, fake contrast makes them differ from walls along x
+    sector_walls: Vec<SectorWall>,
+    weapon: Weapon,
 }
 
 impl MainScene {
-    pub fn new() -> MainScene {
-        MainScene {
+    pub fn new(manifest: &Manifest) -> MainScene {
+        let mut scene = MainScene {
             rotation: 0.0,
-        }
+            lights: Vec::new(),
+            props: vec![
+                (manifest.get_model_id("street_lamp"), Vec3::new(12.0, -5.0, -14.0)),
+                (manifest.get_model_id("bench"), Vec3::new(-8.0, -5.0, -15.0)),
+                (manifest.get_model_id("bush"), Vec3::new(-20.0, -5.0, -16.0)),
+                (manifest.get_model_id("garbage_bin"), Vec3::new(4.0, -5.0, -16.0)),
+                (manifest.get_model_id("busstop"), Vec3::new(30.0, -5.0, -15.0)),
+            ],
+            sector_walls: vec![
+                SectorWall::new(Vec3::new(-50.0, 0.0, 30.0), Vec2::new(100.0, 10.0), 90.0, 160),
+                SectorWall::new(Vec3::new(50.0, 0.0, 30.0), Vec2::new(100.0, 10.0), 90.0, 96),
+            ],
+            weapon: Weapon::new(&SHOTGUN, manifest.get_texture_id(SHOTGUN.texture)),
+        };
+
+        // Dim moonlight, a street lamp and a spot on the bench
+        scene.add_light(Light::directional(Vec3::new(-0.3, -1.0, -0.5), Vec3::new(0.3, 0.3, 0.4)));
+        scene.add_light(Light::point(Vec3::new(12.0, 4.0, -12.0), Vec3::new(1.0, 0.85, 0.6), 40.0));
+        scene.add_light(Light::spot(Vec3::new(-8.0, 10.0, -12.0),
+                                    Vec3::new(0.0, -1.0, -0.2),
+                                    Vec3::new(0.6, 0.7, 1.0),
+                                    30.0,
+                                    15.0,
+                                    25.0));
+
+        scene
     }
 
-    fn draw_crosshair(&self, renderer: &mut graphics::render::Renderer) {
-        // // Vertical
-        renderer.draw_line(Vec2::new(renderer.window_size.x / 2.0, renderer.window_size.y / 2.0 - 10.0),
-                           Vec2::new(renderer.window_size.x / 2.0, renderer.window_size.y / 2.0 + 10.0),
+    pub fn add_light(&mut self, light: Light) {
+        self.lights.push(light);
+    }
+
+    pub fn draw_crosshair(&self, renderer: &mut dyn RenderBackend) {
+        let internal_size = renderer.get_internal_size();
+        // Vertical
+        renderer.draw_line(Vec2::new(internal_size.x / 2.0, internal_size.y / 2.0 - 10.0),
+                           Vec2::new(internal_size.x / 2.0, internal_size.y / 2.0 + 10.0),
                            2.0,
                            Vec4::new(1.0, 0.0, 0.0, 1.0));
-        // // Horizontal
-        renderer.draw_line(Vec2::new(renderer.window_size.x / 2.0 - 10.0, renderer.window_size.y / 2.0),
-                           Vec2::new(renderer.window_size.x / 2.0 + 10.0, renderer.window_size.y / 2.0),
+        // Horizontal
+        renderer.draw_line(Vec2::new(internal_size.x / 2.0 - 10.0, internal_size.y / 2.0),
+                           Vec2::new(internal_size.x / 2.0 + 10.0, internal_size.y / 2.0),
                            2.0,
                            Vec4::new(1.0, 0.0, 0.0, 1.0));
     }
 
-    fn draw_ui(&self, renderer: &mut graphics::render::Renderer) {
-        renderer.draw_rect_textured(
-            Vec2::new(0., 0.0),
-            Vec2::new(renderer.window_size.x, renderer.window_size.y),
-            0.0,
-            Vec4::new(0.1, 0.1, 0.1, 0.2),
-        );
+    fn draw_ui(&self, renderer: &mut dyn RenderBackend) {
+        self.weapon.draw(renderer);
+        self.draw_crosshair(renderer);
 
         // renderer.draw_polygon(&ShapeBuilder::get_hexagon_vertices(),
         //                       Vec3::new(200.0, 200.0, 0.0),
@@ -92,6 +138,7 @@ impl Scene for MainScene {
         // self.rotation += 3.0;
 
         // Handle movement
+        let start_position = camera.position;
         if key_listener.is_key_pressed(glfw::Key::W) {
             camera.move_main_axis(true);
         } else if key_listener.is_key_pressed(glfw::Key::S) {
@@ -104,6 +151,14 @@ impl Scene for MainScene {
             camera.move_cross_axis(false);
         }
 
+        // Fire with ctrl like Doom, the shotgun pumps after every shot
+        let moved = (camera.position - start_position).length();
+        self.weapon.update(1.0 / 60.0, WeaponInput {
+            fire: key_listener.is_key_pressed(glfw::Key::LeftControl),
+            reload: key_listener.is_key_pressed(glfw::Key::R),
+            movement: moved / camera.get_movement_speed(),
+        });
+
         // Handle mouse movement
         let mouse_delta = mouse_listener.get_mouse_delta();
         if mouse_delta.x != 0.0 {
@@ -118,13 +173,43 @@ impl Scene for MainScene {
         mouse_listener.end_frame();
     }
 
-    fn draw(&mut self, camera: &Camera, renderer: &mut graphics::render::Renderer) {
+    fn load_level(&mut self, renderer: &mut dyn RenderBackend) {
+        renderer.load_sector_walls(&self.sector_walls);
+    }
+
+    fn draw(&mut self, camera: &Camera, renderer: &mut dyn RenderBackend) {
+        renderer.draw_sky(camera.get_view_matrix());
+
         renderer.draw_wall(camera.get_view_matrix(),
                            Vec3::new(0.0, 0.0, -20.0),
                            Vec2::new(100.0, 10.0),
                            self.rotation, Vec4::new(1.0, 1.0, 1.0, 1.0));
 
+        renderer.draw_sector_walls(camera.get_view_matrix());
+
+        // Open to the sky over the street, a lower lit ceiling past it
+        renderer.draw_sector_ceiling(camera.get_view_matrix(),
+                                     Vec3::new(0.0, 5.0, 0.0),
+                                     Vec2::new(100.0, 40.0),
+                                     SKY_FLAT,
+                                     255);
+        renderer.draw_sector_ceiling(camera.get_view_matrix(),
+                                     Vec3::new(0.0, 5.0, 50.0),
+                                     Vec2::new(100.0, 60.0),
+                                     "brick_wall",
+                                     128);
+
+        // Street props
+        let view = camera.get_view_matrix();
+        for (model_id, position) in &self.props {
+            renderer.draw_model(view, *model_id, *position, 0.0, 5.0);
+        }
+
         // Draw ui
         self.draw_ui(renderer);
     }
+
+    fn lights(&self) -> &[Light] {
+        &self.lights
+    }
 }
\ No newline at end of file
@@ -0,0 +1,312 @@
+use std::f32::consts::TAU;
+
+use glam::{Vec2, Vec4};
+
+use crate::graphics::render_backend::RenderBackend;
+use crate::graphics::shape_builder::ShapeBuilder;
+use crate::registry::texture_registry::TextureId;
+
+// Weapon frames advance in Doom tics
+pub const TICS_PER_SECOND: f32 = 35.0;
+// Weapon sprites are laid out for a 320x200 screen, scaled to the height of the HUD
+const HUD_WIDTH: f32 = 320.0;
+const HUD_HEIGHT: f32 = 200.0;
+// Where the sprite offset lands on the HUD at rest, from the top left like Doom's psprites
+const WEAPON_POSITION: Vec2 = Vec2::new(1.0, 32.0);
+
+// Bob in HUD pixels when moving at full speed
+const MAX_BOB: f32 = 8.0;
+// Share of the distance to the target bob covered each tic
+const BOB_SMOOTHING: f32 = 0.2;
+// One bob cycle every 64 tics, like Doom
+const BOB_ANGLE_PER_TIC: f32 = TAU / 64.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WeaponState {
+    // Bobs and waits for fire or reload
+    Ready,
+    Fire,
+    Reload,
+}
+
+// Placed by the sprite offset of its layer
+#[derive(Debug, Clone, Copy)]
+pub struct WeaponFrame {
+    // Layer of the weapon's array texture
+    pub layer: u32,
+    pub tics: u32,
+}
+
+// Muzzle flash drawn over the weapon
+#[derive(Debug, Clone, Copy)]
+pub struct FlashFrame {
+    pub tics: u32,
+    // Diameter in HUD pixels
+    pub size: f32,
+    pub color: Vec4,
+}
+
+// Frame table of a weapon, every state plays its frames in order and then moves on
+pub struct WeaponInfo {
+    // Array texture key in the manifest
+    pub texture: &'static str,
+    pub ready: &'static [WeaponFrame],
+    // Followed by reload
+    pub fire: &'static [WeaponFrame],
+    pub reload: &'static [WeaponFrame],
+    // Started when firing
+    pub flash: &'static [FlashFrame],
+    // Flash position in HUD pixels from the top left, moves with the bob
+    pub muzzle: Vec2,
+}
+
+const fn frame(layer: u32, tics: u32) -> WeaponFrame {
+    WeaponFrame {
+        layer,
+        tics,
+    }
+}
+
+// Layers are SHTFC0 to SHTFG0
+pub const SHOTGUN: WeaponInfo = WeaponInfo {
+    texture: "shotgun_frames",
+    ready: &[frame(0, 1)],
+    fire: &[
+        frame(0, 3),
+        frame(1, 4),
+        frame(2, 5),
+    ],
+    // Pump
+    reload: &[
+        frame(3, 5),
+        frame(4, 5),
+        frame(3, 5),
+        frame(0, 3),
+    ],
+    flash: &[
+        FlashFrame { tics: 4, size: 28.0, color: Vec4::new(1.0, 0.9, 0.5, 0.9) },
+        FlashFrame { tics: 3, size: 18.0, color: Vec4::new(1.0, 0.6, 0.2, 0.7) },
+    ],
+    muzzle: Vec2::new(160.0, 136.0),
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WeaponInput {
+    pub fire: bool,
+    pub reload: bool,
+    // Player speed from 0 to 1, drives the bob
+    pub movement: f32,
+}
+
+// First person weapon drawn on the HUD
+pub struct Weapon {
+    info: &'static WeaponInfo,
+    texture_id: TextureId,
+    state: WeaponState,
+    frame: usize,
+    tics_left: u32,
+    // Index into the flash frames while the flash shows
+    flash_frame: Option<usize>,
+    flash_tics_left: u32,
+    // Seconds not yet run as tics
+    time_s: f32,
+
+    bob: f32,
+    bob_angle: f32,
+    // Kept while firing or reloading
+    bob_offset: Vec2,
+}
+
+impl Weapon {
+    pub fn new(info: &'static WeaponInfo, texture_id: TextureId) -> Weapon {
+        Weapon {
+            info,
+            texture_id,
+            state: WeaponState::Ready,
+            frame: 0,
+            tics_left: info.ready[0].tics,
+            flash_frame: None,
+            flash_tics_left: 0,
+            time_s: 0.0,
+            bob: 0.0,
+            bob_angle: 0.0,
+            bob_offset: Vec2::ZERO,
+        }
+    }
+
+    // Runs the tics that fit in the elapsed time
+    pub fn update(&mut self, delta_s: f32, input: WeaponInput) {
+        self.time_s += delta_s;
+        let tic_s = 1.0 / TICS_PER_SECOND;
+        while self.time_s >= tic_s {
+            self.time_s -= tic_s;
+            self.tic(input);
+        }
+    }
+
+    pub fn tic(&mut self, input: WeaponInput) {
+        self.tic_flash();
+
+        self.tics_left = self.tics_left.saturating_sub(1);
+        if self.tics_left == 0 {
+            self.next_frame();
+        }
+
+        self.bob += (input.movement.clamp(0.0, 1.0) * MAX_BOB - self.bob) * BOB_SMOOTHING;
+        if self.state != WeaponState::Ready {
+            return;
+        }
+
+        self.bob_angle = (self.bob_angle + BOB_ANGLE_PER_TIC) % TAU;
+        // Sways sideways and dips twice per cycle
+        self.bob_offset = Vec2::new(self.bob * self.bob_angle.cos(), -self.bob * self.bob_angle.sin().abs());
+
+        if input.fire {
+            self.set_state(WeaponState::Fire);
+            self.flash_frame = Some(0);
+            self.flash_tics_left = self.info.flash[0].tics;
+        } else if input.reload {
+            self.set_state(WeaponState::Reload);
+        }
+    }
+
+    fn tic_flash(&mut self) {
+        let Some(flash_frame) = self.flash_frame else {
+            return;
+        };
+
+        self.flash_tics_left = self.flash_tics_left.saturating_sub(1);
+        if self.flash_tics_left == 0 {
+            let next = flash_frame + 1;
+            self.flash_frame = self.info.flash.get(next).map(|frame| {
+                self.flash_tics_left = frame.tics;
+                next
+            });
+        }
+    }
+
+    fn next_frame(&mut self) {
+        if self.frame + 1 < self.get_frames(self.state).len() {
+            self.frame += 1;
+            self.tics_left = self.get_frame().tics;
+            return;
+        }
+
+        let next_state = match self.state {
+            WeaponState::Ready => WeaponState::Ready,
+            WeaponState::Fire => WeaponState::Reload,
+            WeaponState::Reload => WeaponState::Ready,
+        };
+        self.set_state(next_state);
+    }
+
+    fn set_state(&mut self, state: WeaponState) {
+        self.state = state;
+        self.frame = 0;
+        self.tics_left = self.get_frame().tics;
+    }
+
+    fn get_frames(&self, state: WeaponState) -> &'static [WeaponFrame] {
+        match state {
+            WeaponState::Ready => self.info.ready,
+            WeaponState::Fire => self.info.fire,
+            WeaponState::Reload => self.info.reload,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn get_state(&self) -> WeaponState {
+        self.state
+    }
+
+    pub fn get_frame(&self) -> &WeaponFrame {
+        &self.get_frames(self.state)[self.frame]
+    }
+
+    pub fn get_flash_frame(&self) -> Option<&FlashFrame> {
+        self.flash_frame.map(|frame| &self.info.flash[frame])
+    }
+
+    #[cfg(test)]
+    pub fn get_bob_offset(&self) -> Vec2 {
+        self.bob_offset
+    }
+
+    // On a 320x200 HUD centered at the bottom of the screen
+    pub fn draw(&self, renderer: &mut dyn RenderBackend) {
+        let internal_size = renderer.get_internal_size();
+        let scale = internal_size.y / HUD_HEIGHT;
+        let hud_left = (internal_size.x - HUD_WIDTH * scale) / 2.0;
+        // HUD positions are top down, the renderer's y is up
+        let to_screen = |position: Vec2| Vec2::new(hud_left + position.x * scale, (HUD_HEIGHT - position.y) * scale);
+        let bob = Vec2::new(self.bob_offset.x, -self.bob_offset.y);
+
+        let frame = self.get_frame();
+        renderer.draw_sprite_layer(self.texture_id, frame.layer, to_screen(WEAPON_POSITION + bob), scale);
+
+        if let Some(flash) = self.get_flash_frame() {
+            renderer.draw_polygon(&ShapeBuilder::get_high_res_cirlce_vertices(),
+                                  to_screen(self.info.muzzle + bob).extend(0.0),
+                                  0.0,
+                                  flash.size * scale,
+                                  flash.color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tics(weapon: &mut Weapon, count: u32, input: WeaponInput) {
+        for _ in 0..count {
+            weapon.tic(input);
+        }
+    }
+
+    fn get_total_tics(frames: &[WeaponFrame]) -> u32 {
+        frames.iter().map(|frame| frame.tics).sum()
+    }
+
+    #[test]
+    fn fires_then_reloads_then_is_ready() {
+        let mut weapon = Weapon::new(&SHOTGUN, TextureId(0));
+        let fire = WeaponInput { fire: true, ..Default::default() };
+
+        weapon.tic(fire);
+        assert_eq!(weapon.get_state(), WeaponState::Fire);
+        assert!(weapon.get_flash_frame().is_some());
+
+        // Holding fire does nothing until the weapon is ready again
+        tics(&mut weapon, get_total_tics(SHOTGUN.fire), fire);
+        assert_eq!(weapon.get_state(), WeaponState::Reload);
+        assert!(weapon.get_flash_frame().is_none());
+
+        tics(&mut weapon, get_total_tics(SHOTGUN.reload), WeaponInput::default());
+        assert_eq!(weapon.get_state(), WeaponState::Ready);
+        assert_eq!(weapon.get_frame().layer, SHOTGUN.ready[0].layer);
+    }
+
+    #[test]
+    fn runs_tics_at_tic_rate() {
+        let mut weapon = Weapon::new(&SHOTGUN, TextureId(0));
+        weapon.update(0.6 / TICS_PER_SECOND, WeaponInput { reload: true, ..Default::default() });
+        assert_eq!(weapon.get_state(), WeaponState::Ready);
+
+        weapon.update(0.6 / TICS_PER_SECOND, WeaponInput { reload: true, ..Default::default() });
+        assert_eq!(weapon.get_state(), WeaponState::Reload);
+    }
+
+    #[test]
+    fn bobs_only_while_moving() {
+        let mut weapon = Weapon::new(&SHOTGUN, TextureId(0));
+        tics(&mut weapon, 16, WeaponInput::default());
+        assert_eq!(weapon.get_bob_offset(), Vec2::ZERO);
+
+        tics(&mut weapon, 16, WeaponInput { movement: 1.0, ..Default::default() });
+        let offset = weapon.get_bob_offset();
+        assert!(offset.length() > 1.0 && offset.length() <= MAX_BOB);
+        // The weapon only dips
+        assert!(offset.y <= 0.0);
+    }
+}
@@ -1,66 +1,208 @@
-use glfw::{Context, GlfwReceiver, Key, PWindow, WindowEvent, WindowHint};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use glam::{Vec2, Vec4};
+use glfw::{Context, ContextCreationApi, Glfw, GlfwReceiver, Key, PWindow, WindowEvent, WindowHint};
 
 use crate::game::camera::Camera;
 use crate::game::key_listener::KeyListener;
 use crate::game::mouse_listener::MouseListener;
 use crate::game::scene::{MainScene, Scene};
+use crate::graphics::bitmap_font;
+use crate::graphics::display::{DisplaySettings, InternalResolution};
+use crate::graphics::gl_resource;
+use crate::graphics::post_process::PostEffectKind;
 use crate::graphics::render::Renderer;
+use crate::graphics::render_backend::{Backend, RenderBackend};
+use crate::graphics::software_renderer::SoftwareRenderer;
+use crate::assets::vfs::Vfs;
+use crate::registry::Assets;
+use crate::registry::asset_store::AssetScope;
+use crate::registry::shader_registry::ShaderProgramId;
 
 const SCREEN_WIDTH: u32 = 1920;
 const SCREEN_HEIGHT: u32 = 1080;
+// GL upload time per frame for assets decoded in the background
+const LOAD_BUDGET: Duration = Duration::from_millis(4);
+// Unloads the level scope and loads it again
+const RESTART_LEVEL_KEY: Key = Key::F2;
+// Shader error log lines shown over the scene
+const MAX_ERROR_LINES: usize = 16;
+
+pub struct WindowSettings {
+    pub width: u32,
+    pub height: u32,
+    pub title: String,
+    // Invisible window, falls back to an OSMesa software context when there is no display
+    pub headless: bool,
+    pub screenshot: ScreenshotSettings,
+    // Internal resolution to start with, F7 cycles it
+    pub resolution: InternalResolution,
+    pub backend: Backend,
+    // Folders, WADs and PK3s mounted over the asset root, later ones win
+    pub files: Vec<PathBuf>,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            width: SCREEN_WIDTH,
+            height: SCREEN_HEIGHT,
+            title: "Hello, Triangle".to_string(),
+            headless: false,
+            screenshot: ScreenshotSettings::default(),
+            resolution: InternalResolution::Native,
+            backend: Backend::Gl,
+            files: Vec::new(),
+        }
+    }
+}
+
+pub struct ScreenshotSettings {
+    pub key: Key,
+    pub directory: PathBuf,
+    // Save the image before it is scaled to the window, e.g. 320x200
+    pub internal_resolution: bool,
+}
+
+impl Default for ScreenshotSettings {
+    fn default() -> Self {
+        ScreenshotSettings {
+            key: Key::F12,
+            directory: PathBuf::from("screenshots"),
+            internal_resolution: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WindowError {
+    Init(glfw::InitError),
+    // No window or OpenGL 3.3 context could be created
+    CreateWindow,
+}
+
+impl fmt::Display for WindowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WindowError::Init(error) => write!(f, "Failed to initialize GLFW: {}", error),
+            WindowError::CreateWindow => write!(f, "Failed to create window with an OpenGL 3.3 context"),
+        }
+    }
+}
+
+impl std::error::Error for WindowError {}
+
+pub fn init_glfw(settings: &WindowSettings) -> Result<Glfw, WindowError> {
+    // Headless callers want an error back instead of a panic when there is no display
+    let glfw = if settings.headless {
+        glfw::init(glfw::log_errors)
+    } else {
+        glfw::init(glfw::fail_on_errors)
+    };
+    glfw.map_err(WindowError::Init)
+}
+
+// Creates the window, makes its context current and loads the GL functions
+pub fn create_window(glfw: &mut Glfw, settings: &WindowSettings) -> Result<(PWindow, GlfwReceiver<(f64, WindowEvent)>), WindowError> {
+    glfw.window_hint(WindowHint::ContextVersion(3, 3));
+    glfw.window_hint(WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
+    glfw.window_hint(WindowHint::OpenGlForwardCompat(true));
+    glfw.window_hint(WindowHint::Resizable(!settings.headless));
+    glfw.window_hint(WindowHint::Visible(!settings.headless));
+    glfw.window_hint(WindowHint::Focused(!settings.headless));
+
+    let mut result = glfw.create_window(settings.width, settings.height, &settings.title, glfw::WindowMode::Windowed);
+    if result.is_none() && settings.headless {
+        // No display or GPU, try software rendering. OSMesa has no forward compatible contexts.
+        glfw.window_hint(WindowHint::ContextCreationApi(ContextCreationApi::OsMesa));
+        glfw.window_hint(WindowHint::OpenGlForwardCompat(false));
+        result = glfw.create_window(settings.width, settings.height, &settings.title, glfw::WindowMode::Windowed);
+        glfw.window_hint(WindowHint::ContextCreationApi(ContextCreationApi::Native));
+        glfw.window_hint(WindowHint::OpenGlForwardCompat(true));
+    }
+
+    let (mut window, events) = result.ok_or(WindowError::CreateWindow)?;
+    window.make_current();
+    // Load OpenGL functions dynamically
+    gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
+    Ok((window, events))
+}
 
 pub struct GameWindow {
+    // Dropped first, GL resources need the context of the window
+    renderer: Renderer,
+    // Draws the scene with the software backend, the GL renderer then shows its frames
+    software: Option<SoftwareRenderer>,
+    assets: Rc<Assets>,
+
     glfw: glfw::Glfw,
     window: PWindow,
     events: GlfwReceiver<(f64, WindowEvent)>,
 
     // Game info
-    renderer: Renderer,
     scene: Box<dyn Scene>,
+    // Level geometry is built once the streamed level assets are in
+    level_built: bool,
     camera: Camera,
     key_listener: KeyListener,
     mouse_listener: MouseListener,
+    screenshot: ScreenshotSettings,
 
+    // Debug builds reload shaders from disk, failed programs are listed here until fixed
+    title: String,
+    shader_errors: HashMap<ShaderProgramId, String>,
 }
 
 impl GameWindow {
-    pub fn new() -> GameWindow {
-        let mut glfw = glfw::init(glfw::fail_on_errors).unwrap();
-
-        glfw.window_hint(WindowHint::ContextVersion(3, 3));
-        glfw.window_hint(WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
-        glfw.window_hint(WindowHint::OpenGlForwardCompat(true));
-        glfw.window_hint(WindowHint::Resizable(true));
-        glfw.window_hint(WindowHint::Visible(true));
-        glfw.window_hint(WindowHint::Focused(true));
+    pub fn new(settings: WindowSettings) -> Result<GameWindow, WindowError> {
+        let mut glfw = init_glfw(&settings)?;
 
         // Create actual window
-        let (mut window, events) = glfw.create_window(
-            SCREEN_WIDTH,
-            SCREEN_HEIGHT,
-            "Hello, Triangle",
-            glfw::WindowMode::Windowed)
-            .unwrap();
+        let (mut window, events) = create_window(&mut glfw, &settings)?;
 
         // Set up polling
-        window.make_current();
         window.set_key_polling(true);
         window.set_cursor_pos_polling(true);
+        window.set_framebuffer_size_polling(true);
         window.set_cursor_mode(glfw::CursorMode::Disabled);
         // glfw.set_swap_interval(glfw::SwapInterval::Sync(1));
 
-        let mut renderer = Renderer::new(&mut window);
-        let scene = Box::new(MainScene::new());
-        GameWindow {
-            glfw: glfw,
-            window: window,
-            events: events,
-            renderer: renderer,
-            scene: scene,
+        // Global assets are loaded here, once the context exists, the level streams in behind a loading bar
+        let assets = Rc::new(Assets::load(Vfs::with_files(&settings.files).unwrap_or_else(|e| panic!("{}", e))));
+        assets.queue_scope(AssetScope::Level);
+
+        let mut renderer = Renderer::new(&window, assets.clone());
+        renderer.set_offscreen(settings.headless);
+        renderer.set_display_settings(DisplaySettings::with_resolution(settings.resolution));
+        let software = match settings.backend {
+            Backend::Gl => None,
+            Backend::Software => {
+                let size = renderer.internal_size.as_uvec2();
+                Some(SoftwareRenderer::new(size.x, size.y, assets.vfs.clone()))
+            }
+        };
+        let scene = Box::new(MainScene::new(&assets.manifest));
+        Ok(GameWindow {
+            glfw,
+            window,
+            events,
+            renderer,
+            software,
+            assets,
+            scene,
+            level_built: false,
             camera: Camera::new(),
             key_listener: KeyListener::new(),
             mouse_listener: MouseListener::new(),
-        }
+            screenshot: settings.screenshot,
+            title: settings.title,
+            shader_errors: HashMap::new(),
+        })
     }
 
     pub fn run_loop(&mut self) {
@@ -70,40 +212,208 @@ impl GameWindow {
 
         while !self.window.should_close() {
             let now = self.glfw.get_time();
-            let mut delta_time_s = now - last_time;
+            let delta_time_s = now - last_time;
             elapsed_frames += (delta_time_s / frame_time).floor();
             last_time = now;
 
             // Polling
+            let mut capture_requested = false;
+            let mut restart_requested = false;
             self.glfw.poll_events();
             for (_, event) in glfw::flush_messages(&self.events) {
+                if let WindowEvent::Key(key, _, glfw::Action::Press, _) = event {
+                    if key == self.screenshot.key {
+                        capture_requested = true;
+                        continue;
+                    }
+                    if key == RESTART_LEVEL_KEY {
+                        restart_requested = true;
+                        continue;
+                    }
+                }
+
                 GameWindow::handle_window_event(&mut self.window,
                                                 event,
+                                                &mut self.renderer,
                                                 &mut self.key_listener,
                                                 &mut self.mouse_listener
                 );
             }
 
+            if restart_requested {
+                self.restart_level();
+            }
+
             // Perform update logic, ideally at 60 fps
             while elapsed_frames >= 1.0 {
                 self.scene.update_fixed(&mut self.camera, &self.key_listener, &mut self.mouse_listener);
                 elapsed_frames -= 1.0;
             }
 
-            // Clear screen
-            unsafe {
-                gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+            if cfg!(debug_assertions) {
+                self.reload_shaders();
             }
+            let load_progress = self.assets.update_loading(LOAD_BUDGET);
 
-            self.scene.draw(&self.camera, &mut self.renderer);
+            // Draw offscreen, then post process to the screen
+            self.renderer.begin_frame();
+            // The scene draws level assets, only the loading bar shows until they are in
+            if !self.assets.is_loading() {
+                if !self.level_built {
+                    self.build_level();
+                }
+                self.draw_scene();
+            }
+            if !self.shader_errors.is_empty() {
+                self.draw_shader_errors();
+            }
+            if !load_progress.is_done() {
+                self.draw_loading_bar(load_progress.get_fraction());
+            }
+            self.renderer.end_frame();
+
+            // Back buffer still holds the frame
+            if capture_requested {
+                self.save_screenshot();
+            }
 
             // Swap buffers
             self.window.swap_buffers();
         }
     }
 
+    fn build_level(&mut self) {
+        match self.software.as_mut() {
+            Some(software) => {
+                // Baked into the walls
+                software.fake_contrast = self.renderer.fake_contrast;
+                self.scene.load_level(software);
+            }
+            None => self.scene.load_level(&mut self.renderer),
+        }
+        self.level_built = true;
+    }
+
+    fn draw_scene(&mut self) {
+        let Some(software) = self.software.as_mut() else {
+            self.renderer.set_lights(self.scene.lights());
+            self.scene.draw(&self.camera, &mut self.renderer);
+            return;
+        };
+
+        // Same settings and resolution as the GL renderer, so its toggles apply to both
+        let size = self.renderer.internal_size.as_uvec2();
+        software.resize(size.x, size.y);
+        software.set_display_aspect(self.renderer.get_display_aspect());
+        software.ambient_light = self.renderer.ambient_light;
+        software.blinn_phong = self.renderer.blinn_phong;
+        software.sky_mode = self.renderer.sky_mode;
+        software.light_mode = self.renderer.light_mode;
+        software.fake_contrast = self.renderer.fake_contrast;
+        software.light_distance_scale = self.renderer.light_distance_scale;
+
+        software.begin_frame();
+        software.set_lights(self.scene.lights());
+        self.scene.draw(&self.camera, software);
+        software.end_frame();
+        self.renderer.draw_image(&software.read_frame());
+    }
+
+    // Frees all GL resources while the context is still alive and reports anything left over
+    pub fn shutdown(self) {
+        let GameWindow { renderer, scene, assets, window, .. } = self;
+        drop(renderer);
+        drop(scene);
+        drop(assets);
+        gl_resource::report_live_resources();
+        drop(window);
+    }
+
+    // Drops what only the level holds and streams it in again with a fresh scene
+    fn restart_level(&mut self) {
+        let unloaded = self.assets.unload_scope(AssetScope::Level);
+        println!("Restarting level, unloaded {} assets", unloaded);
+        self.assets.queue_scope(AssetScope::Level);
+        self.scene = Box::new(MainScene::new(&self.assets.manifest));
+        self.level_built = false;
+    }
+
+    fn reload_shaders(&mut self) {
+        let results = self.assets.reload_changed_programs();
+        if results.is_empty() {
+            return;
+        }
+
+        for (program_id, result) in results {
+            match result {
+                Ok(()) => {
+                    println!("Reloaded {} program", self.assets.manifest.get_program_key(program_id));
+                    self.shader_errors.remove(&program_id);
+                }
+                Err(error) => {
+                    eprintln!("Failed to reload {} program, keeping the old one: {}", self.assets.manifest.get_program_key(program_id), error);
+                    self.shader_errors.insert(program_id, error.to_string());
+                }
+            }
+        }
+
+        // The log is drawn over the scene, the title shows its first line too
+        match self.shader_errors.values().next() {
+            Some(error) => {
+                // First log line, it names the file and line
+                let summary = error.lines().nth(1).unwrap_or(error);
+                self.window.set_title(&format!("{} - {}", self.title, summary));
+            }
+            None => self.window.set_title(&self.title),
+        }
+    }
+
+    // Red bar along the top edge with the error logs under it while a shader fails to compile
+    fn draw_shader_errors(&mut self) {
+        let size = self.renderer.internal_size;
+        // A font pixel is about a pixel of a 320x200 screen
+        let scale = (size.y / 200.0).floor().max(1.0);
+        let line_height = bitmap_font::LINE_HEIGHT as f32 * scale;
+        let lines: Vec<&str> = self.shader_errors.values()
+            .flat_map(|error| error.lines())
+            .take(MAX_ERROR_LINES)
+            .collect();
+
+        let height = 4.0 + scale + line_height * lines.len() as f32;
+        self.renderer.draw_rect(Vec2::new(0.0, size.y - height), Vec2::new(size.x, height), 0.0, Vec4::new(0.0, 0.0, 0.0, 0.75));
+        self.renderer.draw_rect(Vec2::new(0.0, size.y - 4.0), Vec2::new(size.x, 4.0), 0.0, Vec4::new(1.0, 0.0, 0.0, 1.0));
+        for (index, line) in lines.iter().enumerate() {
+            let top_left = Vec2::new(scale, size.y - 4.0 - scale - index as f32 * line_height);
+            bitmap_font::draw_text(&self.renderer, line, top_left, scale, Vec4::new(1.0, 0.8, 0.8, 1.0));
+        }
+    }
+
+    // Progress bar along the bottom edge while a scope loads
+    fn draw_loading_bar(&mut self, fraction: f32) {
+        let width = self.renderer.internal_size.x;
+        self.renderer.draw_rect(Vec2::ZERO, Vec2::new(width, 4.0), 0.0, Vec4::new(0.2, 0.2, 0.2, 1.0));
+        self.renderer.draw_rect(Vec2::ZERO, Vec2::new(width * fraction, 4.0), 0.0, Vec4::new(1.0, 1.0, 1.0, 1.0));
+    }
+
+    fn save_screenshot(&self) {
+        if let Err(error) = fs::create_dir_all(&self.screenshot.directory) {
+            eprintln!("Failed to create screenshot directory {}: {}", self.screenshot.directory.display(), error);
+            return;
+        }
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let path = self.screenshot.directory.join(format!("screenshot_{}_{:03}.png",
+                                                          timestamp.as_secs(),
+                                                          timestamp.subsec_millis()));
+        match self.renderer.capture_frame(&path, self.screenshot.internal_resolution) {
+            Ok(()) => println!("Saved screenshot {}", path.display()),
+            Err(error) => eprintln!("Failed to save screenshot {}: {}", path.display(), error),
+        }
+    }
+
     fn handle_window_event(window: &mut PWindow,
                            event: WindowEvent,
+                           renderer: &mut Renderer,
                            key_listener: &mut KeyListener,
                            mouse_listener: &mut MouseListener
     ) {
@@ -112,6 +422,34 @@ impl GameWindow {
             WindowEvent::Key(Key::Escape, _, glfw::Action::Press, _) => {
                 window.set_should_close(true)
             }
+            WindowEvent::Key(Key::F4, _, glfw::Action::Press, _) => {
+                renderer.sky_mode = renderer.sky_mode.next();
+            }
+            // Post processing toggles
+            WindowEvent::Key(Key::F5, _, glfw::Action::Press, _) => {
+                renderer.post_process.toggle(PostEffectKind::Palette);
+            }
+            WindowEvent::Key(Key::F6, _, glfw::Action::Press, _) => {
+                renderer.post_process.toggle(PostEffectKind::Crt);
+            }
+            // Display toggles
+            WindowEvent::Key(Key::F7, _, glfw::Action::Press, _) => {
+                renderer.cycle_internal_resolution();
+            }
+            WindowEvent::Key(Key::F8, _, glfw::Action::Press, _) => {
+                let settings = renderer.get_display_settings();
+                renderer.set_display_settings(DisplaySettings {
+                    integer_scale: !settings.integer_scale,
+                    ..settings
+                });
+            }
+            WindowEvent::Key(Key::F9, _, glfw::Action::Press, _) => {
+                renderer.toggle_light_mode();
+            }
+            // Loaded assets and their memory
+            WindowEvent::Key(Key::F10, _, glfw::Action::Press, _) => {
+                println!("{}", renderer.get_assets().get_memory_report());
+            }
             // Any other key
             WindowEvent::Key(key, _, event, _) => {
                 key_listener.handle_key_callback(key, event);
@@ -119,6 +457,9 @@ impl GameWindow {
             WindowEvent::CursorPos(xpos, ypos) => {
                 mouse_listener.mouse_pos_callback(xpos, ypos);
             }
+            WindowEvent::FramebufferSize(width, height) => {
+                renderer.resize(width, height);
+            }
             _ => {}
         }
     }
@@ -0,0 +1,291 @@
+// Golden image tests. Canonical scenes are rendered headless and compared against the reference
+// PNGs in tests/golden, mismatches write the actual frame and a diff image to target/golden.
+// A missing reference fails, run with UPDATE_GOLDEN=1 to record the references after an intended change.
+// GL references are recorded with Mesa's llvmpipe, other drivers may round differently.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use glam::{Vec2, Vec3, Vec4};
+use image::{Rgba, RgbaImage};
+
+use crate::assets::vfs::Vfs;
+use crate::game::camera::Camera;
+use crate::game::scene::{MainScene, Scene};
+use crate::game::weapon::{SHOTGUN, Weapon};
+use crate::graphics::render::Renderer;
+use crate::graphics::render_backend::RenderBackend;
+use crate::graphics::shape_builder::ShapeBuilder;
+use crate::graphics::software_renderer::SoftwareRenderer;
+use crate::headless::HeadlessRenderer;
+
+const GOLDEN_WIDTH: u32 = 320;
+const GOLDEN_HEIGHT: u32 = 200;
+
+// Drivers round differently, allow small differences per channel
+const CHANNEL_TOLERANCE: u8 = 3;
+// and a few differing pixels along triangle edges
+const MAX_MISMATCH_RATIO: f32 = 0.002;
+
+struct GoldenScene {
+    name: &'static str,
+    draw: fn(&mut Renderer),
+}
+
+const GOLDEN_SCENES: [GoldenScene; 6] = [
+    GoldenScene { name: "gl/crosshair", draw: draw_crosshair },
+    GoldenScene { name: "gl/weapon", draw: draw_weapon },
+    GoldenScene { name: "gl/wall", draw: draw_wall },
+    GoldenScene { name: "gl/polygons", draw: draw_polygons },
+    GoldenScene { name: "gl/model", draw: draw_model },
+    GoldenScene { name: "gl/main_scene", draw: draw_main_scene },
+];
+
+fn draw_crosshair(renderer: &mut Renderer) {
+    MainScene::new(&renderer.get_assets().manifest).draw_crosshair(renderer);
+}
+
+// Catches sprite offsets read wrong from the PNGs
+fn draw_weapon(renderer: &mut Renderer) {
+    let texture_id = renderer.get_assets().manifest.get_texture_id(SHOTGUN.texture);
+    Weapon::new(&SHOTGUN, texture_id).draw(renderer);
+}
+
+// Same wall as MainScene, catches broken uv scaling along its width
+fn draw_wall(renderer: &mut Renderer) {
+    let scene = MainScene::new(&renderer.get_assets().manifest);
+    renderer.set_lights(scene.lights());
+    renderer.draw_wall(Camera::new().get_view_matrix(),
+                       Vec3::new(0.0, 0.0, -20.0),
+                       Vec2::new(100.0, 10.0),
+                       0.0,
+                       Vec4::ONE);
+}
+
+fn draw_polygons(renderer: &mut Renderer) {
+    let red = Vec4::new(1.0, 0.0, 0.0, 1.0);
+    renderer.draw_polygon(&ShapeBuilder::get_hexagon_vertices(), Vec3::new(60.0, 100.0, 0.0), 0.0, 30.0, red);
+    renderer.draw_polygon(&ShapeBuilder::get_octagon_vertices(), Vec3::new(160.0, 100.0, 0.0), 15.0, 30.0, red);
+    renderer.draw_polygon(&ShapeBuilder::get_high_res_cirlce_vertices(), Vec3::new(260.0, 100.0, 0.0), 0.0, 30.0, red);
+}
+
+fn draw_model(renderer: &mut Renderer) {
+    let scene = MainScene::new(&renderer.get_assets().manifest);
+    renderer.set_lights(scene.lights());
+    renderer.draw_model(Camera::new().get_view_matrix(), renderer.get_assets().manifest.get_model_id("bench"), Vec3::new(0.0, -5.0, -15.0), 30.0, 5.0);
+}
+
+// Sector walls, sky, props, weapon and crosshair from the starting camera
+fn draw_main_scene(renderer: &mut Renderer) {
+    let mut scene = MainScene::new(&renderer.get_assets().manifest);
+    scene.load_level(renderer);
+    renderer.set_lights(scene.lights());
+    scene.draw(&Camera::new(), renderer);
+}
+
+// Software frames need no GL context, their references are in tests/golden/software
+struct SoftwareGoldenScene {
+    name: &'static str,
+    draw: fn(&mut SoftwareRenderer),
+}
+
+const SOFTWARE_GOLDEN_SCENES: [SoftwareGoldenScene; 6] = [
+    SoftwareGoldenScene { name: "software/crosshair", draw: draw_software_crosshair },
+    SoftwareGoldenScene { name: "software/wall", draw: draw_software_wall },
+    SoftwareGoldenScene { name: "software/polygons", draw: draw_software_polygons },
+    SoftwareGoldenScene { name: "software/model", draw: draw_software_model },
+    SoftwareGoldenScene { name: "software/weapon", draw: draw_software_weapon },
+    SoftwareGoldenScene { name: "software/main_scene", draw: draw_software_main_scene },
+];
+
+fn draw_software_crosshair(renderer: &mut SoftwareRenderer) {
+    MainScene::new(renderer.get_manifest()).draw_crosshair(renderer);
+}
+
+fn draw_software_wall(renderer: &mut SoftwareRenderer) {
+    let scene = MainScene::new(renderer.get_manifest());
+    renderer.set_lights(scene.lights());
+    renderer.draw_wall(Camera::new().get_view_matrix(),
+                       Vec3::new(0.0, 0.0, -20.0),
+                       Vec2::new(100.0, 10.0),
+                       0.0,
+                       Vec4::ONE);
+}
+
+fn draw_software_polygons(renderer: &mut SoftwareRenderer) {
+    let red = Vec4::new(1.0, 0.0, 0.0, 1.0);
+    renderer.draw_polygon(&ShapeBuilder::get_hexagon_vertices(), Vec3::new(60.0, 100.0, 0.0), 0.0, 30.0, red);
+    renderer.draw_polygon(&ShapeBuilder::get_octagon_vertices(), Vec3::new(160.0, 100.0, 0.0), 15.0, 30.0, red);
+    renderer.draw_polygon(&ShapeBuilder::get_high_res_cirlce_vertices(), Vec3::new(260.0, 100.0, 0.0), 0.0, 30.0, red);
+}
+
+fn draw_software_model(renderer: &mut SoftwareRenderer) {
+    let scene = MainScene::new(renderer.get_manifest());
+    renderer.set_lights(scene.lights());
+    let model_id = renderer.get_manifest().get_model_id("bench");
+    renderer.draw_model(Camera::new().get_view_matrix(), model_id, Vec3::new(0.0, -5.0, -15.0), 30.0, 5.0);
+}
+
+fn draw_software_weapon(renderer: &mut SoftwareRenderer) {
+    let texture_id = renderer.get_manifest().get_texture_id(SHOTGUN.texture);
+    Weapon::new(&SHOTGUN, texture_id).draw(renderer);
+}
+
+// Walls, sky ceiling, bench, weapon and crosshair from the starting camera
+fn draw_software_main_scene(renderer: &mut SoftwareRenderer) {
+    let mut scene = MainScene::new(renderer.get_manifest());
+    scene.load_level(renderer);
+    renderer.set_lights(scene.lights());
+    scene.draw(&Camera::new(), renderer);
+}
+
+struct Comparison {
+    mismatched: usize,
+    max_difference: u8,
+    // Mismatched pixels in red over a faded copy of the expected image
+    diff: RgbaImage,
+}
+
+impl Comparison {
+    fn mismatch_ratio(&self) -> f32 {
+        self.mismatched as f32 / (self.diff.width() * self.diff.height()).max(1) as f32
+    }
+}
+
+fn compare(actual: &RgbaImage, expected: &RgbaImage, tolerance: u8) -> Comparison {
+    assert_eq!(actual.dimensions(), expected.dimensions(), "Compared images differ in size");
+
+    let mut mismatched = 0;
+    let mut max_difference = 0;
+    let mut diff = RgbaImage::new(expected.width(), expected.height());
+    for ((actual, expected), diff) in actual.pixels().zip(expected.pixels()).zip(diff.pixels_mut()) {
+        let difference = actual.0.iter()
+            .zip(expected.0.iter())
+            .map(|(a, e)| a.abs_diff(*e))
+            .max()
+            .unwrap_or(0);
+        max_difference = max_difference.max(difference);
+
+        *diff = if difference > tolerance {
+            mismatched += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let [r, g, b, _] = expected.0;
+            let gray = ((r as u32 + g as u32 + b as u32) / 3 / 4) as u8;
+            Rgba([gray, gray, gray, 255])
+        };
+    }
+
+    Comparison {
+        mismatched,
+        max_difference,
+        diff,
+    }
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.png", name))
+}
+
+fn failure_path(name: &str, suffix: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden").join(format!("{}.{}.png", name, suffix))
+}
+
+// Records the frame instead of comparing when UPDATE_GOLDEN is set
+fn check_golden(name: &str, frame: &RgbaImage) -> Result<(), String> {
+    let path = golden_path(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        frame.save(&path).unwrap();
+        eprintln!("Recorded golden image {}", path.display());
+        return Ok(());
+    }
+    if !path.exists() {
+        return Err(format!("{}: no reference at {}, record it with UPDATE_GOLDEN=1", name, path.display()));
+    }
+
+    let expected = image::open(&path)
+        .map_err(|error| format!("{}: failed to load {}: {}", name, path.display(), error))?
+        .to_rgba8();
+    if expected.dimensions() != frame.dimensions() {
+        return Err(format!("{}: size {:?} does not match reference {:?}", name, frame.dimensions(), expected.dimensions()));
+    }
+
+    let comparison = compare(frame, &expected, CHANNEL_TOLERANCE);
+    if comparison.mismatch_ratio() <= MAX_MISMATCH_RATIO {
+        return Ok(());
+    }
+
+    let actual_path = failure_path(name, "actual");
+    let diff_path = failure_path(name, "diff");
+    fs::create_dir_all(actual_path.parent().unwrap()).unwrap();
+    frame.save(&actual_path).unwrap();
+    comparison.diff.save(&diff_path).unwrap();
+
+    Err(format!("{}: {} pixels differ (max difference {}), see {} and {}",
+                name,
+                comparison.mismatched,
+                comparison.max_difference,
+                actual_path.display(),
+                diff_path.display()))
+}
+
+// Creating a context and loading every asset is slow, so all scenes share one renderer
+#[test]
+#[ignore = "needs a display, a GPU or OSMesa, run with --ignored"]
+fn golden_images() {
+    let mut headless = HeadlessRenderer::new(GOLDEN_WIDTH, GOLDEN_HEIGHT).unwrap_or_else(|e| panic!("{}", e));
+
+    let failures: Vec<String> = GOLDEN_SCENES.iter()
+        .filter_map(|scene| check_golden(scene.name, &headless.render(scene.draw)).err())
+        .collect();
+
+    assert!(failures.is_empty(), "Golden image mismatches:\n{}", failures.join("\n"));
+}
+
+#[test]
+fn software_golden_images() {
+    let mut renderer = SoftwareRenderer::new(GOLDEN_WIDTH, GOLDEN_HEIGHT, Arc::new(Vfs::with_asset_root()));
+
+    let failures: Vec<String> = SOFTWARE_GOLDEN_SCENES.iter()
+        .filter_map(|scene| {
+            renderer.begin_frame();
+            (scene.draw)(&mut renderer);
+            renderer.end_frame();
+            check_golden(scene.name, &renderer.read_frame()).err()
+        })
+        .collect();
+
+    assert!(failures.is_empty(), "Golden image mismatches:\n{}", failures.join("\n"));
+}
+
+#[test]
+fn compare_identical_images() {
+    let image = RgbaImage::from_pixel(4, 4, Rgba([10, 20, 30, 255]));
+    let comparison = compare(&image, &image, 0);
+    assert_eq!(comparison.mismatched, 0);
+    assert_eq!(comparison.max_difference, 0);
+}
+
+#[test]
+fn compare_within_tolerance() {
+    let expected = RgbaImage::from_pixel(4, 4, Rgba([100, 100, 100, 255]));
+    let actual = RgbaImage::from_pixel(4, 4, Rgba([102, 99, 100, 255]));
+    let comparison = compare(&actual, &expected, CHANNEL_TOLERANCE);
+    assert_eq!(comparison.mismatched, 0);
+    assert_eq!(comparison.max_difference, 2);
+}
+
+#[test]
+fn compare_marks_mismatches_in_diff() {
+    let expected = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 255]));
+    let mut actual = expected.clone();
+    actual.put_pixel(1, 2, Rgba([0, 200, 0, 255]));
+
+    let comparison = compare(&actual, &expected, CHANNEL_TOLERANCE);
+    assert_eq!(comparison.mismatched, 1);
+    assert_eq!(comparison.max_difference, 200);
+    assert_eq!(comparison.mismatch_ratio(), 1.0 / 16.0);
+    assert_eq!(comparison.diff.get_pixel(1, 2).0, [255, 0, 0, 255]);
+    assert_eq!(comparison.diff.get_pixel(0, 0).0, [0, 0, 0, 255]);
+}
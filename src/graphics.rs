@@ -1,7 +1,26 @@
 pub mod render;
+pub mod gl_resource;
+pub mod render_backend;
 pub mod program;
+pub mod uniform;
+pub mod frame_uniforms;
 pub mod shader;
+pub mod shader_error;
 pub mod mesh;
 mod polygon;
+pub mod sector_wall;
 pub mod shape_builder;
-pub mod texture;
\ No newline at end of file
+pub mod bitmap_font;
+pub mod texture;
+pub mod vertex_layout;
+pub mod light;
+pub mod material;
+pub mod model;
+pub mod sky;
+pub mod render_target;
+pub mod post_process;
+pub mod palette;
+pub mod display;
+pub mod colormap;
+pub mod rasterizer;
+pub mod software_renderer;
@@ -0,0 +1,153 @@
+use glam::{Vec2, Vec4};
+
+use crate::graphics::render::Renderer;
+
+pub const GLYPH_WIDTH: u32 = 5;
+pub const GLYPH_HEIGHT: u32 = 7;
+// One pixel between glyphs and between lines
+pub const GLYPH_ADVANCE: u32 = GLYPH_WIDTH + 1;
+pub const LINE_HEIGHT: u32 = GLYPH_HEIGHT + 1;
+
+const FIRST_GLYPH: char = ' ';
+const MISSING_GLYPH: char = '?';
+
+// 5x7 glyphs from space to underscore, a row per byte with the leftmost pixel in bit 4.
+// Lowercase is drawn as uppercase.
+const GLYPHS: [[u8; 7]; 64] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x04, 0x04, 0x04, 0x04, 0x00, 0x00, 0x04], // !
+    [0x0A, 0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00], // "
+    [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A], // #
+    [0x04, 0x0F, 0x14, 0x0E, 0x05, 0x1E, 0x04], // $
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], // %
+    [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D], // &
+    [0x0C, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00], // '
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], // (
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], // )
+    [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00], // *
+    [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00], // +
+    [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08], // ,
+    [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00], // -
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C], // .
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00], // /
+    [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E], // 0
+    [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E], // 1
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F], // 2
+    [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E], // 3
+    [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02], // 4
+    [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E], // 5
+    [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E], // 6
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08], // 7
+    [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E], // 8
+    [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C], // 9
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00], // :
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08], // ;
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02], // <
+    [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00], // =
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08], // >
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // ?
+    [0x0E, 0x11, 0x01, 0x0D, 0x15, 0x15, 0x0E], // @
+    [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11], // A
+    [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E], // B
+    [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E], // C
+    [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C], // D
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F], // E
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10], // F
+    [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F], // G
+    [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11], // H
+    [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E], // I
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C], // J
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11], // K
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F], // L
+    [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11], // M
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], // N
+    [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], // O
+    [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10], // P
+    [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D], // Q
+    [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11], // R
+    [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E], // S
+    [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // T
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], // U
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04], // V
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A], // W
+    [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11], // X
+    [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04], // Y
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F], // Z
+    [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E], // [
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00], // \
+    [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E], // ]
+    [0x04, 0x0A, 0x11, 0x00, 0x00, 0x00, 0x00], // ^
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F], // _
+];
+
+// Horizontal run of lit pixels, x and y from the top left of the text
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PixelRun {
+    pub x: u32,
+    pub y: u32,
+    pub length: u32,
+}
+
+pub fn get_glyph(character: char) -> &'static [u8; 7] {
+    // Tabs in driver logs
+    if character.is_whitespace() {
+        return &GLYPHS[0];
+    }
+    let character = character.to_ascii_uppercase();
+    let index = (character as u32).wrapping_sub(FIRST_GLYPH as u32) as usize;
+    GLYPHS.get(index).unwrap_or(&GLYPHS[(MISSING_GLYPH as u32 - FIRST_GLYPH as u32) as usize])
+}
+
+// Lit pixels of a single line merged into runs, so a row of a glyph is one rect
+pub fn get_pixel_runs(text: &str) -> Vec<PixelRun> {
+    let mut runs = Vec::new();
+    for (column, character) in text.chars().enumerate() {
+        let left = column as u32 * GLYPH_ADVANCE;
+        for (y, row) in get_glyph(character).iter().enumerate() {
+            let mut x = 0;
+            while x < GLYPH_WIDTH {
+                let start = x;
+                while x < GLYPH_WIDTH && row & (0x10 >> x) != 0 {
+                    x += 1;
+                }
+                if x > start {
+                    runs.push(PixelRun { x: left + start, y: y as u32, length: x - start });
+                }
+                x += 1;
+            }
+        }
+    }
+    runs
+}
+
+// Draws a line of text with its top left corner at the position, scale is the size of a font pixel
+pub fn draw_text(renderer: &Renderer, text: &str, top_left: Vec2, scale: f32, color: Vec4) {
+    for run in get_pixel_runs(text) {
+        let position = Vec2::new(top_left.x + run.x as f32 * scale, top_left.y - (run.y + 1) as f32 * scale);
+        renderer.draw_rect(position, Vec2::new(run.length as f32 * scale, scale), 0.0, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_glyph_rows_into_runs() {
+        let runs = get_pixel_runs("T1");
+        // Top bar of the T, then its stem
+        assert_eq!(runs[0], PixelRun { x: 0, y: 0, length: 5 });
+        assert_eq!(runs[1], PixelRun { x: 2, y: 1, length: 1 });
+        // The 1 starts after the advance
+        assert!(runs.iter().filter(|run| run.x >= GLYPH_ADVANCE).all(|run| run.x < 2 * GLYPH_ADVANCE));
+        assert_eq!(runs.last(), Some(&PixelRun { x: GLYPH_ADVANCE + 1, y: 6, length: 3 }));
+    }
+
+    #[test]
+    fn draws_lowercase_as_uppercase_and_unknown_as_question_mark() {
+        assert_eq!(get_glyph('a'), get_glyph('A'));
+        assert_eq!(get_glyph('~'), get_glyph('?'));
+        assert_eq!(get_glyph('é'), get_glyph('?'));
+        assert_eq!(get_glyph('\t'), get_glyph(' '));
+    }
+}
@@ -0,0 +1,205 @@
+use gl::types::GLint;
+
+use crate::assets::vfs::Vfs;
+use crate::graphics::gl_resource::GlObject;
+use crate::graphics::palette::{PALETTE_SIZE, Palette};
+
+// Light rows in COLORMAP, from full bright to black
+pub const NUM_COLORMAPS: usize = 32;
+// Light rows plus the invulnerability and all black maps
+pub const COLORMAP_ROWS: usize = 34;
+// Sector light (0-255) is reduced to this many levels
+pub const LIGHT_LEVELS: i32 = 16;
+// Lump names in a WAD
+pub const PLAYPAL_LUMP: &str = "PLAYPAL";
+pub const COLORMAP_LUMP: &str = "COLORMAP";
+// Resolution of the color to palette index lookup table per channel
+pub const INDEX_LUT_SIZE: usize = 32;
+
+// How sector light is applied to level geometry
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LightMode {
+    // Colors are remapped through COLORMAP like the original renderer, banding included
+    Palette,
+    // Same light falloff, applied as a plain RGB multiply
+    Smooth,
+}
+
+// Palette index remapping tables, one row per light level, like Doom's COLORMAP
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Colormap {
+    pub maps: Vec<[u8; PALETTE_SIZE]>,
+}
+
+impl Colormap {
+    pub fn from_lump(lump: &[u8]) -> Colormap {
+        if lump.len() < NUM_COLORMAPS * PALETTE_SIZE {
+            panic!("COLORMAP lump too small: {} bytes", lump.len());
+        }
+
+        Colormap {
+            maps: lump.chunks_exact(PALETTE_SIZE)
+                .map(|row| row.try_into().unwrap())
+                .collect()
+        }
+    }
+
+    // Builds the tables from a palette the same way id's tools did, fading every color to black
+    pub fn generate(palette: &Palette) -> Colormap {
+        let mut maps = Vec::with_capacity(COLORMAP_ROWS);
+
+        for row in 0..NUM_COLORMAPS {
+            let brightness = (NUM_COLORMAPS - row) as f32 / NUM_COLORMAPS as f32;
+            let mut map = [0; PALETTE_SIZE];
+            for (i, color) in palette.colors.iter().enumerate() {
+                let faded = color.map(|c| (c as f32 * brightness).round() as u8);
+                map[i] = palette.nearest(faded) as u8;
+            }
+            maps.push(map);
+        }
+
+        // Invulnerability, inverted grayscale
+        let mut map = [0; PALETTE_SIZE];
+        for (i, color) in palette.colors.iter().enumerate() {
+            let gray = (color[0] as u32 * 77 + color[1] as u32 * 151 + color[2] as u32 * 28) >> 8;
+            let inverted = 255 - gray as u8;
+            map[i] = palette.nearest([inverted, inverted, inverted]) as u8;
+        }
+        maps.push(map);
+
+        // All black
+        maps.push([palette.nearest([0, 0, 0]) as u8; PALETTE_SIZE]);
+
+        Colormap {
+            maps
+        }
+    }
+
+    // One row per map, red channel holds the palette index
+    pub fn create_texture(&self) -> GlObject {
+        let data: Vec<u8> = self.maps.iter().flatten().copied().collect();
+
+        let texture = GlObject::texture();
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, texture.id());
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexImage2D(gl::TEXTURE_2D,
+                           0,
+                           gl::R8 as i32,
+                           PALETTE_SIZE as i32,
+                           self.maps.len() as i32,
+                           0,
+                           gl::RED,
+                           gl::UNSIGNED_BYTE,
+                           data.as_ptr().cast());
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+        texture
+    }
+}
+
+// Palette and colormap from the PLAYPAL and COLORMAP lumps of a mounted WAD, None without PLAYPAL.
+// A WAD without COLORMAP gets one generated from its palette.
+pub fn load_lumps(vfs: &Vfs) -> Option<(Palette, Colormap)> {
+    if !vfs.exists(PLAYPAL_LUMP) {
+        return None;
+    }
+
+    let read = |lump: &str| vfs.read(lump).unwrap_or_else(|e| panic!("{}", e));
+    let palette = Palette::from_playpal(&read(PLAYPAL_LUMP), 0);
+    let colormap = if vfs.exists(COLORMAP_LUMP) {
+        Colormap::from_lump(&read(COLORMAP_LUMP))
+    } else {
+        Colormap::generate(&palette)
+    };
+    Some((palette, colormap))
+}
+
+// Light level index for a sector light, with fake contrast applied
+pub fn get_light_index(sector_light: u8, contrast: i32) -> i32 {
+    (sector_light as i32 / LIGHT_LEVELS + contrast).clamp(0, LIGHT_LEVELS - 1)
+}
+
+// Colormap row for a light index at a distance in map units, matches the zlight table of the
+// original renderer. Bright sectors stay bright up close and fade towards their darkest row.
+pub fn get_colormap_row(light_index: i32, distance: f32) -> usize {
+    let start_map = ((LIGHT_LEVELS - 1 - light_index) * 2 * NUM_COLORMAPS as i32 / LIGHT_LEVELS) as f32;
+    let row = start_map - 1280.0 / distance.max(1.0);
+    row.clamp(0.0, (NUM_COLORMAPS - 1) as f32) as usize
+}
+
+// Walls running along the x axis are darker and walls along z brighter, like Doom
+pub fn get_wall_contrast(rotation_y_deg: f32) -> i32 {
+    let angle = rotation_y_deg.rem_euclid(180.0);
+    if angle.abs() < 0.01 || (180.0 - angle).abs() < 0.01 {
+        -1
+    } else if (angle - 90.0).abs() < 0.01 {
+        1
+    } else {
+        0
+    }
+}
+
+// GL textures needed for palette accurate lighting
+pub struct LightTables {
+    palette: GlObject,
+    colormap: GlObject,
+    index_lut: GlObject,
+}
+
+impl LightTables {
+    pub fn new(palette: &Palette, colormap: &Colormap) -> LightTables {
+        LightTables {
+            palette: palette.create_texture(),
+            colormap: colormap.create_texture(),
+            index_lut: palette.create_index_lut_texture(INDEX_LUT_SIZE),
+        }
+    }
+
+    // Binds the index lookup table, colormap and palette to consecutive units
+    pub fn bind(&self, first_unit: u32) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + first_unit);
+            gl::BindTexture(gl::TEXTURE_3D, self.index_lut.id());
+            gl::ActiveTexture(gl::TEXTURE0 + first_unit + 1);
+            gl::BindTexture(gl::TEXTURE_2D, self.colormap.id());
+            gl::ActiveTexture(gl::TEXTURE0 + first_unit + 2);
+            gl::BindTexture(gl::TEXTURE_2D, self.palette.id());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::graphics::palette::PLAYPAL_PALETTE_BYTES;
+
+    #[test]
+    fn loads_lumps_when_mounted() {
+        let dir = std::env::temp_dir().join(format!("colormap_lumps_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut vfs = Vfs::new();
+        vfs.mount_dir("", &dir, 0).unwrap();
+        assert!(load_lumps(&vfs).is_none());
+
+        // Two palettes, the first one is used
+        let mut playpal = vec![7; PLAYPAL_PALETTE_BYTES];
+        playpal.extend(vec![200; PLAYPAL_PALETTE_BYTES]);
+        fs::write(dir.join(PLAYPAL_LUMP), &playpal).unwrap();
+        let (palette, colormap) = load_lumps(&vfs).unwrap();
+        assert_eq!(palette.colors[0], [7, 7, 7]);
+        assert_eq!(colormap, Colormap::generate(&palette));
+
+        fs::write(dir.join(COLORMAP_LUMP), vec![3; COLORMAP_ROWS * PALETTE_SIZE]).unwrap();
+        let (_, colormap) = load_lumps(&vfs).unwrap();
+        assert_eq!(colormap.maps.len(), COLORMAP_ROWS);
+        assert_eq!(colormap.maps[0][0], 3);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
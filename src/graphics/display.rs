@@ -0,0 +1,127 @@
+use glam::{IVec2, UVec2};
+
+// Resolution the scene is drawn at before being scaled to the window
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum InternalResolution {
+    // Same as the window
+    Native,
+    // Original Doom
+    Low,
+    // Doom at double resolution
+    LowDouble,
+    Custom(u32, u32),
+}
+
+impl InternalResolution {
+    pub fn get_size(&self, window_size: UVec2) -> UVec2 {
+        match self {
+            InternalResolution::Native => window_size.max(UVec2::ONE),
+            InternalResolution::Low => UVec2::new(320, 200),
+            InternalResolution::LowDouble => UVec2::new(640, 400),
+            InternalResolution::Custom(width, height) => UVec2::new(*width, *height).max(UVec2::ONE),
+        }
+    }
+
+    // "native" or width x height, e.g. "320x200"
+    pub fn parse(text: &str) -> Option<InternalResolution> {
+        if text.eq_ignore_ascii_case("native") {
+            return Some(InternalResolution::Native);
+        }
+
+        let (width, height) = text.split_once('x')?;
+        let size = UVec2::new(width.trim().parse().ok()?, height.trim().parse().ok()?);
+        match (size.x, size.y) {
+            (0, _) | (_, 0) => None,
+            (320, 200) => Some(InternalResolution::Low),
+            (640, 400) => Some(InternalResolution::LowDouble),
+            (width, height) => Some(InternalResolution::Custom(width, height)),
+        }
+    }
+
+    // Native -> 320x200 -> 640x400 -> Native, custom resolutions go back to native
+    pub fn next(&self) -> InternalResolution {
+        match self {
+            InternalResolution::Native => InternalResolution::Low,
+            InternalResolution::Low => InternalResolution::LowDouble,
+            InternalResolution::LowDouble | InternalResolution::Custom(..) => InternalResolution::Native,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DisplaySettings {
+    pub resolution: InternalResolution,
+    // Show 320x200 as 4:3 like a CRT did, pixels end up 1.2 times taller than wide
+    pub aspect_correction: bool,
+    // Only scale by whole numbers, leaves a border around the image
+    pub integer_scale: bool,
+}
+
+impl DisplaySettings {
+    pub fn native() -> Self {
+        DisplaySettings {
+            resolution: InternalResolution::Native,
+            aspect_correction: false,
+            integer_scale: false,
+        }
+    }
+
+    // Doom's resolutions are shown at 4:3 like on a CRT
+    pub fn with_resolution(resolution: InternalResolution) -> Self {
+        DisplaySettings {
+            resolution,
+            aspect_correction: matches!(resolution, InternalResolution::Low | InternalResolution::LowDouble),
+            ..DisplaySettings::native()
+        }
+    }
+
+    // Width / height of the image as shown on screen
+    pub fn get_display_aspect(&self, window_size: UVec2) -> f32 {
+        let internal_size = self.resolution.get_size(window_size);
+        if self.aspect_correction && self.resolution != InternalResolution::Native {
+            4.0 / 3.0
+        } else {
+            internal_size.x as f32 / internal_size.y as f32
+        }
+    }
+
+    // Where on the window the image ends up, centered with black bars around it
+    pub fn get_output_viewport(&self, window_size: UVec2) -> Viewport {
+        let window_size = window_size.max(UVec2::ONE);
+        if self.resolution == InternalResolution::Native {
+            return Viewport::new(IVec2::ZERO, window_size);
+        }
+
+        let internal_size = self.resolution.get_size(window_size);
+        let display_width = internal_size.x as f32;
+        let display_height = display_width / self.get_display_aspect(window_size);
+
+        let mut scale = (window_size.x as f32 / display_width).min(window_size.y as f32 / display_height);
+        if self.integer_scale {
+            scale = scale.floor().max(1.0);
+        }
+
+        let size = UVec2::new(
+            ((display_width * scale).round() as u32).min(window_size.x),
+            ((display_height * scale).round() as u32).min(window_size.y),
+        );
+        let position = (window_size.as_ivec2() - size.as_ivec2()) / 2;
+        Viewport::new(position, size)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Viewport {
+    // Bottom left corner
+    pub position: IVec2,
+    pub size: UVec2,
+}
+
+impl Viewport {
+    pub fn new(position: IVec2, size: UVec2) -> Self {
+        Viewport {
+            position,
+            size,
+        }
+    }
+}
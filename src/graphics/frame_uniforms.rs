@@ -0,0 +1,190 @@
+use std::cell::Cell;
+
+use glam::{Mat4, Vec3};
+
+use crate::graphics::gl_resource::GlObject;
+use crate::graphics::light::{Light, MAX_LIGHTS};
+
+// Must match the Frame block in assets/shaders/common/frame.glsl
+pub const FRAME_BLOCK_NAME: &str = "Frame";
+pub const FRAME_BLOCK_BINDING: u32 = 0;
+
+// std140 layout of the Light struct in common/light.glsl
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct LightBlock {
+    light_type: i32,
+    _padding0: [i32; 3],
+    position: [f32; 3],
+    _padding1: f32,
+    direction: [f32; 3],
+    _padding2: f32,
+    color: [f32; 3],
+    constant: f32,
+    linear: f32,
+    quadratic: f32,
+    cut_off: f32,
+    outer_cut_off: f32,
+}
+
+impl LightBlock {
+    fn new(light: &Light) -> Self {
+        let mut block = LightBlock {
+            light_type: light.get_type_id(),
+            ..LightBlock::default()
+        };
+
+        match light {
+            Light::Directional { direction, color } => {
+                block.direction = direction.to_array();
+                block.color = color.to_array();
+            }
+            Light::Point { position, color, attenuation } => {
+                block.position = position.to_array();
+                block.color = color.to_array();
+                block.constant = attenuation.constant;
+                block.linear = attenuation.linear;
+                block.quadratic = attenuation.quadratic;
+            }
+            Light::Spot { position, direction, color, attenuation, inner_cutoff_deg, outer_cutoff_deg } => {
+                block.position = position.to_array();
+                block.direction = direction.to_array();
+                block.color = color.to_array();
+                block.constant = attenuation.constant;
+                block.linear = attenuation.linear;
+                block.quadratic = attenuation.quadratic;
+                block.cut_off = inner_cutoff_deg.to_radians().cos();
+                block.outer_cut_off = outer_cutoff_deg.to_radians().cos();
+            }
+        }
+        block
+    }
+}
+
+// std140 layout of the Frame block
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FrameBlock {
+    view: [f32; 16],
+    projection: [f32; 16],
+    ortho_projection: [f32; 16],
+    view_position: [f32; 3],
+    time: f32,
+    ambient_light: [f32; 3],
+    light_count: i32,
+    blinn: i32,
+    _padding: [i32; 3],
+    lights: [LightBlock; MAX_LIGHTS],
+}
+
+// Camera, projections, time and lights shared by every program through one uniform buffer
+pub struct FrameUniforms {
+    buffer: GlObject,
+    // Last uploaded view, the draw calls pass the camera in
+    view: Cell<Mat4>,
+}
+
+impl FrameUniforms {
+    pub fn new() -> Self {
+        let buffer = GlObject::buffer();
+        unsafe {
+            gl::BindBuffer(gl::UNIFORM_BUFFER, buffer.id());
+            gl::BufferData(gl::UNIFORM_BUFFER, size_of::<FrameBlock>() as isize, std::ptr::null(), gl::DYNAMIC_DRAW);
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+            // Stays bound, programs point their Frame block here when linked
+            gl::BindBufferBase(gl::UNIFORM_BUFFER, FRAME_BLOCK_BINDING, buffer.id());
+        }
+
+        Self {
+            buffer,
+            view: Cell::new(Mat4::IDENTITY),
+        }
+    }
+
+    // Uploads the whole block, once per frame and when the lights change
+    pub fn upload(&self,
+                  perspective_projection: Mat4,
+                  orthographic_projection: Mat4,
+                  time: f32,
+                  ambient_light: Vec3,
+                  blinn_phong: bool,
+                  lights: &[Light]) {
+        if lights.len() > MAX_LIGHTS {
+            panic!("Too many lights: {} (max {})", lights.len(), MAX_LIGHTS);
+        }
+
+        let view = self.view.get();
+        let mut block = FrameBlock {
+            view: view.to_cols_array(),
+            projection: perspective_projection.to_cols_array(),
+            ortho_projection: orthographic_projection.to_cols_array(),
+            view_position: get_view_position(view).to_array(),
+            time,
+            ambient_light: ambient_light.to_array(),
+            light_count: lights.len() as i32,
+            blinn: blinn_phong as i32,
+            _padding: [0; 3],
+            lights: [LightBlock::default(); MAX_LIGHTS],
+        };
+        for (block_light, light) in block.lights.iter_mut().zip(lights) {
+            *block_light = LightBlock::new(light);
+        }
+
+        self.write(0, &block);
+    }
+
+    // Only uploads the view and camera position when the camera moved
+    pub fn set_view(&self, view: Mat4) {
+        if self.view.get() == view {
+            return;
+        }
+        self.view.set(view);
+
+        self.write(std::mem::offset_of!(FrameBlock, view), &view.to_cols_array());
+        self.write(std::mem::offset_of!(FrameBlock, view_position), &get_view_position(view).to_array());
+    }
+
+    fn write<T>(&self, offset: usize, data: &T) {
+        unsafe {
+            gl::BindBuffer(gl::UNIFORM_BUFFER, self.buffer.id());
+            gl::BufferSubData(gl::UNIFORM_BUFFER,
+                              offset as isize,
+                              size_of::<T>() as isize,
+                              (data as *const T).cast());
+            gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        }
+    }
+}
+
+// Camera position is the translation of the inverse view matrix
+fn get_view_position(view: Mat4) -> Vec3 {
+    view.inverse().w_axis.truncate()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::offset_of;
+
+    use super::*;
+
+    // Offsets the std140 rules give the GLSL declarations
+    #[test]
+    fn blocks_match_std140() {
+        assert_eq!(size_of::<LightBlock>(), 80);
+        assert_eq!(offset_of!(LightBlock, position), 16);
+        assert_eq!(offset_of!(LightBlock, direction), 32);
+        assert_eq!(offset_of!(LightBlock, color), 48);
+        assert_eq!(offset_of!(LightBlock, constant), 60);
+        assert_eq!(offset_of!(LightBlock, outer_cut_off), 76);
+
+        assert_eq!(offset_of!(FrameBlock, projection), 64);
+        assert_eq!(offset_of!(FrameBlock, ortho_projection), 128);
+        assert_eq!(offset_of!(FrameBlock, view_position), 192);
+        assert_eq!(offset_of!(FrameBlock, time), 204);
+        assert_eq!(offset_of!(FrameBlock, ambient_light), 208);
+        assert_eq!(offset_of!(FrameBlock, light_count), 220);
+        assert_eq!(offset_of!(FrameBlock, blinn), 224);
+        assert_eq!(offset_of!(FrameBlock, lights), 240);
+        assert_eq!(size_of::<FrameBlock>(), 240 + 80 * MAX_LIGHTS);
+    }
+}
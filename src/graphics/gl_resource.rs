@@ -0,0 +1,149 @@
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+use gl::types::{GLenum, GLuint};
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum GlResourceKind {
+    VertexArray,
+    Buffer,
+    Texture,
+    Framebuffer,
+    Renderbuffer,
+    Shader,
+    Program,
+}
+
+impl GlResourceKind {
+    pub fn all() -> [GlResourceKind; 7] {
+        [GlResourceKind::VertexArray,
+         GlResourceKind::Buffer,
+         GlResourceKind::Texture,
+         GlResourceKind::Framebuffer,
+         GlResourceKind::Renderbuffer,
+         GlResourceKind::Shader,
+         GlResourceKind::Program]
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+// Live objects per kind, for finding leaks
+static LIVE_RESOURCES: [AtomicIsize; 7] = [const { AtomicIsize::new(0) }; 7];
+
+pub fn get_live_count(kind: GlResourceKind) -> isize {
+    LIVE_RESOURCES[kind.index()].load(Ordering::Relaxed)
+}
+
+// Prints every kind that still has live objects, call at shutdown after everything is dropped
+pub fn report_live_resources() {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+
+    let live: Vec<String> = GlResourceKind::all().iter()
+        .filter(|kind| get_live_count(**kind) != 0)
+        .map(|kind| format!("{:?}: {}", kind, get_live_count(*kind)))
+        .collect();
+
+    if live.is_empty() {
+        println!("No live GL resources");
+    } else {
+        println!("Live GL resources at shutdown: {}", live.join(", "));
+    }
+}
+
+// Owned GL object name, deleted on drop. Needs the context that created it to still be current,
+// so drop GL owners before the window.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct GlObject {
+    id: GLuint,
+    kind: GlResourceKind,
+}
+
+impl GlObject {
+    fn track(id: GLuint, kind: GlResourceKind) -> GlObject {
+        LIVE_RESOURCES[kind.index()].fetch_add(1, Ordering::Relaxed);
+        GlObject {
+            id,
+            kind,
+        }
+    }
+
+    pub fn vertex_array() -> GlObject {
+        let mut id = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut id);
+        }
+        GlObject::track(id, GlResourceKind::VertexArray)
+    }
+
+    pub fn buffer() -> GlObject {
+        let mut id = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut id);
+        }
+        GlObject::track(id, GlResourceKind::Buffer)
+    }
+
+    pub fn texture() -> GlObject {
+        let mut id = 0;
+        unsafe {
+            gl::GenTextures(1, &mut id);
+        }
+        GlObject::track(id, GlResourceKind::Texture)
+    }
+
+    pub fn framebuffer() -> GlObject {
+        let mut id = 0;
+        unsafe {
+            gl::GenFramebuffers(1, &mut id);
+        }
+        GlObject::track(id, GlResourceKind::Framebuffer)
+    }
+
+    pub fn renderbuffer() -> GlObject {
+        let mut id = 0;
+        unsafe {
+            gl::GenRenderbuffers(1, &mut id);
+        }
+        GlObject::track(id, GlResourceKind::Renderbuffer)
+    }
+
+    // VERTEX_SHADER or FRAGMENT_SHADER
+    pub fn shader(shader_type: GLenum) -> GlObject {
+        let id = unsafe {
+            gl::CreateShader(shader_type)
+        };
+        GlObject::track(id, GlResourceKind::Shader)
+    }
+
+    pub fn program() -> GlObject {
+        let id = unsafe {
+            gl::CreateProgram()
+        };
+        GlObject::track(id, GlResourceKind::Program)
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+}
+
+impl Drop for GlObject {
+    fn drop(&mut self) {
+        unsafe {
+            match self.kind {
+                GlResourceKind::VertexArray => gl::DeleteVertexArrays(1, &self.id),
+                GlResourceKind::Buffer => gl::DeleteBuffers(1, &self.id),
+                GlResourceKind::Texture => gl::DeleteTextures(1, &self.id),
+                GlResourceKind::Framebuffer => gl::DeleteFramebuffers(1, &self.id),
+                GlResourceKind::Renderbuffer => gl::DeleteRenderbuffers(1, &self.id),
+                GlResourceKind::Shader => gl::DeleteShader(self.id),
+                GlResourceKind::Program => gl::DeleteProgram(self.id),
+            }
+        }
+        LIVE_RESOURCES[self.kind.index()].fetch_sub(1, Ordering::Relaxed);
+    }
+}
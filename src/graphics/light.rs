@@ -0,0 +1,92 @@
+use glam::Vec3;
+
+// Must match MAX_LIGHTS in common/light.glsl
+pub const MAX_LIGHTS: usize = 16;
+
+// Smallest cosine between the inner and outer spot cone, equal cutoffs give a hard edge instead of
+// dividing by zero. Must match the lit fragment shader
+pub const MIN_SPOT_EPSILON: f32 = 0.0001;
+
+// Falloff of point and spot lights, 1 / (constant + linear * d + quadratic * d^2)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Attenuation {
+    pub fn new(constant: f32, linear: f32, quadratic: f32) -> Self {
+        Attenuation {
+            constant,
+            linear,
+            quadratic,
+        }
+    }
+
+    // Approximate falloff that fades out at the given distance
+    pub fn from_range(range: f32) -> Self {
+        let range = range.max(1.0);
+        Attenuation::new(1.0, 4.5 / range, 75.0 / (range * range))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Light {
+    // Infinitely far away, e.g. the sun
+    Directional {
+        direction: Vec3,
+        color: Vec3,
+    },
+    Point {
+        position: Vec3,
+        color: Vec3,
+        attenuation: Attenuation,
+    },
+    // Point light limited to a cone, smoothed between the inner and outer angle
+    Spot {
+        position: Vec3,
+        direction: Vec3,
+        color: Vec3,
+        attenuation: Attenuation,
+        inner_cutoff_deg: f32,
+        outer_cutoff_deg: f32,
+    },
+}
+
+impl Light {
+    pub fn directional(direction: Vec3, color: Vec3) -> Self {
+        Light::Directional {
+            direction: direction.normalize(),
+            color,
+        }
+    }
+
+    pub fn point(position: Vec3, color: Vec3, range: f32) -> Self {
+        Light::Point {
+            position,
+            color,
+            attenuation: Attenuation::from_range(range),
+        }
+    }
+
+    pub fn spot(position: Vec3, direction: Vec3, color: Vec3, range: f32, inner_cutoff_deg: f32, outer_cutoff_deg: f32) -> Self {
+        Light::Spot {
+            position,
+            direction: direction.normalize(),
+            color,
+            attenuation: Attenuation::from_range(range),
+            inner_cutoff_deg,
+            outer_cutoff_deg: outer_cutoff_deg.max(inner_cutoff_deg),
+        }
+    }
+
+    // Matches the LIGHT_* defines in common/light.glsl
+    pub fn get_type_id(&self) -> i32 {
+        match self {
+            Light::Directional { .. } => 0,
+            Light::Point { .. } => 1,
+            Light::Spot { .. } => 2,
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use glam::Vec3;
+
+use crate::graphics::program::{BUILTIN_UNIFORMS, Uniform};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Material {
+    pub ambient: Vec3,
+    pub diffuse: Vec3,
+    pub specular: Vec3,
+    pub shininess: f32,
+}
+
+impl Material {
+    pub fn new(ambient: Vec3, diffuse: Vec3, specular: Vec3, shininess: f32) -> Self {
+        Material {
+            ambient,
+            diffuse,
+            specular,
+            shininess,
+        }
+    }
+
+    // Lets textures show through unchanged, with a bit of shine
+    pub fn plain() -> Self {
+        Material::new(Vec3::ZERO, Vec3::ONE, Vec3::splat(0.2), 16.0)
+    }
+
+    // Missing values fall back to the plain material
+    pub fn from_mtl(material: &tobj::Material) -> Self {
+        let plain = Material::plain();
+        Material {
+            ambient: material.ambient.map(Vec3::from).unwrap_or(plain.ambient),
+            diffuse: material.diffuse.map(Vec3::from).unwrap_or(plain.diffuse),
+            specular: material.specular.map(Vec3::from).unwrap_or(plain.specular),
+            // Asset Forge exports leave Ns out
+            shininess: material.shininess.filter(|s| *s > 0.0).unwrap_or(plain.shininess),
+        }
+    }
+
+    pub fn get_uniforms(&self) -> [Uniform; 4] {
+        let ids = &*BUILTIN_UNIFORMS;
+        [
+            Uniform::Vec3(ids.material_ambient, self.ambient),
+            Uniform::Vec3(ids.material_diffuse, self.diffuse),
+            Uniform::Vec3(ids.material_specular, self.specular),
+            Uniform::Float(ids.material_shininess, self.shininess),
+        ]
+    }
+}
@@ -1,79 +1,68 @@
-use std::collections::HashMap;
-
-use gl::types::GLuint;
-
+use crate::graphics::gl_resource::GlObject;
 use crate::graphics::program::{ShaderProgram, Uniform};
-use crate::registry::texture_registry::{TEXTURE_REGISTRY, TextureId};
+use crate::graphics::texture::Texture;
+use crate::graphics::vertex_layout::{AttributeKind, VertexLayout};
+use crate::registry::asset_store::{AssetMemory, Handle};
 
 pub struct Mesh {
-    vao_id: GLuint,
-    vbo_id: GLuint,
-    ebo_id: GLuint,
+    vao: GlObject,
+    // Only held so the buffers the VAO references are deleted with the mesh
+    _vbo: GlObject,
+    _ebo: GlObject,
 
-    vertices: Vec<f32>,
     indices: Vec<u32>,
 
     // TODO make below easier to work with
-    program: ShaderProgram,
-    textures: HashMap<u32, TextureId>
+    program: Handle<ShaderProgram>,
+    // Bound to the unit of their index
+    textures: Vec<Handle<Texture>>,
+    // Vertex and index buffers
+    memory_size: usize,
 }
 
 impl Mesh {
 
+    // Vertices are interleaved as described by the layout, any plain data type can be used
+    // as long as its size is a multiple of the layout stride (f32s, #[repr(C)] structs...)
     // TODO exand for more textures
-    pub fn new(vbo: Vec<f32>, ebo: Vec<u32>, program: ShaderProgram, texture_id: Option<TextureId>) -> Self {
-        let mut vao_id = 0;
+    pub fn new<V: Copy>(vbo: Vec<V>, ebo: Vec<u32>, layout: VertexLayout, program: Handle<ShaderProgram>, texture: Option<Handle<Texture>>) -> Self {
+        let vbo_size = vbo.len() * std::mem::size_of::<V>();
+        if layout.stride() == 0 || !vbo_size.is_multiple_of(layout.stride()) {
+            panic!("Vertex buffer of {} bytes does not match layout stride of {} bytes", vbo_size, layout.stride());
+        }
+
+        // Position only meshes are skies, their shaders sample the texture by direction
+        if texture.is_some() && !layout.has(AttributeKind::Uv) && layout != VertexLayout::position() {
+            panic!("Textured mesh requires a vertex layout with uvs");
+        }
 
         // Create VAO
+        let vao = GlObject::vertex_array();
         unsafe {
-            gl::GenVertexArrays(1, &mut vao_id);
-            gl::BindVertexArray(vao_id);
+            gl::BindVertexArray(vao.id());
         }
 
 
         // Create VBO
-        let mut vbo_id = 0;
+        let vbo_object = GlObject::buffer();
         unsafe {
-            // Create vbo
-            gl::GenBuffers(1, &mut vbo_id);
-            gl::BindBuffer(gl::ARRAY_BUFFER, vbo_id);
-            gl::BufferData(gl::ARRAY_BUFFER, (vbo.len() * std::mem::size_of::<f32>()) as isize, vbo.as_ptr().cast(), gl::STATIC_DRAW);
-
-
-            // Create vertex attribute arrays
-            let attrib_index = 0;
-            // attribute index, number of components per attribute, type, is normalized, size of stride, offset
-            gl::VertexAttribPointer(attrib_index, 3, gl::FLOAT, gl::FALSE, (5 * std::mem::size_of::<f32>()) as i32, 0 as *const _);
-            gl::EnableVertexAttribArray(attrib_index);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo_object.id());
+            gl::BufferData(gl::ARRAY_BUFFER, vbo_size as isize, vbo.as_ptr().cast(), gl::STATIC_DRAW);
         }
 
+        // Create vertex attribute arrays
+        layout.configure();
+
 
         // Create EBO
-        let mut ebo_id = 0;
+        let ebo_size = ebo.len() * std::mem::size_of::<u32>();
+        let ebo_object = GlObject::buffer();
         unsafe {
-            gl::GenBuffers(1, &mut ebo_id);
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo_id);
-            gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, (ebo.len() * std::mem::size_of::<u32>()) as isize, ebo.as_ptr().cast(), gl::STATIC_DRAW);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo_object.id());
+            gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, ebo_size as isize, ebo.as_ptr().cast(), gl::STATIC_DRAW);
         }
 
 
-        let mut texture_unit_to_texture_id = HashMap::new();
-
-        // Create texture if needed!
-        match texture_id {
-            Some(textureId) => {
-                // Configure attribute for texture coordinates
-                unsafe {
-                    // attribute index, number of components per attribute, type, is normalized, size of stride, offset
-                    gl::VertexAttribPointer(1, 2, gl::FLOAT, gl::FALSE, (5 * std::mem::size_of::<f32>()) as i32, (3 * std::mem::size_of::<f32>()) as *const _);
-                    gl::EnableVertexAttribArray(1);
-                    texture_unit_to_texture_id.insert(0, textureId);
-                }
-            }
-            _ => {}
-        };
-
-
         unsafe {
             // Unbind vao
             gl::BindVertexArray(0);
@@ -84,50 +73,43 @@ impl Mesh {
 
 
         Self {
-            vao_id,
-            vbo_id: vbo_id,
-            ebo_id: ebo_id,
-            vertices: vbo,
+            vao,
+            _vbo: vbo_object,
+            _ebo: ebo_object,
             indices: ebo,
             program,
-            textures: texture_unit_to_texture_id
+            textures: texture.into_iter().collect(),
+            memory_size: vbo_size + ebo_size,
         }
     }
 
+    // Draws with the textures given at construction
     pub fn draw(&self, uniforms: &[Uniform]) {
-        unsafe {
+        // Bind textures to texture units
+        for (texture_unit, texture) in self.textures.iter().enumerate() {
+            texture.bind(texture_unit as u32);
+        }
+        self.draw_elements(uniforms);
+    }
 
-            // Bind textures to texture units
-            for (texture_unit, texture_id) in &self.textures {
-                TEXTURE_REGISTRY.read()
-                    .expect("Texture registry lock poisoned")
-                    .get(texture_id)
-                    .expect("Texture not found")
-                    .bind(*texture_unit);
-            }
+    // Draws with other textures for this call, bound to the unit of their index
+    pub fn draw_with_textures(&self, uniforms: &[Uniform], textures: &[&Texture]) {
+        for (texture_unit, texture) in textures.iter().enumerate() {
+            texture.bind(texture_unit as u32);
+        }
+        self.draw_elements(uniforms);
+    }
+
+    fn draw_elements(&self, uniforms: &[Uniform]) {
+        unsafe {
 
             // Bind program and VAO
             self.program.use_program();
-            gl::BindVertexArray(self.vao_id);
+            gl::BindVertexArray(self.vao.id());
 
 
             // Set uniforms
-            for uniform in uniforms {
-                match uniform {
-                    Uniform::Matrix4f(name, matrix) => {
-                        self.program.set_uniform_matrix_4f(name, matrix);
-                    }
-                    Uniform::Vec4(name, vec4) => {
-                        self.program.set_uniform_vec4(name, vec4);
-                    }
-                    Uniform::Int(name, value) => {
-                        self.program.set_uniform_int(name, *value);
-                    },
-                    Uniform::Float(name, value) => {
-                        self.program.set_uniform_float(name, *value);
-                    }
-                }
-            }
+            self.program.set_uniforms(uniforms);
 
 
             // Draw mesh
@@ -138,4 +120,10 @@ impl Mesh {
             gl::UseProgram(0);
         }
     }
-}
\ No newline at end of file
+}
+
+impl AssetMemory for Mesh {
+    fn get_memory_size(&self) -> usize {
+        self.memory_size
+    }
+}
@@ -0,0 +1,145 @@
+use std::path::Path;
+
+use glam::Vec3;
+
+use crate::assets::vfs::Vfs;
+use crate::graphics::material::Material;
+use crate::graphics::mesh::Mesh;
+use crate::graphics::program::{ShaderProgram, Uniform};
+use crate::graphics::vertex_layout::VertexLayout;
+use crate::registry::asset_store::{AssetMemory, Handle};
+
+// One OBJ object, drawn with a single material
+pub struct ModelPart {
+    pub mesh: Mesh,
+    pub material: Material,
+}
+
+pub struct Model {
+    pub parts: Vec<ModelPart>,
+}
+
+// CPU side data of a model part, vertices use the position_uv_normal layout
+pub struct ModelGeometry {
+    pub vertices: Vec<f32>,
+    pub indices: Vec<u32>,
+    pub material: Material,
+}
+
+impl Model {
+    // Loads an OBJ file and its MTL materials, vertices use the position_uv_normal layout
+    pub fn load(vfs: &Vfs, path: impl AsRef<Path>, program: Handle<ShaderProgram>) -> Self {
+        Model::upload(Model::load_geometry(vfs, path), program)
+    }
+
+    // Geometry can be read on any thread, the upload needs the GL thread
+    pub fn upload(geometry: Vec<ModelGeometry>, program: Handle<ShaderProgram>) -> Self {
+        let parts = geometry
+            .into_iter()
+            .map(|geometry| ModelPart {
+                mesh: Mesh::new(geometry.vertices,
+                                geometry.indices,
+                                VertexLayout::position_uv_normal(),
+                                program.clone(),
+                                None),
+                material: geometry.material,
+            })
+            .collect();
+
+        Model {
+            parts
+        }
+    }
+
+    // Reads an OBJ file from the VFS without uploading it, e.g. for the software renderer
+    pub fn load_geometry(vfs: &Vfs, path: impl AsRef<Path>) -> Vec<ModelGeometry> {
+        let path = path.as_ref();
+        let obj = vfs.read(path).unwrap_or_else(|e| panic!("{}", e));
+
+        // MTL files are next to the OBJ
+        let folder = path.parent().unwrap_or(Path::new(""));
+        let (models, materials_result) = tobj::load_obj_buf(&mut obj.as_slice(), &tobj::GPU_LOAD_OPTIONS, |mtl_path| {
+            let mtl = vfs.read(folder.join(mtl_path)).map_err(|_| tobj::LoadError::OpenFileFailed)?;
+            tobj::load_mtl_buf(&mut mtl.as_slice())
+        })
+            .unwrap_or_else(|e| panic!("Failed to load model {:?}: {}", path, e));
+
+        let materials = materials_result.unwrap_or_else(|e| {
+            println!("Failed to load materials for model {:?}: {}", path, e);
+            Vec::new()
+        });
+
+        models.iter()
+            .filter(|model| !model.mesh.indices.is_empty())
+            .map(|model| {
+                let material = model.mesh.material_id
+                    .and_then(|id| materials.get(id))
+                    .map(Material::from_mtl)
+                    .unwrap_or_else(Material::plain);
+
+                ModelGeometry {
+                    vertices: Model::build_vbo(&model.mesh),
+                    indices: model.mesh.indices.clone(),
+                    material,
+                }
+            })
+            .collect()
+    }
+
+    // Interleave into x, y, z, u, v, nx, ny, nz
+    fn build_vbo(mesh: &tobj::Mesh) -> Vec<f32> {
+        let vertex_count = mesh.positions.len() / 3;
+        let normals = if mesh.normals.len() == mesh.positions.len() {
+            mesh.normals.clone()
+        } else {
+            Model::calculate_normals(mesh)
+        };
+
+        let mut vbo = Vec::with_capacity(vertex_count * 8);
+        for i in 0..vertex_count {
+            vbo.extend_from_slice(&mesh.positions[i * 3..i * 3 + 3]);
+            if mesh.texcoords.len() >= (i + 1) * 2 {
+                vbo.extend_from_slice(&mesh.texcoords[i * 2..i * 2 + 2]);
+            } else {
+                vbo.extend([0.0, 0.0]);
+            }
+            vbo.extend_from_slice(&normals[i * 3..i * 3 + 3]);
+        }
+        vbo
+    }
+
+    // Smooth normals from the average of the adjacent faces
+    fn calculate_normals(mesh: &tobj::Mesh) -> Vec<f32> {
+        let position = |i: u32| {
+            let i = i as usize * 3;
+            Vec3::new(mesh.positions[i], mesh.positions[i + 1], mesh.positions[i + 2])
+        };
+
+        let mut normals = vec![Vec3::ZERO; mesh.positions.len() / 3];
+        for face in mesh.indices.chunks_exact(3) {
+            let normal = (position(face[1]) - position(face[0])).cross(position(face[2]) - position(face[0]));
+            for index in face {
+                normals[*index as usize] += normal;
+            }
+        }
+
+        normals.iter()
+            .flat_map(|n| n.normalize_or(Vec3::Y).to_array())
+            .collect()
+    }
+
+    // Uniforms are shared by every part, material uniforms are added per part
+    pub fn draw(&self, uniforms: &[Uniform]) {
+        for part in &self.parts {
+            let mut part_uniforms = part.material.get_uniforms().to_vec();
+            part_uniforms.extend_from_slice(uniforms);
+            part.mesh.draw(&part_uniforms);
+        }
+    }
+}
+
+impl AssetMemory for Model {
+    fn get_memory_size(&self) -> usize {
+        self.parts.iter().map(|part| part.mesh.get_memory_size()).sum()
+    }
+}
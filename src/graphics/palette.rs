@@ -0,0 +1,162 @@
+use gl::types::{GLenum, GLint};
+
+use crate::graphics::gl_resource::GlObject;
+
+pub const PALETTE_SIZE: usize = 256;
+// Size of a PLAYPAL palette in bytes, the lump holds 14 of them
+pub const PLAYPAL_PALETTE_BYTES: usize = PALETTE_SIZE * 3;
+
+// 256 color palette, like Doom's PLAYPAL
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Palette {
+    pub colors: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    // Reads palette `index` from a PLAYPAL lump
+    pub fn from_playpal(lump: &[u8], index: usize) -> Palette {
+        let start = index * PLAYPAL_PALETTE_BYTES;
+        let end = start + PLAYPAL_PALETTE_BYTES;
+        if lump.len() < end {
+            panic!("PLAYPAL lump too small for palette {}: {} bytes", index, lump.len());
+        }
+
+        Palette {
+            colors: lump[start..end]
+                .chunks_exact(3)
+                .map(|c| [c[0], c[1], c[2]])
+                .collect()
+        }
+    }
+
+    // Stand-in until a WAD is loaded, a 6x7x6 color cube followed by a gray ramp
+    pub fn fallback() -> Palette {
+        let mut colors = Vec::with_capacity(PALETTE_SIZE);
+        for r in 0..6 {
+            for g in 0..7 {
+                for b in 0..6 {
+                    colors.push([(r * 255 / 5) as u8, (g * 255 / 6) as u8, (b * 255 / 5) as u8]);
+                }
+            }
+        }
+
+        let grays = PALETTE_SIZE - colors.len();
+        for i in 0..grays {
+            let value = (i * 255 / (grays - 1)) as u8;
+            colors.push([value, value, value]);
+        }
+
+        Palette {
+            colors
+        }
+    }
+
+    // Index of the palette color closest to the given color
+    pub fn nearest(&self, color: [u8; 3]) -> usize {
+        let distance = |c: &[u8; 3]| {
+            let dr = c[0] as i32 - color[0] as i32;
+            let dg = c[1] as i32 - color[1] as i32;
+            let db = c[2] as i32 - color[2] as i32;
+            dr * dr + dg * dg + db * db
+        };
+
+        self.colors.iter()
+            .enumerate()
+            .min_by_key(|(_, c)| distance(c))
+            .map(|(i, _)| i)
+            .unwrap_or(0)
+    }
+
+    // size^3 RGB lookup table, maps any color to its nearest palette color
+    pub fn build_lut(&self, size: usize) -> Vec<u8> {
+        let step = |i: usize| (i * 255 / (size - 1)) as u8;
+
+        let mut lut = Vec::with_capacity(size * size * size * 3);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let nearest = self.colors[self.nearest([step(r), step(g), step(b)])];
+                    lut.extend_from_slice(&nearest);
+                }
+            }
+        }
+        lut
+    }
+
+    // size^3 lookup table of palette indices, used to turn colors back into indexed color
+    pub fn build_index_lut(&self, size: usize) -> Vec<u8> {
+        let step = |i: usize| (i * 255 / (size - 1)) as u8;
+
+        let mut lut = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    lut.push(self.nearest([step(r), step(g), step(b)]) as u8);
+                }
+            }
+        }
+        lut
+    }
+
+    // Uploads the lookup table as a 3D texture, sampled with the color as coordinate
+    pub fn create_lut_texture(&self, size: usize) -> GlObject {
+        create_lut_texture(size, gl::RGB8, gl::RGB, &self.build_lut(size))
+    }
+
+    // Same as the color lookup table, but the red channel holds the palette index
+    pub fn create_index_lut_texture(&self, size: usize) -> GlObject {
+        create_lut_texture(size, gl::R8, gl::RED, &self.build_index_lut(size))
+    }
+
+    // 256x1 texture of the palette colors
+    pub fn create_texture(&self) -> GlObject {
+        let data: Vec<u8> = self.colors.iter().flatten().copied().collect();
+
+        let texture = GlObject::texture();
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, texture.id());
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexImage2D(gl::TEXTURE_2D,
+                           0,
+                           gl::RGB8 as i32,
+                           self.colors.len() as i32,
+                           1,
+                           0,
+                           gl::RGB,
+                           gl::UNSIGNED_BYTE,
+                           data.as_ptr().cast());
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+        texture
+    }
+}
+
+fn create_lut_texture(size: usize, internal_format: GLenum, format: GLenum, lut: &[u8]) -> GlObject {
+    let texture = GlObject::texture();
+    unsafe {
+        gl::BindTexture(gl::TEXTURE_3D, texture.id());
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        gl::TexImage3D(gl::TEXTURE_3D,
+                       0,
+                       internal_format as i32,
+                       size as i32,
+                       size as i32,
+                       size as i32,
+                       0,
+                       format,
+                       gl::UNSIGNED_BYTE,
+                       lut.as_ptr().cast());
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as GLint);
+        gl::BindTexture(gl::TEXTURE_3D, 0);
+    }
+    texture
+}
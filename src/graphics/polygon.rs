@@ -5,6 +5,8 @@ use ordered_float::NotNan;
 
 use crate::graphics::mesh::Mesh;
 use crate::graphics::program::ShaderProgram;
+use crate::graphics::vertex_layout::VertexLayout;
+use crate::registry::asset_store::Handle;
 
 pub struct Polygon {
     pub mesh: Mesh,
@@ -14,18 +16,14 @@ pub struct Polygon {
 impl Polygon {
     // Assume vertices are in counter-clockwise order
     // TODO sort vertices in counter-clockwise order if needed
-    pub fn new(vertices: &[Vec3], program: ShaderProgram) -> Self {
+    pub fn new(vertices: &[Vec3], program: Handle<ShaderProgram>) -> Self {
         if vertices.len() < 3 {
             panic!("Polygon must have at least 3 vertices");
         }
 
         // Build VBO
         let vbo: Vec<f32> = vertices.iter()
-            .flat_map(|v| {
-                let mut temp = Vec::from(v.to_array());
-                temp.extend([0.0, 0.0]);
-                temp
-            })
+            .flat_map(|v| v.to_array())
             .collect();
 
         // Build EBO
@@ -38,7 +36,7 @@ impl Polygon {
         }
 
         Polygon {
-            mesh: Mesh::new(vbo, ebo, program, None),
+            mesh: Mesh::new(vbo, ebo, VertexLayout::position(), program, None),
             hash: Polygon::get_vertex_list_hash(vertices),
         }
     }
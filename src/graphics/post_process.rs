@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use glam::{UVec2, Vec2, Vec3};
+
+use crate::graphics::display::Viewport;
+use crate::graphics::gl_resource::GlObject;
+use crate::graphics::mesh::Mesh;
+use crate::graphics::palette::Palette;
+use crate::graphics::program::{Uniform, UniformId};
+use crate::graphics::render_target::RenderTarget;
+use crate::graphics::texture::TextureType;
+use crate::registry::Assets;
+use crate::registry::mesh_registry::MeshShape;
+
+// Resolution of the palette lookup table per color channel
+const PALETTE_LUT_SIZE: usize = 32;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum PostEffectKind {
+    Gamma,
+    Palette,
+    DamageTint,
+    Crt,
+}
+
+impl PostEffectKind {
+    pub fn all() -> [PostEffectKind; 4] {
+        [PostEffectKind::Gamma, PostEffectKind::Palette, PostEffectKind::DamageTint, PostEffectKind::Crt]
+    }
+
+    // Program in the asset manifest
+    fn get_program_key(&self) -> &'static str {
+        match self {
+            PostEffectKind::Gamma => "post_gamma",
+            PostEffectKind::Palette => "post_palette",
+            PostEffectKind::DamageTint => "post_damage",
+            PostEffectKind::Crt => "post_crt",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PostEffect {
+    Gamma {
+        gamma: f32,
+    },
+    // Snap every color to the nearest palette color
+    Palette,
+    // Flash the screen when hurt, like Doom's red palettes
+    DamageTint {
+        color: Vec3,
+        strength: f32,
+    },
+    Crt {
+        scanline_intensity: f32,
+        curvature: f32,
+    },
+}
+
+impl PostEffect {
+    pub fn get_kind(&self) -> PostEffectKind {
+        match self {
+            PostEffect::Gamma { .. } => PostEffectKind::Gamma,
+            PostEffect::Palette => PostEffectKind::Palette,
+            PostEffect::DamageTint { .. } => PostEffectKind::DamageTint,
+            PostEffect::Crt { .. } => PostEffectKind::Crt,
+        }
+    }
+
+    // Effects at their neutral settings leave the image as it is, their pass is skipped
+    pub fn is_visible(&self) -> bool {
+        match self {
+            PostEffect::Gamma { gamma } => *gamma != 1.0,
+            PostEffect::DamageTint { strength, .. } => *strength > 0.0,
+            PostEffect::Palette | PostEffect::Crt { .. } => true,
+        }
+    }
+
+    fn get_uniforms(&self, resolution: UVec2) -> Vec<Uniform> {
+        let mut uniforms = vec![Uniform::Sampler(UniformId::get("screen"), 0)];
+        match self {
+            PostEffect::Gamma { gamma } => {
+                uniforms.push(Uniform::Float(UniformId::get("gamma"), gamma.max(0.01)));
+            }
+            PostEffect::Palette => {
+                uniforms.push(Uniform::Sampler(UniformId::get("paletteLut"), 1));
+                uniforms.push(Uniform::Float(UniformId::get("lutSize"), PALETTE_LUT_SIZE as f32));
+            }
+            PostEffect::DamageTint { color, strength } => {
+                uniforms.push(Uniform::Vec3(UniformId::get("tintColor"), *color));
+                uniforms.push(Uniform::Float(UniformId::get("strength"), *strength));
+            }
+            PostEffect::Crt { scanline_intensity, curvature } => {
+                let resolution = Vec2::new(resolution.x as f32, resolution.y as f32);
+                uniforms.push(Uniform::Vec2(UniformId::get("resolution"), resolution));
+                uniforms.push(Uniform::Float(UniformId::get("scanlineIntensity"), *scanline_intensity));
+                uniforms.push(Uniform::Float(UniformId::get("curvature"), *curvature));
+            }
+        }
+        uniforms
+    }
+}
+
+// Fullscreen quad drawn with a program from the asset manifest
+fn create_quad(assets: &Assets, program_key: &str) -> Mesh {
+    let program = assets.get_program(assets.manifest.get_program_id(program_key));
+
+    let shape = MeshShape::Quad;
+    Mesh::new(shape.get_vbo(), shape.get_ebo(), shape.get_layout(), program, None)
+}
+
+struct PostPass {
+    effect: PostEffect,
+    enabled: bool,
+}
+
+// Fullscreen shader passes run in order over the rendered scene, the last one draws to the window
+pub struct PostProcessChain {
+    passes: Vec<PostPass>,
+
+    // Fullscreen quad for every effect, and one that only copies
+    quads: HashMap<PostEffectKind, Mesh>,
+    copy_quad: Mesh,
+    // Passes ping-pong between these
+    targets: [RenderTarget; 2],
+    palette_lut: GlObject,
+}
+
+impl PostProcessChain {
+    pub fn new(size: UVec2, assets: &Assets) -> PostProcessChain {
+        let mut quads = HashMap::new();
+        for kind in PostEffectKind::all() {
+            quads.insert(kind, create_quad(assets, kind.get_program_key()));
+        }
+
+        PostProcessChain {
+            passes: Vec::new(),
+            quads,
+            copy_quad: create_quad(assets, "post_copy"),
+            targets: [
+                RenderTarget::new(size.x, size.y, TextureType::SPRITE, false),
+                RenderTarget::new(size.x, size.y, TextureType::SPRITE, false),
+            ],
+            palette_lut: Palette::fallback().create_lut_texture(PALETTE_LUT_SIZE),
+        }
+    }
+
+    // Adds an enabled pass at the end of the chain, replacing a pass of the same kind
+    pub fn push(&mut self, effect: PostEffect) {
+        self.remove(effect.get_kind());
+        self.passes.push(PostPass {
+            effect,
+            enabled: true,
+        });
+    }
+
+    pub fn remove(&mut self, kind: PostEffectKind) {
+        self.passes.retain(|pass| pass.effect.get_kind() != kind);
+    }
+
+    pub fn set_enabled(&mut self, kind: PostEffectKind, enabled: bool) {
+        if let Some(pass) = self.passes.iter_mut().find(|pass| pass.effect.get_kind() == kind) {
+            pass.enabled = enabled;
+        }
+    }
+
+    pub fn is_enabled(&self, kind: PostEffectKind) -> bool {
+        self.passes.iter().any(|pass| pass.effect.get_kind() == kind && pass.enabled)
+    }
+
+    pub fn toggle(&mut self, kind: PostEffectKind) {
+        self.set_enabled(kind, !self.is_enabled(kind));
+    }
+
+    pub fn set_palette(&mut self, palette: &Palette) {
+        self.palette_lut = palette.create_lut_texture(PALETTE_LUT_SIZE);
+    }
+
+    // Passes run at the source resolution
+    pub fn resize(&mut self, width: u32, height: u32) {
+        for target in &mut self.targets {
+            target.resize(width, height);
+        }
+    }
+
+    // Runs all enabled passes over the source and draws the result to the viewport of the
+    // destination, or of the window when there is none
+    pub fn apply(&self, source: &RenderTarget, destination: Option<&RenderTarget>, output: Viewport) {
+        let bind_output = || match destination {
+            Some(destination) => destination.bind_viewport(output),
+            None => RenderTarget::bind_default(output),
+        };
+
+        let effects: Vec<&PostEffect> = self.passes.iter()
+            .filter(|pass| pass.enabled && pass.effect.is_visible())
+            .map(|pass| &pass.effect)
+            .collect();
+
+        unsafe {
+            gl::Disable(gl::DEPTH_TEST);
+        }
+
+        if effects.is_empty() {
+            bind_output();
+            source.bind_color(0);
+            self.copy_quad.draw(&[Uniform::Sampler(UniformId::get("screen"), 0)]);
+        }
+
+        let mut input = source;
+        for (i, effect) in effects.iter().enumerate() {
+            let target = &self.targets[i % 2];
+            if i == effects.len() - 1 {
+                bind_output();
+            } else {
+                target.bind();
+            }
+
+            input.bind_color(0);
+            if **effect == PostEffect::Palette {
+                unsafe {
+                    gl::ActiveTexture(gl::TEXTURE1);
+                    gl::BindTexture(gl::TEXTURE_3D, self.palette_lut.id());
+                }
+            }
+
+            self.quads[&effect.get_kind()].draw(&effect.get_uniforms(input.size));
+            input = target;
+        }
+
+        unsafe {
+            gl::Enable(gl::DEPTH_TEST);
+        }
+    }
+}
@@ -1,149 +1,275 @@
+use std::collections::{HashMap, HashSet};
 use std::ffi::CString;
 use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::SystemTime;
 
-use glfw::Error;
+use gl::types::GLuint;
 
+use crate::assets::shader_loader::ShaderDefine;
+use crate::assets::vfs::Vfs;
+use crate::graphics::gl_resource::GlObject;
+use crate::graphics::frame_uniforms::{FRAME_BLOCK_BINDING, FRAME_BLOCK_NAME};
 pub use crate::graphics::shader::{Shader, ShaderType};
-use crate::registry::shader_registry::ShaderId;
+pub use crate::graphics::shader_error::ShaderError;
+pub use crate::graphics::uniform::{BUILTIN_UNIFORMS, Uniform, UniformId};
+use crate::graphics::uniform::UniformInfo;
+use crate::registry::asset_store::AssetMemory;
 
-#[derive(Clone, PartialEq, Eq, Hash)]
-pub struct ShaderProgram {
-    id: u32,
+// Program object and the shaders linked into it, replaced as a whole on reload
+struct LinkedProgram {
+    program: GlObject,
+    // Reflected when linking
+    uniforms: HashMap<UniformId, UniformInfo>,
+    // Deleted together with the program
     vertex_shader: Shader,
     fragment_shader: Shader,
 }
 
-pub enum Uniform {
-    Matrix4f(String, glam::Mat4),
-    Vec4(String, glam::Vec4),
-    Int(String, i32),
-    Float(String, f32),
-}
-
-impl ShaderProgram {
-    pub fn new(shader_folder_path: impl AsRef<Path>, vertex_shader_id: ShaderId, fragment_shader_id: ShaderId) -> Self {
-        let folder_path = shader_folder_path.as_ref();
-        if !folder_path.exists() {
-            panic!("Shader folder not found: {:?}", folder_path);
-        }
-
-        let program_id = unsafe {
-            gl::CreateProgram()
-        };
-
-        let vertex_shader = Shader::new(ShaderType::Vertex, Self::resolve_shader_path(folder_path, vertex_shader_id));
-        let fragment_shader = Shader::new(ShaderType::Fragment, Self::resolve_shader_path(folder_path, fragment_shader_id));
+impl LinkedProgram {
+    fn load(vfs: &Vfs, root: &Path, vertex_path: &Path, fragment_path: &Path, defines: &[ShaderDefine]) -> Result<Self, ShaderError> {
+        let vertex_shader = Shader::new(vfs, ShaderType::Vertex, root, vertex_path, defines)?;
+        let fragment_shader = Shader::new(vfs, ShaderType::Fragment, root, fragment_path, defines)?;
 
-        Self {
-            id: program_id,
+        Ok(Self {
+            program: GlObject::program(),
+            uniforms: HashMap::new(),
             vertex_shader,
             fragment_shader,
-        }
+        })
     }
 
-    fn resolve_shader_path(shader_folder_path: impl AsRef<Path>, shader_id: ShaderId) -> PathBuf {
-        let mut folder_path = shader_folder_path.as_ref().to_path_buf();
-        folder_path.push(shader_id.get_path());
-        println!("Resolved shader path: {:?}", folder_path);
-        return folder_path;
-    }
-
-    pub fn build(&self) -> Result<(), Error> {
+    fn link(&mut self) -> Result<(), ShaderError> {
         // Attach vertex shader
         self.vertex_shader.compile()?;
         unsafe {
-            gl::AttachShader(self.id, self.vertex_shader.id);
+            gl::AttachShader(self.program.id(), self.vertex_shader.object.id());
         }
 
         // Attach fragment shader
         self.fragment_shader.compile()?;
         unsafe {
-            gl::AttachShader(self.id, self.fragment_shader.id);
+            gl::AttachShader(self.program.id(), self.fragment_shader.object.id());
         }
 
         // Link program
         unsafe {
-            gl::LinkProgram(self.id);
+            gl::LinkProgram(self.program.id());
         }
 
         let mut link_status = 0;
         unsafe {
-            gl::GetProgramiv(self.id, gl::LINK_STATUS, &mut link_status);
+            gl::GetProgramiv(self.program.id(), gl::LINK_STATUS, &mut link_status);
         }
 
         if link_status == 0 {
-            unsafe {
+            let log = unsafe {
                 let mut log_length: i32 = 0;
-                gl::GetProgramiv(self.id, gl::INFO_LOG_LENGTH, &mut log_length);
+                gl::GetProgramiv(self.program.id(), gl::INFO_LOG_LENGTH, &mut log_length);
 
                 if log_length > 0 {
                     let mut log: Vec<u8> = Vec::with_capacity(log_length as usize);
-                    gl::GetProgramInfoLog(self.id,
+                    gl::GetProgramInfoLog(self.program.id(),
                                           log_length,
                                           std::ptr::null_mut(),
                                           log.as_mut_ptr().cast());
                     log.set_len(log_length as usize);
-                    let error_log = String::from_utf8_lossy(&log);
-                    panic!("Program Link Error: {}", error_log);
+                    String::from_utf8_lossy(&log).trim_end_matches('\0').to_string()
+                } else {
+                    "Unknown error".to_string()
                 }
-            }
-            panic!("Program Link Error");
+            };
+            return Err(ShaderError::Link {
+                vertex_path: self.vertex_shader.path.clone(),
+                fragment_path: self.fragment_shader.path.clone(),
+                log,
+            });
         }
 
         // Clean up shaders
         unsafe {
-            gl::DetachShader(self.id, self.vertex_shader.id);
-            gl::DetachShader(self.id, self.fragment_shader.id);
+            gl::DetachShader(self.program.id(), self.vertex_shader.object.id());
+            gl::DetachShader(self.program.id(), self.fragment_shader.object.id());
         }
-        self.vertex_shader.delete();
-        self.fragment_shader.delete();
-        Ok(())
-    }
 
-    pub fn use_program(&self) {
+        self.uniforms = reflect_uniforms(self.program.id());
+
+        // Per frame data comes from the shared uniform buffer
         unsafe {
-            gl::UseProgram(self.id);
+            let block_name = CString::new(FRAME_BLOCK_NAME).unwrap();
+            let block_index = gl::GetUniformBlockIndex(self.program.id(), block_name.as_ptr());
+            if block_index != gl::INVALID_INDEX {
+                gl::UniformBlockBinding(self.program.id(), block_index, FRAME_BLOCK_BINDING);
+            }
         }
+        Ok(())
     }
 
-    pub fn set_uniform_matrix_4f(&self, name: &str, matrix: &glam::Mat4) {
-        let location = self.check_uniform(name);
-        unsafe {
-            gl::UniformMatrix4fv(location, 1, gl::FALSE, matrix.to_cols_array().as_ptr());
-        }
+    // Source files of both shaders with their current modification times.
+    // None when the file is missing, e.g. while an editor replaces it, and for files in archives.
+    fn get_watched_files(&self, vfs: &Vfs) -> Vec<(PathBuf, Option<SystemTime>)> {
+        self.vertex_shader.files.iter()
+            .chain(self.fragment_shader.files.iter())
+            .map(|path| (path.clone(), vfs.get_modified(path)))
+            .collect()
     }
+}
 
-    pub fn set_uniform_vec4(&self, name: &str, vec4: &glam::Vec4) {
-        let location = self.check_uniform(name);
-        unsafe {
-            gl::Uniform4fv(location, 1, vec4.as_ref().as_ptr());
+// Not Clone, share it through a Handle. Meshes keep their handle across reloads.
+pub struct ShaderProgram {
+    root: PathBuf,
+    vertex_path: PathBuf,
+    fragment_path: PathBuf,
+    defines: Vec<ShaderDefine>,
+    linked: RwLock<LinkedProgram>,
+    // Source files including includes, with their modification times at the last (re)load
+    watched_files: Mutex<Vec<(PathBuf, Option<SystemTime>)>>,
+    // Uniforms already warned about, so a missing or mismatched one is only logged once
+    warned_uniforms: Mutex<HashSet<UniformId>>,
+}
+
+impl ShaderProgram {
+    // Includes resolve relative to the shader folder, the defines select a variant of the shader files
+    pub fn new(vfs: &Vfs, shader_folder_path: impl AsRef<Path>, vertex_path: impl AsRef<Path>, fragment_path: impl AsRef<Path>, defines: &[ShaderDefine]) -> Result<Self, ShaderError> {
+        let folder_path = shader_folder_path.as_ref();
+        if !vfs.is_dir(folder_path) {
+            panic!("Shader folder not found: {:?}", folder_path);
         }
+
+        let vertex_path = vertex_path.as_ref().to_path_buf();
+        let fragment_path = fragment_path.as_ref().to_path_buf();
+        let linked = LinkedProgram::load(vfs, folder_path, &vertex_path, &fragment_path, defines)?;
+
+        Ok(Self {
+            root: folder_path.to_path_buf(),
+            vertex_path,
+            fragment_path,
+            defines: defines.to_vec(),
+            watched_files: Mutex::new(linked.get_watched_files(vfs)),
+            linked: RwLock::new(linked),
+            warned_uniforms: Mutex::new(HashSet::new()),
+        })
     }
 
-    pub fn set_uniform_int(&self, name: &str, value: i32) {
-        let location = self.check_uniform(name);
-        unsafe {
-            gl::Uniform1i(location, value);
+    pub fn build(&self) -> Result<(), ShaderError> {
+        self.linked.write().unwrap().link()
+    }
+
+    // Recompiles when a source file changed since the last attempt, None if nothing changed.
+    // A failed reload keeps the old program and is not retried until the file changes again.
+    pub fn reload_if_changed(&self, vfs: &Vfs) -> Option<Result<(), ShaderError>> {
+        {
+            let mut watched_files = self.watched_files.lock().unwrap();
+            let mut changed = false;
+            for (path, modified) in watched_files.iter_mut() {
+                let current = vfs.get_modified(path);
+                if *modified != current {
+                    *modified = current;
+                    changed = true;
+                }
+            }
+            if !changed {
+                return None;
+            }
         }
+
+        let result = LinkedProgram::load(vfs, &self.root, &self.vertex_path, &self.fragment_path, &self.defines)
+            .and_then(|mut linked| linked.link().map(|_| linked));
+        Some(result.map(|linked| {
+            // Includes may have been added or removed
+            *self.watched_files.lock().unwrap() = linked.get_watched_files(vfs);
+            *self.linked.write().unwrap() = linked;
+            self.warned_uniforms.lock().unwrap().clear();
+        }))
     }
 
-    pub fn set_uniform_float(&self, name: &str, value: f32) {
-        let location = self.check_uniform(name);
+    pub fn use_program(&self) {
         unsafe {
-            gl::Uniform1f(location, value);
+            gl::UseProgram(self.linked.read().unwrap().program.id());
         }
     }
 
+    // Sets uniforms of the program in use by their cached locations
+    pub fn set_uniforms(&self, uniforms: &[Uniform]) {
+        let linked = self.linked.read().unwrap();
+        for uniform in uniforms {
+            if let Some(info) = self.check_uniform(&linked, uniform) {
+                uniform.set(info.location);
+            }
+        }
+    }
 
-    fn check_uniform(&self, name: &str) -> i32 {
-        let location = unsafe {
-            gl::GetUniformLocation(self.id, CString::new(name).unwrap().as_ptr())
+    // Missing uniforms are usually optimized out by the driver, they are skipped with a warning.
+    // Debug builds also skip values that don't fit the reflected type.
+    fn check_uniform(&self, linked: &LinkedProgram, uniform: &Uniform) -> Option<UniformInfo> {
+        let id = uniform.get_id();
+        let problem = match linked.uniforms.get(&id) {
+            None => "not found".to_string(),
+            Some(info) if cfg!(debug_assertions) => match uniform.check(info) {
+                None => return Some(*info),
+                Some(problem) => problem,
+            },
+            Some(info) => return Some(*info),
         };
 
-        if location == -1 {
-            panic!("Uniform not found: {}", name);
+        if self.warned_uniforms.lock().unwrap().insert(id) {
+            eprintln!("Warning: uniform {} {} in program ({}, {})",
+                      id.get_name(),
+                      problem,
+                      self.vertex_path.display(),
+                      self.fragment_path.display());
         }
+        None
+    }
+}
+
+// Compiled programs live in driver memory, the size is not known
+impl AssetMemory for ShaderProgram {
+    fn get_memory_size(&self) -> usize {
+        0
+    }
+}
+
+// Locations and types of the active uniforms, arrays are also found by their name without [0].
+// Uniform block members have no location and are left out.
+fn reflect_uniforms(program_id: GLuint) -> HashMap<UniformId, UniformInfo> {
+    let mut uniforms = HashMap::new();
+    unsafe {
+        let mut count = 0;
+        let mut max_length = 0;
+        gl::GetProgramiv(program_id, gl::ACTIVE_UNIFORMS, &mut count);
+        gl::GetProgramiv(program_id, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_length);
 
-        return location;
+        let mut name_buffer: Vec<u8> = vec![0; max_length.max(1) as usize];
+        for index in 0..count as GLuint {
+            let mut length = 0;
+            let mut size = 0;
+            let mut uniform_type = 0;
+            gl::GetActiveUniform(program_id,
+                                 index,
+                                 name_buffer.len() as i32,
+                                 &mut length,
+                                 &mut size,
+                                 &mut uniform_type,
+                                 name_buffer.as_mut_ptr().cast());
+            let name = String::from_utf8_lossy(&name_buffer[..length as usize]).to_string();
+
+            let location = gl::GetUniformLocation(program_id, CString::new(name.as_str()).unwrap().as_ptr());
+            if location == -1 {
+                continue;
+            }
+
+            let info = UniformInfo {
+                location,
+                gl_type: uniform_type,
+                size,
+            };
+            uniforms.insert(UniformId::get(&name), info);
+            if let Some(base_name) = name.strip_suffix("[0]") {
+                uniforms.insert(UniformId::get(base_name), info);
+            }
+        }
     }
-}
\ No newline at end of file
+    uniforms
+}
+
@@ -0,0 +1,369 @@
+use glam::{Vec2, Vec3, Vec4};
+use image::{Rgba, RgbaImage};
+
+// Values interpolated across a triangle, e.g. uv and light
+pub const VARYINGS: usize = 8;
+pub type Varyings = [f32; VARYINGS];
+
+// Keeps clipped vertices just in front of the near plane, so w never reaches zero
+const NEAR_EPSILON: f32 = 1e-5;
+
+// Vertex after the vertex stage, position is in clip space
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ClipVertex {
+    pub position: Vec4,
+    pub varyings: Varyings,
+}
+
+impl ClipVertex {
+    pub fn new(position: Vec4, varyings: Varyings) -> Self {
+        ClipVertex {
+            position,
+            varyings,
+        }
+    }
+
+    fn lerp(&self, other: &ClipVertex, t: f32) -> ClipVertex {
+        let mut varyings = [0.0; VARYINGS];
+        for (i, varying) in varyings.iter_mut().enumerate() {
+            *varying = self.varyings[i] + (other.varyings[i] - self.varyings[i]) * t;
+        }
+        ClipVertex::new(self.position.lerp(other.position, t), varyings)
+    }
+}
+
+// Vertex after the perspective divide, in pixels with y pointing down
+#[derive(Debug, Clone, Copy)]
+struct ScreenVertex {
+    position: Vec2,
+    // 0 at the near plane, 1 at the far plane
+    depth: f32,
+    inverse_w: f32,
+    varyings: Varyings,
+}
+
+// CPU color and depth buffer, row 0 is the top of the image.
+// Works like the GL state the renderer uses: depth test LESS, depth writes and alpha blending.
+pub struct Framebuffer {
+    color: RgbaImage,
+    depth: Vec<f32>,
+    // Off draws over everything without writing depth, like glDisable(GL_DEPTH_TEST)
+    depth_test: bool,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Framebuffer {
+        let (width, height) = (width.max(1), height.max(1));
+        Framebuffer {
+            color: RgbaImage::new(width, height),
+            depth: vec![1.0; width as usize * height as usize],
+            depth_test: true,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.color.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.color.height()
+    }
+
+    pub fn clear(&mut self, color: Vec4) {
+        let color = to_rgba(color);
+        for pixel in self.color.pixels_mut() {
+            *pixel = color;
+        }
+        self.depth.fill(1.0);
+    }
+
+    pub fn set_depth_test(&mut self, enabled: bool) {
+        self.depth_test = enabled;
+    }
+
+    pub fn get_image(&self) -> &RgbaImage {
+        &self.color
+    }
+
+    #[cfg(test)]
+    pub fn get_depth(&self, x: u32, y: u32) -> f32 {
+        self.depth[(y * self.width() + x) as usize]
+    }
+
+    // Colors every pixel nothing has been drawn to yet, e.g. the sky.
+    // The shader gets the pixel center in normalized device coordinates.
+    pub fn shade_background<F: FnMut(Vec2) -> Vec4>(&mut self, mut shade: F) {
+        let (width, height) = (self.width(), self.height());
+        for y in 0..height {
+            for x in 0..width {
+                if self.depth[(y * width + x) as usize] < 1.0 {
+                    continue;
+                }
+
+                let ndc = Vec2::new((x as f32 + 0.5) / width as f32 * 2.0 - 1.0,
+                                    1.0 - (y as f32 + 0.5) / height as f32 * 2.0);
+                self.color.put_pixel(x, y, to_rgba(shade(ndc)));
+            }
+        }
+    }
+
+    // Shade returns the color of a pixel from its interpolated varyings, None discards it
+    pub fn draw_triangle<F: FnMut(&Varyings) -> Option<Vec4>>(&mut self, vertices: [ClipVertex; 3], shade: &mut F) {
+        let clipped = clip_near(&vertices);
+        if clipped.len() < 3 {
+            return;
+        }
+
+        let screen: Vec<ScreenVertex> = clipped.iter().map(|vertex| self.to_screen(vertex)).collect();
+        for i in 1..screen.len() - 1 {
+            self.rasterize(screen[0], screen[i], screen[i + 1], shade);
+        }
+    }
+
+    fn to_screen(&self, vertex: &ClipVertex) -> ScreenVertex {
+        let inverse_w = 1.0 / vertex.position.w;
+        let ndc = vertex.position.truncate() * inverse_w;
+        ScreenVertex {
+            position: Vec2::new((ndc.x + 1.0) * 0.5 * self.width() as f32,
+                                (1.0 - ndc.y) * 0.5 * self.height() as f32),
+            depth: ndc.z * 0.5 + 0.5,
+            inverse_w,
+            varyings: vertex.varyings,
+        }
+    }
+
+    fn rasterize<F: FnMut(&Varyings) -> Option<Vec4>>(&mut self, v0: ScreenVertex, v1: ScreenVertex, v2: ScreenVertex, shade: &mut F) {
+        // No culling, wind every triangle the same way
+        let (v1, v2) = if edge(v0.position, v1.position, v2.position) < 0.0 { (v2, v1) } else { (v1, v2) };
+        let area = edge(v0.position, v1.position, v2.position);
+        if area <= f32::EPSILON {
+            return;
+        }
+
+        let min = v0.position.min(v1.position).min(v2.position).floor().max(Vec2::ZERO);
+        let max = v0.position.max(v1.position).max(v2.position).ceil()
+            .min(Vec2::new(self.width() as f32, self.height() as f32));
+
+        let edges = [(v1, v2), (v2, v0), (v0, v1)];
+        for y in min.y as u32..max.y as u32 {
+            for x in min.x as u32..max.x as u32 {
+                let point = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+
+                // Barycentric weights, pixels on an edge shared by two triangles are only drawn once
+                let mut weights = [0.0; 3];
+                let mut inside = true;
+                for (i, (a, b)) in edges.iter().enumerate() {
+                    let weight = edge(a.position, b.position, point);
+                    if weight < 0.0 || (weight == 0.0 && !owns_edge(a.position, b.position)) {
+                        inside = false;
+                        break;
+                    }
+                    weights[i] = weight / area;
+                }
+                if !inside {
+                    continue;
+                }
+
+                let depth = weights[0] * v0.depth + weights[1] * v1.depth + weights[2] * v2.depth;
+                let index = (y * self.width() + x) as usize;
+                if !(0.0..=1.0).contains(&depth) || (self.depth_test && depth >= self.depth[index]) {
+                    continue;
+                }
+
+                // Perspective correct interpolation
+                let w = [weights[0] * v0.inverse_w, weights[1] * v1.inverse_w, weights[2] * v2.inverse_w];
+                let inverse_w = w[0] + w[1] + w[2];
+                let mut varyings = [0.0; VARYINGS];
+                for (i, varying) in varyings.iter_mut().enumerate() {
+                    *varying = (w[0] * v0.varyings[i] + w[1] * v1.varyings[i] + w[2] * v2.varyings[i]) / inverse_w;
+                }
+
+                if let Some(color) = shade(&varyings) {
+                    self.blend(x, y, color);
+                    if self.depth_test {
+                        self.depth[index] = depth;
+                    }
+                }
+            }
+        }
+    }
+
+    // SRC_ALPHA, ONE_MINUS_SRC_ALPHA
+    fn blend(&mut self, x: u32, y: u32, color: Vec4) {
+        let destination = self.color.get_pixel(x, y).0.map(|c| c as f32 / 255.0);
+        let destination = Vec4::from_array(destination);
+        let alpha = color.w.clamp(0.0, 1.0);
+        self.color.put_pixel(x, y, to_rgba(color * alpha + destination * (1.0 - alpha)));
+    }
+}
+
+// Twice the signed area of the triangle a, b, p
+fn edge(a: Vec2, b: Vec2, p: Vec2) -> f32 {
+    (p.x - a.x) * (b.y - a.y) - (p.y - a.y) * (b.x - a.x)
+}
+
+// Exactly one of two triangles sharing an edge walks it in this direction
+fn owns_edge(a: Vec2, b: Vec2) -> bool {
+    let direction = b - a;
+    direction.y < 0.0 || (direction.y == 0.0 && direction.x > 0.0)
+}
+
+// Cuts off the part of the triangle behind the near plane, leaves up to 4 vertices
+fn clip_near(vertices: &[ClipVertex; 3]) -> Vec<ClipVertex> {
+    let distance = |vertex: &ClipVertex| vertex.position.z + vertex.position.w - NEAR_EPSILON;
+
+    let mut clipped = Vec::with_capacity(4);
+    for i in 0..3 {
+        let current = &vertices[i];
+        let next = &vertices[(i + 1) % 3];
+        let (current_distance, next_distance) = (distance(current), distance(next));
+
+        if current_distance >= 0.0 {
+            clipped.push(*current);
+        }
+        if (current_distance >= 0.0) != (next_distance >= 0.0) {
+            let t = current_distance / (current_distance - next_distance);
+            clipped.push(current.lerp(next, t));
+        }
+    }
+    clipped
+}
+
+fn to_rgba(color: Vec4) -> Rgba<u8> {
+    Rgba(color.clamp(Vec4::ZERO, Vec4::ONE).to_array().map(|c| (c * 255.0).round() as u8))
+}
+
+// Nearest texel with repeat wrapping, like a SPRITE texture. v points up, like GL after the
+// textures are flipped on load.
+pub fn sample_nearest(texture: &RgbaImage, uv: Vec2) -> Vec4 {
+    let (width, height) = texture.dimensions();
+    let x = ((uv.x * width as f32).floor() as i64).rem_euclid(width as i64) as u32;
+    let y = (((1.0 - uv.y) * height as f32).floor() as i64).rem_euclid(height as i64) as u32;
+    Vec4::from_array(texture.get_pixel(x, y).0.map(|c| c as f32 / 255.0))
+}
+
+// Varyings from up to 8 floats, the rest stay zero
+pub fn varyings(values: &[f32]) -> Varyings {
+    let mut varyings = [0.0; VARYINGS];
+    varyings[..values.len()].copy_from_slice(values);
+    varyings
+}
+
+pub fn get_varying3(varyings: &Varyings, start: usize) -> Vec3 {
+    Vec3::new(varyings[start], varyings[start + 1], varyings[start + 2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(x: f32, y: f32, z: f32) -> ClipVertex {
+        ClipVertex::new(Vec4::new(x, y, z, 1.0), [0.0; VARYINGS])
+    }
+
+    fn pixel(framebuffer: &Framebuffer, x: u32, y: u32) -> [u8; 4] {
+        framebuffer.get_image().get_pixel(x, y).0
+    }
+
+    #[test]
+    fn fills_covered_pixels() {
+        let mut framebuffer = Framebuffer::new(8, 8);
+        framebuffer.clear(Vec4::new(0.0, 0.0, 0.0, 1.0));
+        framebuffer.draw_triangle([vertex(-1.0, -1.0, 0.0), vertex(1.0, -1.0, 0.0), vertex(-1.0, 1.0, 0.0)],
+                                  &mut |_| Some(Vec4::new(1.0, 0.0, 0.0, 1.0)));
+
+        // Bottom left half
+        assert_eq!(pixel(&framebuffer, 0, 7), [255, 0, 0, 255]);
+        assert_eq!(pixel(&framebuffer, 7, 0), [0, 0, 0, 255]);
+        assert_eq!(framebuffer.get_depth(0, 7), 0.5);
+    }
+
+    #[test]
+    fn nearer_triangle_wins_in_any_order() {
+        let near = [vertex(-1.0, -1.0, -0.5), vertex(3.0, -1.0, -0.5), vertex(-1.0, 3.0, -0.5)];
+        let far = [vertex(-1.0, -1.0, 0.5), vertex(3.0, -1.0, 0.5), vertex(-1.0, 3.0, 0.5)];
+        let green = Vec4::new(0.0, 1.0, 0.0, 1.0);
+        let red = Vec4::new(1.0, 0.0, 0.0, 1.0);
+
+        for near_first in [true, false] {
+            let mut framebuffer = Framebuffer::new(4, 4);
+            if near_first {
+                framebuffer.draw_triangle(near, &mut |_| Some(green));
+                framebuffer.draw_triangle(far, &mut |_| Some(red));
+            } else {
+                framebuffer.draw_triangle(far, &mut |_| Some(red));
+                framebuffer.draw_triangle(near, &mut |_| Some(green));
+            }
+            assert_eq!(pixel(&framebuffer, 1, 1), [0, 255, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn draws_over_everything_without_depth_test() {
+        let mut framebuffer = Framebuffer::new(4, 4);
+        let near = [vertex(-1.0, -1.0, -0.5), vertex(3.0, -1.0, -0.5), vertex(-1.0, 3.0, -0.5)];
+        framebuffer.draw_triangle(near, &mut |_| Some(Vec4::new(0.0, 1.0, 0.0, 1.0)));
+
+        framebuffer.set_depth_test(false);
+        let far = [vertex(-1.0, -1.0, 0.5), vertex(3.0, -1.0, 0.5), vertex(-1.0, 3.0, 0.5)];
+        framebuffer.draw_triangle(far, &mut |_| Some(Vec4::new(1.0, 0.0, 0.0, 1.0)));
+
+        // Drawn, but the depth of the near triangle is kept
+        assert_eq!(pixel(&framebuffer, 1, 1), [255, 0, 0, 255]);
+        assert_eq!(framebuffer.get_depth(1, 1), 0.25);
+    }
+
+    #[test]
+    fn shared_edges_are_drawn_once() {
+        let mut framebuffer = Framebuffer::new(16, 16);
+        framebuffer.clear(Vec4::new(0.0, 0.0, 0.0, 1.0));
+
+        // Quad from two triangles, blended at half alpha without depth getting in the way
+        let quad = [vertex(-1.0, -1.0, 0.0), vertex(1.0, -1.0, 0.0), vertex(1.0, 1.0, 0.0), vertex(-1.0, 1.0, 0.0)];
+        let mut shade = |_: &Varyings| Some(Vec4::new(1.0, 1.0, 1.0, 0.5));
+        framebuffer.draw_triangle([quad[0], quad[1], quad[2]], &mut shade);
+        framebuffer.depth.fill(1.0);
+        framebuffer.draw_triangle([quad[2], quad[3], quad[0]], &mut shade);
+
+        assert!(framebuffer.get_image().pixels().all(|pixel| pixel.0 == [128, 128, 128, 191]));
+    }
+
+    #[test]
+    fn interpolates_perspective_correct() {
+        let mut framebuffer = Framebuffer::new(64, 1);
+
+        // Left edge at w = 1, right edge at w = 3, u goes from 0 to 1
+        let left = |y: f32| ClipVertex::new(Vec4::new(-1.0, y, 0.0, 1.0), varyings(&[0.0]));
+        let right = |y: f32| ClipVertex::new(Vec4::new(3.0, y * 3.0, 0.0, 3.0), varyings(&[1.0]));
+        let mut u_values = Vec::new();
+        framebuffer.draw_triangle([left(-1.0), right(-1.0), right(1.0)], &mut |v| {
+            u_values.push(v[0]);
+            Some(Vec4::ONE)
+        });
+        framebuffer.draw_triangle([right(1.0), left(1.0), left(-1.0)], &mut |v| {
+            u_values.push(v[0]);
+            Some(Vec4::ONE)
+        });
+
+        // Halfway across the screen is a quarter of the way in u
+        let middle = u_values.iter().copied().fold(f32::MAX, |closest, u| {
+            if (u - 0.25).abs() < (closest - 0.25).abs() { u } else { closest }
+        });
+        assert!((middle - 0.25).abs() < 0.02, "u at the screen center is {}", middle);
+        assert!(u_values.iter().all(|u| (0.0..=1.0).contains(u)));
+    }
+
+    #[test]
+    fn clips_triangles_crossing_the_near_plane() {
+        let mut framebuffer = Framebuffer::new(8, 8);
+        framebuffer.clear(Vec4::new(0.0, 0.0, 0.0, 1.0));
+
+        // Top vertex is in front of the near plane
+        let triangle = [vertex(-1.0, -1.0, 0.0), vertex(1.0, -1.0, 0.0), vertex(0.0, 1.0, -3.0)];
+        assert_eq!(clip_near(&triangle).len(), 4);
+
+        framebuffer.draw_triangle(triangle, &mut |_| Some(Vec4::ONE));
+        assert_eq!(pixel(&framebuffer, 4, 7), [255, 255, 255, 255]);
+        assert_eq!(pixel(&framebuffer, 4, 1), [0, 0, 0, 255]);
+    }
+}
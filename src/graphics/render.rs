@@ -1,34 +1,79 @@
 use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Instant;
 
-use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
+use glam::{IVec2, Mat3, Mat4, Quat, Vec2, Vec3, Vec4};
 use glfw::PWindow;
-
+use image::{ImageResult, RgbaImage};
+
+use crate::graphics::colormap::{self, Colormap, INDEX_LUT_SIZE, LightMode, LightTables};
+use crate::graphics::display::{DisplaySettings, InternalResolution, Viewport};
+use crate::graphics::frame_uniforms::FrameUniforms;
+use crate::graphics::light::Light;
+use crate::graphics::material::Material;
+use crate::graphics::mesh::Mesh;
+use crate::graphics::palette::Palette;
 use crate::graphics::polygon::Polygon;
-use crate::graphics::program::Uniform;
-use crate::registry::mesh_registry;
-use crate::registry::mesh_registry::MeshId;
-use crate::registry::shader_registry::{SHADER_REGISTRY, ShaderProgramId};
+use crate::graphics::post_process::{PostEffect, PostEffectKind, PostProcessChain};
+use crate::graphics::program::{BUILTIN_UNIFORMS, Uniform};
+use crate::graphics::render_backend::RenderBackend;
+use crate::graphics::render_target::RenderTarget;
+use crate::graphics::sector_wall::SectorWall;
+use crate::graphics::sky::{self, SKY_HEIGHT_DEG, SKY_REPEAT, SkyMode};
+use crate::graphics::texture::TextureType;
+use crate::registry::Assets;
+use crate::registry::asset_store::AssetScope;
+use crate::registry::mesh_registry::{BuiltinMeshes, LevelMeshId, MeshId};
+use crate::registry::model_registry::ModelId;
+use crate::registry::texture_registry::TextureId;
 
 // TODO factor out to config info
 pub struct Renderer {
     pub window_size: Vec2,
+    // Resolution the scene is drawn at, HUD coordinates span this
+    pub internal_size: Vec2,
+    display_settings: DisplaySettings,
     polygon_cache: HashMap<u64, Polygon>,
+    // Batched walls of the current level, the mesh is kept by the level scope
+    sector_walls: Option<LevelMeshId>,
+    assets: Rc<Assets>,
+    meshes: BuiltinMeshes,
     orthographic_projection: Mat4,
     perspective_projection: Mat4,
+
+    // Lighting
+    lights: Vec<Light>,
+    pub ambient_light: Vec3,
+    pub blinn_phong: bool,
+
+    pub sky_mode: SkyMode,
+
+    // Sector lighting
+    light_tables: LightTables,
+    pub light_mode: LightMode,
+    pub fake_contrast: bool,
+    // Map units per world unit, for distance based light falloff
+    pub light_distance_scale: f32,
+
+    // View, projections, time and lights for every program
+    frame_uniforms: FrameUniforms,
+    start_time: Instant,
+
+    // The scene is drawn here, then post processed to the window
+    scene_target: RenderTarget,
+    pub post_process: PostProcessChain,
+    // When set, frames end up here instead of the window, for headless rendering
+    output_target: Option<RenderTarget>,
 }
 
 // Draws a scene with a camera
 impl Renderer {
-    pub fn new(window: &mut PWindow) -> Renderer {
-        // Load OpenGL functions dynamically
-        gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
-
-        // Set viewport size
-        // TODO handle updates to window size
-        let mut window_size = Vec2::default();
+    // GL functions must be loaded and the assets created on the window's context
+    pub fn new(window: &PWindow, assets: Rc<Assets>) -> Renderer {
+        let (width, height) = window.get_framebuffer_size();
+        let window_size = Vec2::new(width as f32, height as f32);
         unsafe {
-            let (width, height) = window.get_framebuffer_size();
-            window_size = Vec2::new(width as f32, height as f32);
             gl::Viewport(0, 0, width, height);
             gl::ClearColor(0.8, 0.87, 0.95, 1.0);
             gl::Enable(gl::DEPTH_TEST);
@@ -38,40 +83,219 @@ impl Renderer {
             gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
         }
 
-        Renderer {
-            window_size: window_size,
+        let palette = Palette::fallback();
+        let colormap = Colormap::generate(&palette);
+
+        let display_settings = DisplaySettings::native();
+        let internal_size = display_settings.resolution.get_size(window_size.as_uvec2());
+
+        let mut post_process = PostProcessChain::new(internal_size, &assets);
+        // Neutral and skipped until pushed again with other settings
+        post_process.push(PostEffect::Gamma { gamma: 1.0 });
+        post_process.push(PostEffect::DamageTint { color: Vec3::new(1.0, 0.0, 0.0), strength: 0.0 });
+        post_process.push(PostEffect::Palette);
+        post_process.push(PostEffect::Crt { scanline_intensity: 0.25, curvature: 0.05 });
+        post_process.set_enabled(PostEffectKind::Palette, false);
+        post_process.set_enabled(PostEffectKind::Crt, false);
+
+        let mut renderer = Renderer {
+            window_size,
+            internal_size: internal_size.as_vec2(),
+            display_settings,
             polygon_cache: HashMap::new(),
-            orthographic_projection: Mat4::orthographic_rh_gl(
-                0.0,
-                window_size.x,
-                0.0,
-                window_size.y,
-                -1.0,
-                1.0,
-            ),
-            perspective_projection: Mat4::perspective_rh_gl(
-                90.0_f32.to_radians(),
-                window_size.x / window_size.y,
-                0.1,
-                1000.0,
-            ),
-        }
-    }
-
-    pub fn draw_point(&self, position: Vec2, size: f32, color: Vec4) {
-        // let half_size = size / 2.0;
-        let model = Mat4::from_scale_rotation_translation(Vec3::splat(size), Quat::IDENTITY, Vec3::new(position.x, position.y, 0.0));
-
-        let uniforms = vec![
-            Uniform::Matrix4f("model".to_string(), model),
-            Uniform::Matrix4f("projection".to_string(), self.orthographic_projection.clone()),
-            Uniform::Vec4("diffuse".to_string(), color),
-        ];
+            sector_walls: None,
+            meshes: BuiltinMeshes::resolve(&assets.manifest),
+            assets,
+            orthographic_projection: Mat4::IDENTITY,
+            perspective_projection: Mat4::IDENTITY,
+            lights: Vec::new(),
+            ambient_light: Vec3::splat(0.3),
+            blinn_phong: true,
+            sky_mode: SkyMode::Cylindrical,
+            light_tables: LightTables::new(&palette, &colormap),
+            light_mode: LightMode::Palette,
+            fake_contrast: true,
+            light_distance_scale: 8.0,
+            frame_uniforms: FrameUniforms::new(),
+            start_time: Instant::now(),
+            // Nearest filtering keeps pixels sharp when upscaling low resolutions
+            scene_target: RenderTarget::new(internal_size.x, internal_size.y, TextureType::SPRITE, true),
+            post_process,
+            output_target: None,
+        };
+        renderer.update_projections();
+        if let Some((palette, colormap)) = colormap::load_lumps(&renderer.assets.vfs) {
+            renderer.set_palette(&palette, &colormap);
+        }
+        renderer
+    }
+
+    fn update_projections(&mut self) {
+        // HUD is laid out in internal pixels
+        self.orthographic_projection = Mat4::orthographic_rh_gl(
+            0.0,
+            self.internal_size.x,
+            0.0,
+            self.internal_size.y,
+            -1.0,
+            1.0,
+        );
+        // Use the aspect the image is shown at, 320x200 is stretched to 4:3
+        self.perspective_projection = Mat4::perspective_rh_gl(
+            90.0_f32.to_radians(),
+            self.display_settings.get_display_aspect(self.window_size.as_uvec2()),
+            0.1,
+            1000.0,
+        );
+    }
+
+    // Width / height the internal image is shown at, for renderers drawing it elsewhere
+    pub fn get_display_aspect(&self) -> f32 {
+        self.display_settings.get_display_aspect(self.window_size.as_uvec2())
+    }
+
+    // Call when the window framebuffer changes size
+    pub fn resize(&mut self, width: i32, height: i32) {
+        // Minimized
+        if width <= 0 || height <= 0 {
+            return;
+        }
+
+        self.window_size = Vec2::new(width as f32, height as f32);
+        self.update_internal_size();
+    }
+
+    pub fn get_assets(&self) -> &Assets {
+        &self.assets
+    }
+
+    pub fn get_display_settings(&self) -> DisplaySettings {
+        self.display_settings
+    }
+
+    pub fn set_display_settings(&mut self, display_settings: DisplaySettings) {
+        self.display_settings = display_settings;
+        self.update_internal_size();
+    }
+
+    // Switch between native, 320x200 and 640x400
+    pub fn cycle_internal_resolution(&mut self) {
+        let resolution = self.display_settings.resolution.next();
+        self.set_display_settings(DisplaySettings {
+            resolution,
+            aspect_correction: resolution != InternalResolution::Native,
+            ..self.display_settings
+        });
+    }
+
+    fn update_internal_size(&mut self) {
+        let internal_size = self.display_settings.resolution.get_size(self.window_size.as_uvec2());
+        self.internal_size = internal_size.as_vec2();
+        self.update_projections();
+        self.scene_target.resize(internal_size.x, internal_size.y);
+        self.post_process.resize(internal_size.x, internal_size.y);
+        if let Some(output_target) = self.output_target.as_mut() {
+            output_target.resize(self.window_size.x as u32, self.window_size.y as u32);
+        }
+    }
+
+    // Draw finished frames into an offscreen target the size of the window instead of the window
+    pub fn set_offscreen(&mut self, offscreen: bool) {
+        if !offscreen {
+            self.output_target = None;
+        } else if self.output_target.is_none() {
+            let window_size = self.window_size.as_uvec2();
+            self.output_target = Some(RenderTarget::new(window_size.x, window_size.y, TextureType::TEXTURE, false));
+        }
+    }
+
+    // Reads back the last finished frame, including black bars
+    pub fn read_frame(&self) -> RgbaImage {
+        match &self.output_target {
+            Some(output_target) => output_target.read_pixels(),
+            None => RenderTarget::read_default(self.window_size.as_uvec2()),
+        }
+    }
+
+    // Post processed frame at internal resolution, before it is scaled to the window
+    pub fn read_internal_frame(&self) -> RgbaImage {
+        let internal_size = self.internal_size.as_uvec2();
+        let target = RenderTarget::new(internal_size.x, internal_size.y, TextureType::SPRITE, false);
+        self.post_process.apply(&self.scene_target, Some(&target), Viewport::new(IVec2::ZERO, internal_size));
+        target.read_pixels()
+    }
+
+    // Saves the last finished frame as PNG, call after end_frame and before swapping buffers
+    pub fn capture_frame(&self, path: &Path, internal_resolution: bool) -> ImageResult<()> {
+        let image = if internal_resolution {
+            self.read_internal_frame()
+        } else {
+            self.read_frame()
+        };
+        image.save_with_format(path, image::ImageFormat::Png)
+    }
+
+    // Start drawing the scene offscreen
+    pub fn begin_frame(&self) {
+        self.upload_frame_uniforms();
+        self.scene_target.bind();
+        unsafe {
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+    }
+
+    // Uses a frame drawn elsewhere as the scene, e.g. by the software renderer.
+    // Call after begin_frame, the image must be at the internal resolution.
+    pub fn draw_image(&self, image: &RgbaImage) {
+        self.scene_target.upload(image);
+    }
+
+    // Post process the scene onto the window or offscreen output, scaled and centered
+    pub fn end_frame(&self) {
+        let window_size = self.window_size.as_uvec2();
+
+        // Black bars around the image
+        let full_viewport = Viewport::new(IVec2::ZERO, window_size);
+        match &self.output_target {
+            Some(output_target) => output_target.bind_viewport(full_viewport),
+            None => RenderTarget::bind_default(full_viewport),
+        }
+        unsafe {
+            gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+            gl::ClearColor(0.8, 0.87, 0.95, 1.0);
+        }
+
+        self.post_process.apply(&self.scene_target,
+                                self.output_target.as_ref(),
+                                self.display_settings.get_output_viewport(window_size));
+    }
+
+    // Palette and colormap used for palette lighting and quantization, e.g. from a WAD
+    pub fn set_palette(&mut self, palette: &Palette, colormap: &Colormap) {
+        self.light_tables = LightTables::new(palette, colormap);
+        self.post_process.set_palette(palette);
+    }
+
+    pub fn toggle_light_mode(&mut self) {
+        self.light_mode = match self.light_mode {
+            LightMode::Palette => LightMode::Smooth,
+            LightMode::Smooth => LightMode::Palette,
+        };
+    }
+
+    pub fn set_lights(&mut self, lights: &[Light]) {
+        self.lights = lights.to_vec();
+        self.upload_frame_uniforms();
+    }
 
-        mesh_registry::MESH_REGISTRY.read()
-            .unwrap()
-            .get(&MeshId::Rect).expect("MeshId::Quad not found")
-            .draw(&uniforms);
+    fn upload_frame_uniforms(&self) {
+        self.frame_uniforms.upload(self.perspective_projection,
+                                   self.orthographic_projection,
+                                   self.start_time.elapsed().as_secs_f32(),
+                                   self.ambient_light,
+                                   self.blinn_phong,
+                                   &self.lights);
     }
 
     // Position is bottom left corner
@@ -84,41 +308,52 @@ impl Renderer {
             Quat::from_rotation_z(rotation_deg.to_radians()),
             Vec3::new(position.x + width_offset, position.y + height_offset, 0.0));
 
-        let uniforms = vec![
-            Uniform::Matrix4f("model".to_string(), model),
-            Uniform::Matrix4f("projection".to_string(), self.orthographic_projection.clone()),
-            Uniform::Vec4("diffuse".to_string(), color),
+        let uniforms = [
+            Uniform::Matrix4f(BUILTIN_UNIFORMS.model, model),
+            Uniform::Vec4(BUILTIN_UNIFORMS.diffuse, color),
         ];
 
-        mesh_registry::MESH_REGISTRY.read()
-            .unwrap()
-            .get(&MeshId::Rect).expect("MeshId::Quad not found")
-            .draw(&uniforms);
+        self.assets.get_mesh(self.meshes.rect).draw(&uniforms);
     }
 
-    pub fn draw_rect_textured(&self, position: Vec2, size: Vec2, rotation_deg: f32, color: Vec4) {
-        let width_offset = size.x / 2.0;
-        let height_offset = size.y / 2.0;
-
-        let x_scale = 1.0;
-
+    // Draws one layer of an array texture, e.g. a frame of an animation
+    pub fn draw_rect_layer(&self, position: Vec2, size: Vec2, texture: TextureId, layer: u32) {
         let model = Mat4::from_scale_rotation_translation(
             Vec3::new(size.x, size.y, 1.0),
-            Quat::from_rotation_z(rotation_deg.to_radians()),
-            Vec3::new(position.x + width_offset, position.y + height_offset, 0.0));
-
-        let uniforms = vec![
-            Uniform::Matrix4f("model".to_string(), model),
-            Uniform::Matrix4f("projection".to_string(), self.orthographic_projection.clone()),
-            // Uniform::Vec4("diffuse".to_string(), color),
-            Uniform::Int("texture1".to_string(), 0),
-            Uniform::Float("texWidthScale".to_string(), x_scale),
+            Quat::IDENTITY,
+            Vec3::new(position.x + size.x / 2.0, position.y + size.y / 2.0, 0.0));
+
+        let ids = &*BUILTIN_UNIFORMS;
+        let uniforms = [
+            Uniform::Matrix4f(ids.model, model),
+            Uniform::Sampler(ids.texture1, 0),
+            Uniform::Float(ids.tex_width_scale, 1.0),
+            Uniform::Int(ids.layer, layer as i32),
         ];
 
-        mesh_registry::MESH_REGISTRY.read()
-            .unwrap()
-            .get(&MeshId::TexturedRect).expect("MeshId::Textured not found")
-            .draw(&uniforms);
+        let texture = self.assets.get_texture(texture);
+        self.assets.get_mesh(self.meshes.layered_rect).draw_with_textures(&uniforms, &[&texture]);
+    }
+
+    pub fn draw_sprite_layer(&self, texture_id: TextureId, layer: u32, origin: Vec2, scale: f32) {
+        let texture = self.assets.get_texture(texture_id);
+        if layer as usize >= texture.get_layer_count() {
+            eprintln!("Texture '{}' has no layer {}, skipping the sprite", self.assets.manifest.get_texture_key(texture_id), layer);
+            return;
+        }
+        // The layer sits in the bottom left of the array, the padding around it is transparent
+        let layer_size = texture.get_layer_size(layer as usize).as_vec2();
+        let offset = texture.get_layer_offset(layer as usize).as_vec2();
+        let position = origin + Vec2::new(-offset.x, offset.y - layer_size.y) * scale;
+
+        // HUD sprites overlap at the same depth
+        unsafe {
+            gl::Disable(gl::DEPTH_TEST);
+        }
+        self.draw_rect_layer(position, texture.get_size().as_vec2() * scale, texture_id, layer);
+        unsafe {
+            gl::Enable(gl::DEPTH_TEST);
+        }
     }
 
     pub fn draw_line(&self, start: Vec2, end: Vec2, thickness: f32, color: Vec4) {
@@ -132,38 +367,189 @@ impl Renderer {
             Quat::from_rotation_z(angle),
             Vec3::new(midpoint.x, midpoint.y, 0.0));
 
-        let uniforms = vec![
-            Uniform::Matrix4f("model".to_string(), model),
-            Uniform::Matrix4f("projection".to_string(), self.orthographic_projection.clone()),
-            Uniform::Vec4("diffuse".to_string(), color),
+        let uniforms = [
+            Uniform::Matrix4f(BUILTIN_UNIFORMS.model, model),
+            Uniform::Vec4(BUILTIN_UNIFORMS.diffuse, color),
         ];
 
-        mesh_registry::MESH_REGISTRY.read()
-            .unwrap()
-            .get(&MeshId::Rect).expect("MeshId::Quad not found")
-            .draw(&uniforms);
+        self.assets.get_mesh(self.meshes.rect).draw(&uniforms);
     }
 
     pub fn draw_wall(&self, view_matrix: Mat4, position: Vec3, size: Vec2, rotation_y_deg: f32, color: Vec4) {
+        self.frame_uniforms.set_view(view_matrix);
         let x_scale = size.x / size.y * 2.0;
         let model = Mat4::from_scale_rotation_translation(
             Vec3::new(size.x, size.y, 1.0),
             Quat::from_rotation_y(rotation_y_deg.to_radians()),
             position);
 
-        let uniforms = vec![
-            Uniform::Matrix4f("model".to_string(), model),
-            Uniform::Matrix4f("view".to_string(), view_matrix),
-            Uniform::Matrix4f("projection".to_string(), self.perspective_projection.clone()),
-            // Uniform::Vec4("diffuse".to_string(), color),
-            Uniform::Int("texture1".to_string(), 0),
-            Uniform::Float("texWidthScale".to_string(), x_scale),
+        let material = Material {
+            diffuse: color.truncate(),
+            ..Material::plain()
+        };
+
+        let ids = &*BUILTIN_UNIFORMS;
+        let [ambient, diffuse, specular, shininess] = material.get_uniforms();
+        let uniforms = [
+            Uniform::Matrix4f(ids.model, model),
+            Uniform::Matrix3f(ids.normal_matrix, get_normal_matrix(model)),
+            Uniform::Sampler(ids.texture1, 0),
+            Uniform::Bool(ids.use_texture, true),
+            Uniform::Float(ids.tex_width_scale, x_scale),
+            ambient,
+            diffuse,
+            specular,
+            shininess,
         ];
 
-        mesh_registry::MESH_REGISTRY.read()
-            .unwrap()
-            .get(&MeshId::Wall).expect("MeshId::Quad not found")
-            .draw(&uniforms);
+        self.assets.get_mesh(self.meshes.wall).draw(&uniforms);
+    }
+
+    // Batches the level walls into one mesh with the light of each wall in its vertices. The mesh
+    // and what it draws with belong to the level scope, unloading the level frees them.
+    pub fn load_sector_walls(&mut self, walls: &[SectorWall]) {
+        self.sector_walls = None;
+        if walls.is_empty() {
+            return;
+        }
+
+        let entry = self.assets.manifest.get_mesh(self.meshes.sector_wall);
+        let program = self.assets.acquire_program(entry.program, AssetScope::Level);
+        let texture = entry.texture.map(|texture_id| self.assets.acquire_texture(texture_id, AssetScope::Level));
+        let vertices = SectorWall::build_vertices(walls, self.fake_contrast);
+        let mesh = Mesh::new(vertices, SectorWall::build_indices(walls.len()), entry.shape.get_layout(), program, texture);
+        self.sector_walls = Some(self.assets.add_level_mesh(mesh));
+    }
+
+    // Draws the level walls lit like Doom, darker with distance depending on the sector light
+    pub fn draw_sector_walls(&mut self, view_matrix: Mat4) {
+        let Some(mesh) = self.sector_walls.and_then(|id| self.assets.get_level_mesh(id)) else {
+            return;
+        };
+
+        self.frame_uniforms.set_view(view_matrix);
+        let uniforms = self.get_sector_uniforms(Mat4::IDENTITY, 1.0, 0, 0, true);
+
+        self.light_tables.bind(1);
+        mesh.draw(&uniforms);
+    }
+
+    // Draws a level ceiling, position is its center. Flats are texture keys in the manifest,
+    // ceilings with the sky flat show the sky.
+    pub fn draw_sector_ceiling(&self, view_matrix: Mat4, position: Vec3, size: Vec2, flat: &str, sector_light: u8) {
+        if sky::is_sky_flat(flat) {
+            self.draw_sky_ceiling(view_matrix, position, size);
+            return;
+        }
+
+        self.frame_uniforms.set_view(view_matrix);
+        let model = Mat4::from_scale_rotation_translation(
+            Vec3::new(size.x, 1.0, size.y),
+            Quat::IDENTITY,
+            position);
+
+        let uniforms = self.get_sector_uniforms(model, size.x / size.y, sector_light, 0, false);
+        let texture = self.assets.get_texture(self.assets.manifest.get_texture_id(flat));
+
+        self.light_tables.bind(1);
+        self.assets.get_mesh(self.meshes.sector_ceiling).draw_with_textures(&uniforms, &[&texture]);
+    }
+
+    fn get_sector_uniforms(&self, model: Mat4, x_scale: f32, sector_light: u8, contrast: i32, vertex_light: bool) -> [Uniform; 12] {
+        let light_mode = match self.light_mode {
+            LightMode::Palette => 0,
+            LightMode::Smooth => 1,
+        };
+
+        let ids = &*BUILTIN_UNIFORMS;
+        [
+            Uniform::Matrix4f(ids.model, model),
+            Uniform::Sampler(ids.texture1, 0),
+            Uniform::Float(ids.tex_width_scale, x_scale),
+            Uniform::Float(ids.sector_light, sector_light as f32),
+            Uniform::Sampler(ids.palette_index_lut, 1),
+            Uniform::Sampler(ids.colormap, 2),
+            Uniform::Sampler(ids.palette, 3),
+            Uniform::Float(ids.lut_size, INDEX_LUT_SIZE as f32),
+            Uniform::Int(ids.light_mode, light_mode),
+            Uniform::Int(ids.light_contrast, contrast),
+            Uniform::Float(ids.light_distance_scale, self.light_distance_scale),
+            Uniform::Bool(ids.use_vertex_light, vertex_light),
+        ]
+    }
+
+    // Draws an OBJ model with its own materials, lit by the current lights
+    pub fn draw_model(&self, view_matrix: Mat4, model_id: ModelId, position: Vec3, rotation_y_deg: f32, scale: f32) {
+        self.frame_uniforms.set_view(view_matrix);
+        let model = Mat4::from_scale_rotation_translation(
+            Vec3::splat(scale),
+            Quat::from_rotation_y(rotation_y_deg.to_radians()),
+            position);
+
+        let ids = &*BUILTIN_UNIFORMS;
+        let uniforms = [
+            Uniform::Matrix4f(ids.model, model),
+            Uniform::Matrix3f(ids.normal_matrix, get_normal_matrix(model)),
+            Uniform::Bool(ids.use_texture, false),
+            Uniform::Float(ids.tex_width_scale, 0.0),
+        ];
+
+        self.assets.get_model(model_id).draw(&uniforms);
+    }
+
+    // Draws the sky behind everything, call before the rest of the scene
+    pub fn draw_sky(&self, view_matrix: Mat4) {
+        // The shaders strip the translation, the sky moves with the camera
+        self.frame_uniforms.set_view(view_matrix);
+
+        match self.sky_mode {
+            SkyMode::None => {}
+            SkyMode::Cubemap => {
+                self.draw_background(self.meshes.skybox, &[Uniform::Sampler(BUILTIN_UNIFORMS.skybox, 0)]);
+            }
+            SkyMode::Cylindrical => {
+                self.draw_background(self.meshes.sky_background, &self.get_sky_uniforms(Mat4::IDENTITY, true));
+            }
+        }
+    }
+
+    // Sky meshes sit on the far plane and leave the depth buffer to the scene
+    fn draw_background(&self, mesh_id: MeshId, uniforms: &[Uniform]) {
+        unsafe {
+            gl::DepthMask(gl::FALSE);
+            gl::DepthFunc(gl::LEQUAL);
+        }
+
+        self.assets.get_mesh(mesh_id).draw(uniforms);
+
+        unsafe {
+            gl::DepthFunc(gl::LESS);
+            gl::DepthMask(gl::TRUE);
+        }
+    }
+
+    // Draws a ceiling that uses the sky flat, position is the center of the ceiling
+    pub fn draw_sky_ceiling(&self, view_matrix: Mat4, position: Vec3, size: Vec2) {
+        self.frame_uniforms.set_view(view_matrix);
+        let model = Mat4::from_scale_rotation_translation(
+            Vec3::new(size.x, 1.0, size.y),
+            Quat::IDENTITY,
+            position);
+
+        let uniforms = self.get_sky_uniforms(model, false);
+
+        self.assets.get_mesh(self.meshes.sky_ceiling).draw(&uniforms);
+    }
+
+    fn get_sky_uniforms(&self, model: Mat4, background: bool) -> [Uniform; 5] {
+        let ids = &*BUILTIN_UNIFORMS;
+        [
+            Uniform::Matrix4f(ids.model, model),
+            Uniform::Sampler(ids.sky, 0),
+            Uniform::Float(ids.sky_repeat, SKY_REPEAT),
+            Uniform::Float(ids.sky_height, SKY_HEIGHT_DEG.to_radians()),
+            Uniform::Bool(ids.background, background),
+        ]
     }
 
     pub fn draw_polygon(&mut self, vertices: &[Vec3], position: Vec3, rotation_deg: f32, scale: f32, color: Vec4) {
@@ -173,10 +559,9 @@ impl Renderer {
             position,
         );
 
-        let uniforms = vec![
-            Uniform::Matrix4f("model".to_string(), model),
-            Uniform::Matrix4f("projection".to_string(), self.orthographic_projection.clone()),
-            Uniform::Vec4("diffuse".to_string(), color),
+        let uniforms = [
+            Uniform::Matrix4f(BUILTIN_UNIFORMS.model, model),
+            Uniform::Vec4(BUILTIN_UNIFORMS.diffuse, color),
         ];
 
         let polygon = self.get_polygon_from_cache_or_store(vertices);
@@ -187,16 +572,75 @@ impl Renderer {
         let hash = Polygon::get_vertex_list_hash(vertices);
 
         if !self.polygon_cache.contains_key(&hash) {
-            let ortho_program = SHADER_REGISTRY.read()
-                .unwrap()
-                .get(&ShaderProgramId::Ortho)
-                .expect("ShaderProgramId::ORTHO not found")
-                .clone()
-                .into();
+            // Polygons are drawn like rects
+            let ortho_program = self.assets.get_program(self.assets.manifest.get_mesh(self.meshes.rect).program);
 
             let polygon = Polygon::new(vertices, ortho_program);
             self.polygon_cache.insert(hash, polygon);
         }
         self.polygon_cache.get(&hash).unwrap()
     }
-}
\ No newline at end of file
+}
+
+// Normals of the lit shader, once per draw instead of per vertex
+fn get_normal_matrix(model: Mat4) -> Mat3 {
+    Mat3::from_mat4(model).inverse().transpose()
+}
+
+impl RenderBackend for Renderer {
+    fn get_internal_size(&self) -> Vec2 {
+        self.internal_size
+    }
+
+    fn begin_frame(&mut self) {
+        Renderer::begin_frame(self);
+    }
+
+    fn end_frame(&mut self) {
+        Renderer::end_frame(self);
+    }
+
+    fn read_frame(&self) -> RgbaImage {
+        Renderer::read_frame(self)
+    }
+
+    fn set_lights(&mut self, lights: &[Light]) {
+        Renderer::set_lights(self, lights);
+    }
+
+    fn draw_sprite_layer(&mut self, texture_id: TextureId, layer: u32, origin: Vec2, scale: f32) {
+        Renderer::draw_sprite_layer(self, texture_id, layer, origin, scale);
+    }
+
+    fn draw_polygon(&mut self, vertices: &[Vec3], position: Vec3, rotation_deg: f32, scale: f32, color: Vec4) {
+        Renderer::draw_polygon(self, vertices, position, rotation_deg, scale, color);
+    }
+
+    fn draw_line(&mut self, start: Vec2, end: Vec2, thickness: f32, color: Vec4) {
+        Renderer::draw_line(self, start, end, thickness, color);
+    }
+
+    fn draw_wall(&mut self, view_matrix: Mat4, position: Vec3, size: Vec2, rotation_y_deg: f32, color: Vec4) {
+        Renderer::draw_wall(self, view_matrix, position, size, rotation_y_deg, color);
+    }
+
+    fn load_sector_walls(&mut self, walls: &[SectorWall]) {
+        Renderer::load_sector_walls(self, walls);
+    }
+
+    fn draw_sector_walls(&mut self, view_matrix: Mat4) {
+        Renderer::draw_sector_walls(self, view_matrix);
+    }
+
+    fn draw_sector_ceiling(&mut self, view_matrix: Mat4, position: Vec3, size: Vec2, flat: &str, sector_light: u8) {
+        Renderer::draw_sector_ceiling(self, view_matrix, position, size, flat, sector_light);
+    }
+
+    fn draw_model(&mut self, view_matrix: Mat4, model_id: ModelId, position: Vec3, rotation_y_deg: f32, scale: f32) {
+        Renderer::draw_model(self, view_matrix, model_id, position, rotation_y_deg, scale);
+    }
+
+    fn draw_sky(&mut self, view_matrix: Mat4) {
+        Renderer::draw_sky(self, view_matrix);
+    }
+}
@@ -0,0 +1,69 @@
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use image::RgbaImage;
+
+use crate::graphics::light::Light;
+use crate::graphics::sector_wall::SectorWall;
+use crate::registry::model_registry::ModelId;
+use crate::registry::texture_registry::TextureId;
+
+// Renderer the scene is drawn with
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Backend {
+    Gl,
+    // Drawn on the CPU, OpenGL only shows the frame and post processes it
+    Software,
+}
+
+impl Backend {
+    // "gl" or "software"
+    pub fn parse(text: &str) -> Option<Backend> {
+        match text.to_ascii_lowercase().as_str() {
+            "gl" => Some(Backend::Gl),
+            "software" => Some(Backend::Software),
+            _ => None,
+        }
+    }
+}
+
+// What a scene can draw, implemented by the OpenGL renderer and the software rasterizer.
+// 2D positions are in internal pixels with the origin at the bottom left.
+pub trait RenderBackend {
+    // Resolution the scene is drawn at, HUD coordinates span this
+    fn get_internal_size(&self) -> Vec2;
+
+    fn begin_frame(&mut self);
+
+    fn end_frame(&mut self);
+
+    // Last finished frame, top row first
+    fn read_frame(&self) -> RgbaImage;
+
+    fn set_lights(&mut self, lights: &[Light]);
+
+    // Layer of an array texture at its own size times scale, placed like a Doom sprite with its
+    // offset on the origin, e.g. a weapon frame. Drawn over everything before it. Layers the
+    // texture doesn't have are skipped with an error.
+    fn draw_sprite_layer(&mut self, texture_id: TextureId, layer: u32, origin: Vec2, scale: f32);
+
+    // Vertices in counter-clockwise order around the origin
+    fn draw_polygon(&mut self, vertices: &[Vec3], position: Vec3, rotation_deg: f32, scale: f32, color: Vec4);
+
+    fn draw_line(&mut self, start: Vec2, end: Vec2, thickness: f32, color: Vec4);
+
+    fn draw_wall(&mut self, view_matrix: Mat4, position: Vec3, size: Vec2, rotation_y_deg: f32, color: Vec4);
+
+    // Builds the walls of the level once its assets are loaded, replacing the previous level's.
+    // Fake contrast is baked in here.
+    fn load_sector_walls(&mut self, walls: &[SectorWall]);
+
+    // Draws the walls given to load_sector_walls
+    fn draw_sector_walls(&mut self, view_matrix: Mat4);
+
+    // Position is the center, flats are texture keys in the manifest and the sky flat shows the sky
+    fn draw_sector_ceiling(&mut self, view_matrix: Mat4, position: Vec3, size: Vec2, flat: &str, sector_light: u8);
+
+    fn draw_model(&mut self, view_matrix: Mat4, model_id: ModelId, position: Vec3, rotation_y_deg: f32, scale: f32);
+
+    // Call first, the sky is drawn behind everything
+    fn draw_sky(&mut self, view_matrix: Mat4);
+}
@@ -0,0 +1,188 @@
+use gl::types::{GLint, GLuint};
+use glam::UVec2;
+use image::RgbaImage;
+
+use crate::graphics::display::Viewport;
+use crate::graphics::gl_resource::GlObject;
+use crate::graphics::texture::TextureType;
+
+// Offscreen framebuffer with a color texture and an optional depth buffer, deleted on drop
+pub struct RenderTarget {
+    fbo: GlObject,
+    color: GlObject,
+    depth: Option<GlObject>,
+
+    pub size: UVec2,
+    texture_type: TextureType,
+}
+
+impl RenderTarget {
+    pub fn new(width: u32, height: u32, texture_type: TextureType, with_depth: bool) -> RenderTarget {
+        let size = UVec2::new(width.max(1), height.max(1));
+        let (fbo, color, depth) = RenderTarget::create(size, texture_type, with_depth);
+        RenderTarget {
+            fbo,
+            color,
+            depth,
+            size,
+            texture_type,
+        }
+    }
+
+    fn create(size: UVec2, texture_type: TextureType, with_depth: bool) -> (GlObject, GlObject, Option<GlObject>) {
+        let (width, height) = (size.x as i32, size.y as i32);
+
+        let fbo = GlObject::framebuffer();
+        let color = GlObject::texture();
+        let depth = if with_depth { Some(GlObject::renderbuffer()) } else { None };
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fbo.id());
+
+            // Color attachment
+            gl::BindTexture(gl::TEXTURE_2D, color.id());
+            gl::TexImage2D(gl::TEXTURE_2D,
+                           0,
+                           gl::RGBA8 as i32,
+                           width,
+                           height,
+                           0,
+                           gl::RGBA,
+                           gl::UNSIGNED_BYTE,
+                           std::ptr::null());
+
+            let filter = match texture_type {
+                TextureType::SPRITE => gl::NEAREST,
+                TextureType::TEXTURE => gl::LINEAR,
+            };
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, color.id(), 0);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+
+            // Depth attachment
+            if let Some(depth) = &depth {
+                gl::BindRenderbuffer(gl::RENDERBUFFER, depth.id());
+                gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width, height);
+                gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, depth.id());
+                gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+            }
+
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                panic!("Framebuffer incomplete: 0x{:x}", status);
+            }
+
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+
+        (fbo, color, depth)
+    }
+
+    // Recreates the attachments, contents are lost
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let size = UVec2::new(width.max(1), height.max(1));
+        if size == self.size {
+            return;
+        }
+
+        let (fbo, color, depth) = RenderTarget::create(size, self.texture_type, self.depth.is_some());
+        self.fbo = fbo;
+        self.color = color;
+        self.depth = depth;
+        self.size = size;
+    }
+
+    // Render into this target, viewport covers the whole target
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo.id());
+            gl::Viewport(0, 0, self.size.x as i32, self.size.y as i32);
+        }
+    }
+
+    // Render into this target, limited to the viewport
+    pub fn bind_viewport(&self, viewport: Viewport) {
+        bind_framebuffer(self.fbo.id(), viewport);
+    }
+
+    // Render into the window again, limited to the viewport
+    pub fn bind_default(viewport: Viewport) {
+        bind_framebuffer(0, viewport);
+    }
+
+    // Bind the color attachment for sampling
+    pub fn bind_color(&self, unit: u32) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+            gl::BindTexture(gl::TEXTURE_2D, self.color.id());
+        }
+    }
+
+    // Replaces the color attachment with an image of the same size, top row first
+    pub fn upload(&self, image: &RgbaImage) {
+        assert_eq!(image.dimensions(), (self.size.x, self.size.y), "Uploaded image must match the render target size");
+
+        // OpenGL starts at the bottom row
+        let flipped = image::imageops::flip_vertical(image);
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.color.id());
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexSubImage2D(gl::TEXTURE_2D,
+                              0,
+                              0,
+                              0,
+                              self.size.x as i32,
+                              self.size.y as i32,
+                              gl::RGBA,
+                              gl::UNSIGNED_BYTE,
+                              flipped.as_ptr().cast());
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+    }
+
+    // Copies the color attachment back to the CPU, top row first
+    pub fn read_pixels(&self) -> RgbaImage {
+        read_framebuffer(self.fbo.id(), self.size)
+    }
+
+    // Copies the window framebuffer back to the CPU, top row first
+    pub fn read_default(size: UVec2) -> RgbaImage {
+        read_framebuffer(0, size)
+    }
+}
+
+fn bind_framebuffer(fbo_id: GLuint, viewport: Viewport) {
+    unsafe {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo_id);
+        gl::Viewport(viewport.position.x,
+                     viewport.position.y,
+                     viewport.size.x as i32,
+                     viewport.size.y as i32);
+    }
+}
+
+fn read_framebuffer(fbo_id: GLuint, size: UVec2) -> RgbaImage {
+    let mut pixels = vec![0u8; size.x as usize * size.y as usize * 4];
+    unsafe {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo_id);
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(0,
+                       0,
+                       size.x as i32,
+                       size.y as i32,
+                       gl::RGBA,
+                       gl::UNSIGNED_BYTE,
+                       pixels.as_mut_ptr().cast());
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 4);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    // OpenGL starts at the bottom row
+    let mut image = RgbaImage::from_raw(size.x, size.y, pixels).expect("Pixel buffer has wrong size");
+    image::imageops::flip_vertical_in_place(&mut image);
+    image
+}
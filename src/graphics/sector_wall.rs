@@ -0,0 +1,85 @@
+use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
+
+use crate::graphics::colormap::{self, LIGHT_LEVELS};
+use crate::registry::mesh_registry::MeshShape;
+
+// Floats per vertex: x, y, z, u, v, light
+const VERTEX_SIZE: usize = 6;
+
+// Level wall lit by its sector, walls are batched into one mesh with the light in every vertex
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SectorWall {
+    // Center of the wall
+    pub position: Vec3,
+    pub size: Vec2,
+    pub rotation_y_deg: f32,
+    // 0-255
+    pub light: u8,
+}
+
+impl SectorWall {
+    pub fn new(position: Vec3, size: Vec2, rotation_y_deg: f32, light: u8) -> SectorWall {
+        SectorWall {
+            position,
+            size,
+            rotation_y_deg,
+            light,
+        }
+    }
+
+    // Vertices in world space with the texture repeating along the wall. Fake contrast is baked
+    // into the light, a light level is 16 steps of sector light.
+    pub fn build_vertices(walls: &[SectorWall], fake_contrast: bool) -> Vec<f32> {
+        let shape = MeshShape::SectorWall.get_vbo();
+        let mut vertices = Vec::with_capacity(walls.len() * shape.len());
+        for wall in walls {
+            let model = Mat4::from_scale_rotation_translation(
+                Vec3::new(wall.size.x, wall.size.y, 1.0),
+                Quat::from_rotation_y(wall.rotation_y_deg.to_radians()),
+                wall.position);
+            let x_scale = wall.size.x / wall.size.y * 2.0;
+            let contrast = if fake_contrast { colormap::get_wall_contrast(wall.rotation_y_deg) } else { 0 };
+            let light = (wall.light as i32 + contrast * (256 / LIGHT_LEVELS)).clamp(0, 255) as f32;
+
+            for vertex in shape.chunks_exact(VERTEX_SIZE) {
+                let position = model * Vec4::new(vertex[0], vertex[1], vertex[2], 1.0);
+                vertices.extend_from_slice(&[position.x, position.y, position.z, vertex[3] * x_scale, vertex[4], light]);
+            }
+        }
+        vertices
+    }
+
+    pub fn build_indices(wall_count: usize) -> Vec<u32> {
+        let shape = MeshShape::SectorWall;
+        let quad_vertices = (shape.get_vbo().len() / VERTEX_SIZE) as u32;
+        (0..wall_count as u32)
+            .flat_map(|wall| shape.get_ebo().into_iter().map(move |index| index + wall * quad_vertices))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bakes_light_with_fake_contrast() {
+        // Along x is darker, along z brighter
+        let walls = [
+            SectorWall::new(Vec3::ZERO, Vec2::new(4.0, 2.0), 0.0, 160),
+            SectorWall::new(Vec3::new(10.0, 0.0, 0.0), Vec2::new(4.0, 2.0), 90.0, 250),
+        ];
+
+        let vertices = SectorWall::build_vertices(&walls, true);
+        let lights: Vec<f32> = vertices.chunks_exact(VERTEX_SIZE).map(|vertex| vertex[5]).collect();
+        assert_eq!(lights, [144.0, 144.0, 144.0, 144.0, 255.0, 255.0, 255.0, 255.0]);
+        // World space, the texture repeats along the wall
+        assert_eq!(&vertices[..5], &[-2.0, -1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(vertices[VERTEX_SIZE + 3], 4.0);
+
+        let lights: Vec<f32> = SectorWall::build_vertices(&walls, false).chunks_exact(VERTEX_SIZE).map(|vertex| vertex[5]).collect();
+        assert_eq!(lights[0], 160.0);
+
+        assert_eq!(SectorWall::build_indices(2), [0, 1, 2, 2, 3, 0, 4, 5, 6, 6, 7, 4]);
+    }
+}
@@ -1,17 +1,30 @@
-use std::path::Path;
+use std::fmt;
+use std::path::{Path, PathBuf};
 
 use gl::types::GLuint;
-use glfw::Error;
 
-use crate::assets::shader_loader;
+use crate::assets::shader_loader::{self, ShaderDefine};
+use crate::assets::vfs::Vfs;
+use crate::graphics::gl_resource::GlObject;
+use crate::graphics::shader_error::{ShaderError, SourceMap};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ShaderType {
     Vertex,
     Fragment,
 }
 
+impl fmt::Display for ShaderType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderType::Vertex => write!(f, "Vertex"),
+            ShaderType::Fragment => write!(f, "Fragment"),
+        }
+    }
+}
 
-fn compile_shader(id: GLuint, source: &str) -> Result<(), Error> {
+// Returns the raw info log on failure
+fn compile_shader(id: GLuint, source: &str) -> Result<(), String> {
     unsafe {
         gl::ShaderSource(id, 1, &source.as_bytes().as_ptr().cast(), &source.len().try_into().unwrap());
         gl::CompileShader(id);
@@ -31,51 +44,52 @@ fn compile_shader(id: GLuint, source: &str) -> Result<(), Error> {
                                      log.as_mut_ptr().cast());
 
                 log.set_len(log_length as usize);
-                let error_log = String::from_utf8_lossy(&log);
-                panic!("Vertex Shader Compile Error: {}", error_log);
+                return Err(String::from_utf8_lossy(&log).trim_end_matches('\0').to_string());
             }
-            panic!("Vertex Shader Compile Error: Unknown");
+            return Err("Unknown error".to_string());
         }
     }
 
     Ok(())
 }
 
-#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Shader {
-    pub id: GLuint,
+    pub object: GlObject,
+    pub shader_type: ShaderType,
+    pub path: PathBuf,
+    // Preprocessed, includes resolved and defines added
     pub source: String,
+    pub source_map: SourceMap,
+    // The shader file and everything it includes
+    pub files: Vec<PathBuf>,
 }
 
 impl Shader {
-    pub fn new(shader_type: ShaderType, shader_path: impl AsRef<Path> ) -> Self {
-        let id= unsafe {
-            match shader_type {
-                ShaderType::Fragment => {
-                    gl::CreateShader(gl::FRAGMENT_SHADER)
-                },
-                ShaderType::Vertex => {
-                    gl::CreateShader(gl::VERTEX_SHADER)
-                }
-            }
+    // Includes are resolved relative to the shader root
+    pub fn new(vfs: &Vfs, shader_type: ShaderType, root: impl AsRef<Path>, shader_path: impl AsRef<Path>, defines: &[ShaderDefine]) -> Result<Self, ShaderError> {
+        let path = shader_path.as_ref().to_path_buf();
+        let loaded = shader_loader::load_shader_source(vfs, root, &path, defines)?;
+
+        let object = match shader_type {
+            ShaderType::Fragment => GlObject::shader(gl::FRAGMENT_SHADER),
+            ShaderType::Vertex => GlObject::shader(gl::VERTEX_SHADER),
         };
 
-
-        Self {
-            id: id,
-            source: shader_loader::load_shader_source(shader_path),
-        }
+        Ok(Self {
+            object,
+            shader_type,
+            path,
+            source: loaded.source,
+            source_map: loaded.source_map,
+            files: loaded.files,
+        })
     }
 
-    pub fn compile(&self) -> Result<(), Error> {
-        compile_shader(self.id, &self.source)
+    pub fn compile(&self) -> Result<(), ShaderError> {
+        compile_shader(self.object.id(), &self.source).map_err(|log| ShaderError::Compile {
+            stage: self.shader_type,
+            path: self.path.clone(),
+            log: self.source_map.map_log(&log),
+        })
     }
-
-    pub fn delete(&self) {
-        unsafe {
-            gl::DeleteShader(self.id);
-        }
-    }
-
 }
-
@@ -0,0 +1,130 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::assets::vfs::VfsError;
+use crate::graphics::shader::ShaderType;
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Read(VfsError),
+    // Bad or cyclic #include
+    Preprocess {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    Compile {
+        stage: ShaderType,
+        path: PathBuf,
+        // Driver log with line numbers mapped back to the source files
+        log: String,
+    },
+    Link {
+        vertex_path: PathBuf,
+        fragment_path: PathBuf,
+        log: String,
+    },
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Read(error) => {
+                write!(f, "Failed to read shader: {}", error)
+            }
+            ShaderError::Preprocess { path, line, message } => {
+                write!(f, "Failed to preprocess shader\n{}:{}: {}", path.display(), line, message)
+            }
+            ShaderError::Compile { stage, path, log } => {
+                write!(f, "{} shader compile error in {}:\n{}", stage, path.display(), log)
+            }
+            ShaderError::Link { vertex_path, fragment_path, log } => {
+                write!(f, "Program link error ({} + {}):\n{}", vertex_path.display(), fragment_path.display(), log)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+// Which file and line every line of the compiled source came from
+#[derive(Debug, Clone)]
+pub struct SourceMap {
+    lines: Vec<(PathBuf, usize)>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self {
+            lines: Vec::new(),
+        }
+    }
+
+    // Appends the origin of the next line of compiled source
+    pub fn push(&mut self, path: &Path, line: usize) {
+        self.lines.push((path.to_path_buf(), line));
+    }
+
+    // Line numbers start at 1 like in the driver logs
+    pub fn locate(&self, line: usize) -> Option<(&Path, usize)> {
+        let (path, source_line) = self.lines.get(line.checked_sub(1)?)?;
+        Some((path.as_path(), *source_line))
+    }
+
+    // Prefixes every log line the driver tagged with a line number with `file:line:`
+    pub fn map_log(&self, log: &str) -> String {
+        log.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| match parse_log_line_number(line).and_then(|number| self.locate(number)) {
+                Some((path, source_line)) => format!("{}:{}: {}", path.display(), source_line, line.trim()),
+                None => line.trim().to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+// Drivers prefix messages with `<source string>:<line>` or `<source string>(<line>)`, e.g.
+// Mesa `0:12(5): error: ...`, NVIDIA `0(12) : error C0000: ...`, AMD `ERROR: 0:12: ...`
+fn parse_log_line_number(log_line: &str) -> Option<usize> {
+    let mut rest = log_line.trim_start();
+    for prefix in ["ERROR:", "WARNING:"] {
+        if let Some(stripped) = rest.strip_prefix(prefix) {
+            rest = stripped.trim_start();
+        }
+    }
+
+    let string_end = rest.find(|c: char| !c.is_ascii_digit())?;
+    if string_end == 0 {
+        return None;
+    }
+
+    let rest = &rest[string_end..];
+    let rest = rest.strip_prefix(':').or_else(|| rest.strip_prefix('('))?;
+    let line_end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    rest[..line_end].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_driver_log_formats() {
+        assert_eq!(parse_log_line_number("0:12(5): error: `foo' undeclared"), Some(12));
+        assert_eq!(parse_log_line_number("0(7) : error C0000: syntax error"), Some(7));
+        assert_eq!(parse_log_line_number("ERROR: 0:3: 'bar' : undeclared identifier"), Some(3));
+        assert_eq!(parse_log_line_number("error: linking failed"), None);
+    }
+
+    #[test]
+    fn maps_log_to_source_file() {
+        let mut source_map = SourceMap::new();
+        for line in 1..=3 {
+            source_map.push(Path::new("shaders/lit/fragment.glsl"), line);
+        }
+        let log = "0:2(1): error: syntax error\nerror: compilation failed\n";
+        assert_eq!(source_map.map_log(log),
+                   "shaders/lit/fragment.glsl:2: 0:2(1): error: syntax error\nerror: compilation failed");
+    }
+}
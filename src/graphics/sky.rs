@@ -0,0 +1,30 @@
+// Ceilings using this flat show the sky instead, like in Doom
+pub const SKY_FLAT: &str = "F_SKY1";
+
+// Doom's SKY1 is 256 wide and wraps around 4 times per revolution
+pub const SKY_REPEAT: f32 = 4.0;
+// Roughly how much of the view the 128 tall sky covers above the horizon
+pub const SKY_HEIGHT_DEG: f32 = 60.0;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SkyMode {
+    None,
+    // Six sided cubemap skybox
+    Cubemap,
+    // Doom style sky texture wrapped around the camera
+    Cylindrical,
+}
+
+impl SkyMode {
+    pub fn next(self) -> SkyMode {
+        match self {
+            SkyMode::None => SkyMode::Cubemap,
+            SkyMode::Cubemap => SkyMode::Cylindrical,
+            SkyMode::Cylindrical => SkyMode::None,
+        }
+    }
+}
+
+pub fn is_sky_flat(flat_name: &str) -> bool {
+    flat_name.eq_ignore_ascii_case(SKY_FLAT)
+}
@@ -0,0 +1,736 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use glam::{IVec2, Mat3, Mat4, Quat, Vec2, Vec3, Vec4};
+use image::RgbaImage;
+
+use crate::assets::asset_root::MANIFEST_FILE;
+use crate::assets::manifest::{Manifest, MeshEntry, TextureSource};
+use crate::assets::vfs::Vfs;
+use crate::graphics::colormap::{self, Colormap, INDEX_LUT_SIZE, LightMode, NUM_COLORMAPS};
+use crate::graphics::light::{Light, MIN_SPOT_EPSILON};
+use crate::graphics::material::Material;
+use crate::graphics::model::{Model, ModelGeometry};
+use crate::graphics::palette::Palette;
+use crate::graphics::rasterizer::{self, ClipVertex, Framebuffer, Varyings};
+use crate::graphics::render_backend::RenderBackend;
+use crate::graphics::sector_wall::SectorWall;
+use crate::graphics::sky::{self, SKY_HEIGHT_DEG, SKY_REPEAT, SkyMode};
+use crate::graphics::texture;
+use crate::registry::mesh_registry::{BuiltinMeshes, MeshShape};
+use crate::registry::model_registry::ModelId;
+use crate::registry::texture_registry::TextureId;
+
+// Draws on the CPU into an image, no OpenGL context needed.
+// Shaded per pixel like the GL shaders, textures are sampled with perspective correction.
+// Post processing is left to the GL renderer that presents the frame.
+pub struct SoftwareRenderer {
+    framebuffer: Framebuffer,
+    // Last finished frame
+    frame: RgbaImage,
+    clear_color: Vec4,
+    orthographic_projection: Mat4,
+    perspective_projection: Mat4,
+    // Width / height the frame is shown at, None uses the framebuffer's
+    display_aspect: Option<f32>,
+
+    // Lighting
+    lights: Vec<Light>,
+    pub ambient_light: Vec3,
+    pub blinn_phong: bool,
+
+    pub sky_mode: SkyMode,
+
+    // Sector lighting
+    palette: Palette,
+    colormap: Colormap,
+    // Color to palette index, same table the sector shader samples
+    index_lut: Vec<u8>,
+    pub light_mode: LightMode,
+    pub fake_contrast: bool,
+    // Map units per world unit, for distance based light falloff
+    pub light_distance_scale: f32,
+
+    // Same manifest and meshes as the GL renderer, for their shapes and textures
+    vfs: Arc<Vfs>,
+    manifest: Manifest,
+    meshes: BuiltinMeshes,
+    // Loaded on first use
+    textures: HashMap<TextureId, RgbaImage>,
+    // Array texture layers and cubemap faces with their sprite offsets
+    texture_layers: HashMap<TextureId, Vec<RgbaImage>>,
+    layer_offsets: HashMap<TextureId, Vec<IVec2>>,
+    models: HashMap<ModelId, Vec<ModelGeometry>>,
+    // Batched walls of the current level
+    sector_wall_vertices: Vec<f32>,
+    sector_wall_indices: Vec<u32>,
+}
+
+impl SoftwareRenderer {
+    // Only files are read through the vfs, no GL objects are created
+    pub fn new(width: u32, height: u32, vfs: Arc<Vfs>) -> SoftwareRenderer {
+        let palette = Palette::fallback();
+        let colormap = Colormap::generate(&palette);
+        let framebuffer = Framebuffer::new(width, height);
+        let manifest = Manifest::load(&vfs, MANIFEST_FILE).unwrap_or_else(|error| panic!("{}", error));
+
+        let mut renderer = SoftwareRenderer {
+            frame: framebuffer.get_image().clone(),
+            framebuffer,
+            clear_color: Vec4::new(0.8, 0.87, 0.95, 1.0),
+            orthographic_projection: Mat4::IDENTITY,
+            perspective_projection: Mat4::IDENTITY,
+            display_aspect: None,
+            lights: Vec::new(),
+            ambient_light: Vec3::splat(0.3),
+            blinn_phong: true,
+            sky_mode: SkyMode::Cylindrical,
+            index_lut: palette.build_index_lut(INDEX_LUT_SIZE),
+            palette,
+            colormap,
+            light_mode: LightMode::Palette,
+            fake_contrast: true,
+            light_distance_scale: 8.0,
+            meshes: BuiltinMeshes::resolve(&manifest),
+            vfs,
+            manifest,
+            textures: HashMap::new(),
+            texture_layers: HashMap::new(),
+            layer_offsets: HashMap::new(),
+            models: HashMap::new(),
+            sector_wall_vertices: Vec::new(),
+            sector_wall_indices: Vec::new(),
+        };
+        renderer.update_projections();
+        if let Some((palette, colormap)) = colormap::load_lumps(&renderer.vfs) {
+            renderer.set_palette(&palette, &colormap);
+        }
+        renderer
+    }
+
+    fn update_projections(&mut self) {
+        let size = Vec2::new(self.framebuffer.width() as f32, self.framebuffer.height() as f32);
+        self.orthographic_projection = Mat4::orthographic_rh_gl(0.0, size.x, 0.0, size.y, -1.0, 1.0);
+        let aspect = self.display_aspect.unwrap_or(size.x / size.y);
+        self.perspective_projection = Mat4::perspective_rh_gl(90.0_f32.to_radians(), aspect, 0.1, 1000.0);
+    }
+
+    // Same as the GL renderer's, e.g. 4:3 when 320x200 is aspect corrected
+    pub fn set_display_aspect(&mut self, aspect: f32) {
+        if self.display_aspect != Some(aspect) {
+            self.display_aspect = Some(aspect);
+            self.update_projections();
+        }
+    }
+
+    // Follows the internal resolution of the GL renderer, the current frame is lost
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if (width.max(1), height.max(1)) == (self.framebuffer.width(), self.framebuffer.height()) {
+            return;
+        }
+
+        self.framebuffer = Framebuffer::new(width, height);
+        self.frame = self.framebuffer.get_image().clone();
+        self.update_projections();
+    }
+
+    pub fn get_manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    // Palette and colormap used for palette lighting, e.g. from a WAD
+    pub fn set_palette(&mut self, palette: &Palette, colormap: &Colormap) {
+        self.index_lut = palette.build_index_lut(INDEX_LUT_SIZE);
+        self.palette = palette.clone();
+        self.colormap = colormap.clone();
+    }
+
+    fn load_texture(&mut self, texture_id: TextureId) {
+        self.textures.entry(texture_id).or_insert_with(|| {
+            match &self.manifest.get_texture(texture_id).source {
+                TextureSource::Image(path) => texture::load_image(&self.vfs, path).to_rgba8(),
+                _ => panic!("Software renderer only samples 2D image textures"),
+            }
+        });
+    }
+
+    fn load_texture_layers(&mut self, texture_id: TextureId) {
+        if self.texture_layers.contains_key(&texture_id) {
+            return;
+        }
+
+        let (layers, offsets) = match &self.manifest.get_texture(texture_id).source {
+            TextureSource::Layers(paths) => paths.iter()
+                .map(|path| {
+                    let (image, offset) = texture::load_sprite(&self.vfs, path);
+                    (image.to_rgba8(), offset)
+                })
+                .unzip(),
+            // First row at the top, like the GL cubemap
+            TextureSource::Cubemap(folder) => texture::CUBEMAP_FACES.iter()
+                .map(|face| (texture::load_image(&self.vfs, &folder.join(face)).to_rgba8(), IVec2::ZERO))
+                .unzip(),
+            TextureSource::Image(_) => panic!("Texture is not an array texture or cubemap"),
+        };
+        self.texture_layers.insert(texture_id, layers);
+        self.layer_offsets.insert(texture_id, offsets);
+    }
+
+    // Loads the texture of a textured mesh
+    fn load_mesh_texture(&mut self, mesh: MeshEntry) -> TextureId {
+        let texture_id = mesh.texture.expect("Mesh has no texture");
+        self.load_texture(texture_id);
+        texture_id
+    }
+
+    fn load_model(&mut self, model_id: ModelId) {
+        self.models.entry(model_id).or_insert_with(|| {
+            Model::load_geometry(&self.vfs, &self.manifest.get_model(model_id).path)
+        });
+    }
+
+    // Sector lit surface, vertices are position, uv and sector light
+    fn draw_sector_surface(&mut self, view_matrix: Mat4, model: Mat4, vbo: &[f32], ebo: &[u32], texture_id: TextureId, x_scale: f32) {
+        let transform = self.perspective_projection * view_matrix * model;
+
+        // Distance along the view direction, like Doom's z
+        let vertices: Vec<ClipVertex> = vbo
+            .chunks_exact(6)
+            .map(|vertex| {
+                let position = Vec4::new(vertex[0], vertex[1], vertex[2], 1.0);
+                let depth = -(view_matrix * model * position).z;
+                ClipVertex::new(transform * position, rasterizer::varyings(&[vertex[3] * x_scale, vertex[4], depth, vertex[5]]))
+            })
+            .collect();
+
+        let texture = &self.textures[&texture_id];
+        let (palette, colormap, index_lut) = (&self.palette, &self.colormap, &self.index_lut);
+        let (light_mode, light_distance_scale) = (self.light_mode, self.light_distance_scale);
+        let mut shade = |varyings: &Varyings| {
+            let color = rasterizer::sample_nearest(texture, Vec2::new(varyings[0], varyings[1]));
+
+            // Sprites and masked textures
+            if color.w < 0.5 {
+                return None;
+            }
+
+            let light_index = colormap::get_light_index(varyings[3].round() as u8, 0);
+            let row = colormap::get_colormap_row(light_index, varyings[2] * light_distance_scale);
+            match light_mode {
+                LightMode::Palette => Some(apply_colormap(palette, colormap, index_lut, color, row)),
+                LightMode::Smooth => Some((color.truncate() * (1.0 - row as f32 / NUM_COLORMAPS as f32)).extend(1.0)),
+            }
+        };
+
+        for triangle in ebo.chunks_exact(3) {
+            let triangle = [vertices[triangle[0] as usize], vertices[triangle[1] as usize], vertices[triangle[2] as usize]];
+            self.framebuffer.draw_triangle(triangle, &mut shade);
+        }
+    }
+
+    // Ceiling quad showing the sky seen through it, like the sky shader with background off
+    fn draw_sky_ceiling(&mut self, view_matrix: Mat4, model: Mat4) {
+        let transform = self.perspective_projection * view_matrix * model;
+        let camera_position = view_matrix.inverse().w_axis.truncate();
+        let mesh = *self.manifest.get_mesh(self.meshes.sky_ceiling);
+        let vertices: Vec<ClipVertex> = mesh.shape.get_vbo()
+            .chunks_exact(6)
+            .map(|vertex| {
+                let position = Vec4::new(vertex[0], vertex[1], vertex[2], 1.0);
+                let direction = (model * position).truncate() - camera_position;
+                ClipVertex::new(transform * position, rasterizer::varyings(&direction.to_array()))
+            })
+            .collect();
+
+        let texture_id = self.load_mesh_texture(mesh);
+        let texture = &self.textures[&texture_id];
+        let mut shade = |varyings: &Varyings| {
+            let direction = Vec3::new(varyings[0], varyings[1], varyings[2]);
+            Some(sample_sky(texture, direction))
+        };
+
+        for triangle in mesh.shape.get_ebo().chunks_exact(3) {
+            let triangle = [vertices[triangle[0] as usize], vertices[triangle[1] as usize], vertices[triangle[2] as usize]];
+            self.framebuffer.draw_triangle(triangle, &mut shade);
+        }
+    }
+
+    // Flat colored 2D shape, triangles index into positions
+    fn draw_flat(&mut self, positions: &[Vec3], indices: &[u32], model: Mat4, color: Vec4) {
+        let transform = self.orthographic_projection * model;
+        let vertices: Vec<ClipVertex> = positions.iter()
+            .map(|position| ClipVertex::new(transform * position.extend(1.0), [0.0; rasterizer::VARYINGS]))
+            .collect();
+
+        let mut shade = |_: &Varyings| Some(color);
+        for triangle in indices.chunks_exact(3) {
+            let triangle = [vertices[triangle[0] as usize], vertices[triangle[1] as usize], vertices[triangle[2] as usize]];
+            self.framebuffer.draw_triangle(triangle, &mut shade);
+        }
+    }
+
+    // Blinn-Phong per pixel like the lit shader, uv, world position and normal are interpolated
+    fn draw_lit(&mut self, view_matrix: Mat4, model: Mat4, vbo: &[f32], ebo: &[u32], material: Material, texture: Option<(TextureId, f32)>) {
+        let transform = self.perspective_projection * view_matrix * model;
+        let normal_matrix = Mat3::from_mat4(model).inverse().transpose();
+        // Camera position is the translation of the inverse view matrix
+        let view_position = view_matrix.inverse().w_axis.truncate();
+
+        let width_scale = texture.map(|(_, scale)| scale).unwrap_or(1.0);
+        let vertices: Vec<ClipVertex> = vbo.chunks_exact(8)
+            .map(|vertex| {
+                let position = Vec3::new(vertex[0], vertex[1], vertex[2]);
+                let world_position = model.transform_point3(position);
+                let normal = normal_matrix * Vec3::new(vertex[5], vertex[6], vertex[7]);
+                ClipVertex::new(transform * position.extend(1.0),
+                                rasterizer::varyings(&[vertex[3] * width_scale, vertex[4],
+                                                       world_position.x, world_position.y, world_position.z,
+                                                       normal.x, normal.y, normal.z]))
+            })
+            .collect();
+
+        if let Some((texture_id, _)) = texture {
+            self.load_texture(texture_id);
+        }
+        let texture = texture.map(|(texture_id, _)| &self.textures[&texture_id]);
+
+        let (lights, ambient_light, blinn_phong) = (&self.lights, self.ambient_light, self.blinn_phong);
+        let mut shade = |varyings: &Varyings| {
+            let mut base = material.diffuse.extend(1.0);
+            if let Some(texture) = texture {
+                base *= rasterizer::sample_nearest(texture, Vec2::new(varyings[0], varyings[1]));
+            }
+
+            let world_position = rasterizer::get_varying3(varyings, 2);
+            let normal = rasterizer::get_varying3(varyings, 5).normalize_or(Vec3::Y);
+            let view_direction = (view_position - world_position).normalize_or(Vec3::Z);
+
+            let mut diffuse = ambient_light;
+            let mut specular = Vec3::ZERO;
+            for light in lights {
+                let (light_diffuse, light_specular) = calculate_light(light, world_position, normal, view_direction, &material, blinn_phong);
+                diffuse += light_diffuse;
+                specular += light_specular;
+            }
+
+            Some((ambient_light * material.ambient + diffuse * base.truncate() + specular).extend(base.w))
+        };
+
+        for triangle in ebo.chunks_exact(3) {
+            let triangle = [vertices[triangle[0] as usize], vertices[triangle[1] as usize], vertices[triangle[2] as usize]];
+            self.framebuffer.draw_triangle(triangle, &mut shade);
+        }
+    }
+}
+
+// Remaps a color through the palette and a colormap row, like the sector shader
+fn apply_colormap(palette: &Palette, colormap: &Colormap, index_lut: &[u8], color: Vec4, row: usize) -> Vec4 {
+    let step = |c: f32| (c.clamp(0.0, 1.0) * (INDEX_LUT_SIZE - 1) as f32).round() as usize;
+    let lut_index = step(color.x) + step(color.y) * INDEX_LUT_SIZE + step(color.z) * INDEX_LUT_SIZE * INDEX_LUT_SIZE;
+    let mapped = colormap.maps[row][index_lut[lut_index] as usize] as usize;
+    let [r, g, b] = palette.colors[mapped];
+    Vec4::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0)
+}
+
+// Same mapping as the sky shader, the sky wraps around and spans SKY_HEIGHT_DEG above the horizon
+fn sample_sky(texture: &RgbaImage, direction: Vec3) -> Vec4 {
+    let direction = direction.normalize();
+    let u = direction.z.atan2(direction.x) / (2.0 * PI) * SKY_REPEAT;
+    let v = (direction.y.clamp(-1.0, 1.0).asin() / SKY_HEIGHT_DEG.to_radians()).clamp(0.0, 0.999);
+    rasterizer::sample_nearest(texture, Vec2::new(u, v))
+}
+
+// Face and coordinates picked by the largest axis of the direction, like a GL cubemap lookup.
+// Faces are in GL order with their first row at the top.
+fn sample_cubemap(faces: &[RgbaImage], direction: Vec3) -> Vec4 {
+    let absolute = direction.abs();
+    let (face, major, s, t) = if absolute.x >= absolute.y && absolute.x >= absolute.z {
+        if direction.x > 0.0 { (0, absolute.x, -direction.z, -direction.y) } else { (1, absolute.x, direction.z, -direction.y) }
+    } else if absolute.y >= absolute.z {
+        if direction.y > 0.0 { (2, absolute.y, direction.x, direction.z) } else { (3, absolute.y, direction.x, -direction.z) }
+    } else if direction.z > 0.0 {
+        (4, absolute.z, direction.x, -direction.y)
+    } else {
+        (5, absolute.z, -direction.x, -direction.y)
+    };
+
+    let image = &faces[face];
+    let (width, height) = image.dimensions();
+    let x = (((s / major + 1.0) / 2.0 * width as f32) as u32).min(width - 1);
+    let y = (((t / major + 1.0) / 2.0 * height as f32) as u32).min(height - 1);
+    Vec4::from_array(image.get_pixel(x, y).0.map(|c| c as f32 / 255.0))
+}
+
+// Quad sampled per pixel without lighting, transform takes it to clip space
+fn draw_textured_quad(framebuffer: &mut Framebuffer, shape: MeshShape, transform: Mat4, texture: &RgbaImage) {
+    let vertices: Vec<ClipVertex> = shape.get_vbo()
+        .chunks_exact(5)
+        .map(|vertex| ClipVertex::new(transform * Vec4::new(vertex[0], vertex[1], vertex[2], 1.0),
+                                      rasterizer::varyings(&[vertex[3], vertex[4]])))
+        .collect();
+
+    let mut shade = |varyings: &Varyings| Some(rasterizer::sample_nearest(texture, Vec2::new(varyings[0], varyings[1])));
+    for triangle in shape.get_ebo().chunks_exact(3) {
+        let triangle = [vertices[triangle[0] as usize], vertices[triangle[1] as usize], vertices[triangle[2] as usize]];
+        framebuffer.draw_triangle(triangle, &mut shade);
+    }
+}
+
+// Diffuse and specular light from a single light, same as calculateLight in the lit shader
+fn calculate_light(light: &Light, position: Vec3, normal: Vec3, view_direction: Vec3, material: &Material, blinn: bool) -> (Vec3, Vec3) {
+    let (light_direction, color, attenuation) = match light {
+        Light::Directional { direction, color } => (-direction.normalize(), *color, 1.0),
+        Light::Point { position: light_position, color, attenuation } |
+        Light::Spot { position: light_position, color, attenuation, .. } => {
+            let to_light = *light_position - position;
+            let distance = to_light.length();
+            let mut strength = 1.0 / (attenuation.constant + attenuation.linear * distance + attenuation.quadratic * distance * distance);
+
+            if let Light::Spot { direction, inner_cutoff_deg, outer_cutoff_deg, .. } = light {
+                let theta = (to_light / distance).dot(-direction.normalize());
+                let (inner, outer) = (inner_cutoff_deg.to_radians().cos(), outer_cutoff_deg.to_radians().cos());
+                strength *= ((theta - outer) / (inner - outer).max(MIN_SPOT_EPSILON)).clamp(0.0, 1.0);
+            }
+            (to_light / distance, *color, strength)
+        }
+    };
+
+    let diffuse_strength = normal.dot(light_direction).max(0.0);
+    let mut specular_strength = 0.0;
+    if diffuse_strength > 0.0 {
+        specular_strength = if blinn {
+            let halfway = (light_direction + view_direction).normalize();
+            normal.dot(halfway).max(0.0).powf(material.shininess)
+        } else {
+            let reflect_direction = -light_direction - 2.0 * normal.dot(-light_direction) * normal;
+            view_direction.dot(reflect_direction).max(0.0).powf(material.shininess)
+        };
+    }
+
+    (color * diffuse_strength * attenuation, color * specular_strength * material.specular * attenuation)
+}
+
+impl RenderBackend for SoftwareRenderer {
+    fn get_internal_size(&self) -> Vec2 {
+        Vec2::new(self.framebuffer.width() as f32, self.framebuffer.height() as f32)
+    }
+
+    fn begin_frame(&mut self) {
+        self.framebuffer.clear(self.clear_color);
+    }
+
+    // Post processing runs in the GL renderer when it presents the frame
+    fn end_frame(&mut self) {
+        self.frame = self.framebuffer.get_image().clone();
+    }
+
+    fn read_frame(&self) -> RgbaImage {
+        self.frame.clone()
+    }
+
+    fn set_lights(&mut self, lights: &[Light]) {
+        self.lights = lights.to_vec();
+    }
+
+    // Layers keep their own size here, there is no padding to skip
+    fn draw_sprite_layer(&mut self, texture_id: TextureId, layer: u32, origin: Vec2, scale: f32) {
+        self.load_texture_layers(texture_id);
+        let layers = self.texture_layers.get(&texture_id).zip(self.layer_offsets.get(&texture_id));
+        let Some((image, offset)) = layers.and_then(|(images, offsets)| images.get(layer as usize).zip(offsets.get(layer as usize))) else {
+            eprintln!("Texture '{}' has no layer {}, skipping the sprite", self.manifest.get_texture_key(texture_id), layer);
+            return;
+        };
+        let offset = offset.as_vec2();
+        let size = Vec2::new(image.width() as f32, image.height() as f32) * scale;
+        // The offset is right of and below the top left corner, y is up here
+        let top_left = origin + Vec2::new(-offset.x, offset.y) * scale;
+        let model = Mat4::from_scale_rotation_translation(
+            Vec3::new(size.x, size.y, 1.0),
+            Quat::IDENTITY,
+            Vec3::new(top_left.x + size.x / 2.0, top_left.y - size.y / 2.0, 0.0));
+
+        // HUD sprites overlap at the same depth, and their transparent padding must not hide what's drawn next
+        let shape = self.manifest.get_mesh(self.meshes.layered_rect).shape;
+        self.framebuffer.set_depth_test(false);
+        draw_textured_quad(&mut self.framebuffer, shape, self.orthographic_projection * model, image);
+        self.framebuffer.set_depth_test(true);
+    }
+
+    fn draw_polygon(&mut self, vertices: &[Vec3], position: Vec3, rotation_deg: f32, scale: f32, color: Vec4) {
+        if vertices.len() < 3 {
+            panic!("Polygon must have at least 3 vertices");
+        }
+
+        let model = Mat4::from_scale_rotation_translation(
+            Vec3::splat(scale),
+            Quat::from_rotation_z(rotation_deg.to_radians()),
+            position,
+        );
+
+        // Triangulate using fan method, like Polygon
+        let indices: Vec<u32> = (1..vertices.len() as u32 - 1)
+            .flat_map(|i| [0, i, i + 1])
+            .collect();
+        self.draw_flat(vertices, &indices, model, color);
+    }
+
+    fn draw_line(&mut self, start: Vec2, end: Vec2, thickness: f32, color: Vec4) {
+        let length = (end - start).length();
+        let midpoint = (start + end) / 2.0;
+        let angle = (end - start).y.atan2(end.x - start.x);
+        let model = Mat4::from_scale_rotation_translation(
+            Vec3::new(length, thickness, 1.0),
+            Quat::from_rotation_z(angle),
+            midpoint.extend(0.0));
+
+        let corners = [Vec3::new(-0.5, -0.5, 0.0), Vec3::new(0.5, -0.5, 0.0), Vec3::new(0.5, 0.5, 0.0), Vec3::new(-0.5, 0.5, 0.0)];
+        self.draw_flat(&corners, &[0, 1, 2, 2, 3, 0], model, color);
+    }
+
+    fn draw_wall(&mut self, view_matrix: Mat4, position: Vec3, size: Vec2, rotation_y_deg: f32, color: Vec4) {
+        let x_scale = size.x / size.y * 2.0;
+        let model = Mat4::from_scale_rotation_translation(
+            Vec3::new(size.x, size.y, 1.0),
+            Quat::from_rotation_y(rotation_y_deg.to_radians()),
+            position);
+
+        let material = Material {
+            diffuse: color.truncate(),
+            ..Material::plain()
+        };
+
+        let mesh = *self.manifest.get_mesh(self.meshes.wall);
+        self.draw_lit(view_matrix,
+                      model,
+                      &mesh.shape.get_vbo(),
+                      &mesh.shape.get_ebo(),
+                      material,
+                      Some((mesh.texture.expect("Wall mesh has no texture"), x_scale)));
+    }
+
+    fn load_sector_walls(&mut self, walls: &[SectorWall]) {
+        self.sector_wall_vertices = SectorWall::build_vertices(walls, self.fake_contrast);
+        self.sector_wall_indices = SectorWall::build_indices(walls.len());
+    }
+
+    // Uses the same colormap rows as the sector shader
+    fn draw_sector_walls(&mut self, view_matrix: Mat4) {
+        if self.sector_wall_indices.is_empty() {
+            return;
+        }
+
+        let texture_id = self.load_mesh_texture(*self.manifest.get_mesh(self.meshes.sector_wall));
+        // Taken while drawing, the surface borrows the renderer
+        let vertices = std::mem::take(&mut self.sector_wall_vertices);
+        let indices = std::mem::take(&mut self.sector_wall_indices);
+        self.draw_sector_surface(view_matrix, Mat4::IDENTITY, &vertices, &indices, texture_id, 1.0);
+        self.sector_wall_vertices = vertices;
+        self.sector_wall_indices = indices;
+    }
+
+    fn draw_sector_ceiling(&mut self, view_matrix: Mat4, position: Vec3, size: Vec2, flat: &str, sector_light: u8) {
+        let model = Mat4::from_scale_rotation_translation(
+            Vec3::new(size.x, 1.0, size.y),
+            Quat::IDENTITY,
+            position);
+
+        if sky::is_sky_flat(flat) {
+            self.draw_sky_ceiling(view_matrix, model);
+            return;
+        }
+
+        let texture_id = self.manifest.get_texture_id(flat);
+        self.load_texture(texture_id);
+        // Same light in every vertex
+        let shape = self.manifest.get_mesh(self.meshes.sector_ceiling).shape;
+        let mut vertices = shape.get_vbo();
+        for vertex in vertices.chunks_exact_mut(6) {
+            vertex[5] = sector_light as f32;
+        }
+        self.draw_sector_surface(view_matrix, model, &vertices, &shape.get_ebo(), texture_id, size.x / size.y);
+    }
+
+    fn draw_model(&mut self, view_matrix: Mat4, model_id: ModelId, position: Vec3, rotation_y_deg: f32, scale: f32) {
+        let model = Mat4::from_scale_rotation_translation(
+            Vec3::splat(scale),
+            Quat::from_rotation_y(rotation_y_deg.to_radians()),
+            position);
+
+        self.load_model(model_id);
+        let parts = std::mem::take(self.models.get_mut(&model_id).unwrap());
+        for part in &parts {
+            self.draw_lit(view_matrix, model, &part.vertices, &part.indices, part.material, None);
+        }
+        self.models.insert(model_id, parts);
+    }
+
+    fn draw_sky(&mut self, view_matrix: Mat4) {
+        let mesh_id = match self.sky_mode {
+            SkyMode::None => return,
+            SkyMode::Cubemap => self.meshes.skybox,
+            SkyMode::Cylindrical => self.meshes.sky_background,
+        };
+
+        // Strip translation, the sky moves with the camera
+        let view_rotation = Mat4::from_mat3(Mat3::from_mat4(view_matrix));
+        let inverse = (self.perspective_projection * view_rotation).inverse();
+        let direction = move |ndc: Vec2| {
+            let far = inverse * Vec4::new(ndc.x, ndc.y, 1.0, 1.0);
+            far.truncate() / far.w
+        };
+
+        let texture_id = self.manifest.get_mesh(mesh_id).texture.expect("Sky mesh has no texture");
+        if self.sky_mode == SkyMode::Cubemap {
+            self.load_texture_layers(texture_id);
+            let faces = &self.texture_layers[&texture_id];
+            self.framebuffer.shade_background(|ndc| sample_cubemap(faces, direction(ndc)));
+        } else {
+            self.load_texture(texture_id);
+            let texture = &self.textures[&texture_id];
+            self.framebuffer.shade_background(|ndc| sample_sky(texture, direction(ndc)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::graphics::shape_builder::ShapeBuilder;
+
+    #[test]
+    fn draws_2d_primitives() {
+        let mut renderer = SoftwareRenderer::new(64, 48, Arc::new(Vfs::with_asset_root()));
+        let red = Vec4::new(1.0, 0.0, 0.0, 1.0);
+        let green = Vec4::new(0.0, 1.0, 0.0, 1.0);
+
+        renderer.begin_frame();
+        let square = [Vec3::new(-0.5, -0.5, 0.0), Vec3::new(0.5, -0.5, 0.0), Vec3::new(0.5, 0.5, 0.0), Vec3::new(-0.5, 0.5, 0.0)];
+        renderer.draw_polygon(&square, Vec3::new(8.0, 8.0, 0.0), 0.0, 16.0, red);
+        renderer.draw_polygon(&ShapeBuilder::get_octagon_vertices(), Vec3::new(48.0, 32.0, 0.0), 0.0, 8.0, green);
+        renderer.draw_line(Vec2::new(20.0, 40.0), Vec2::new(40.0, 40.0), 2.0, red);
+        renderer.end_frame();
+
+        let frame = renderer.read_frame();
+        assert_eq!(frame.dimensions(), (64, 48));
+        // Bottom left square, rows start at the top
+        assert_eq!(frame.get_pixel(8, 40).0, [255, 0, 0, 255]);
+        assert_eq!(frame.get_pixel(48, 16).0, [0, 255, 0, 255]);
+        assert_eq!(frame.get_pixel(30, 7).0, [255, 0, 0, 255]);
+        // Clear color elsewhere
+        assert_eq!(frame.get_pixel(30, 5).0, [204, 222, 242, 255]);
+    }
+
+    #[test]
+    fn places_sprite_layers_by_their_offset() {
+        let mut renderer = SoftwareRenderer::new(320, 200, Arc::new(Vfs::with_asset_root()));
+        let texture_id = renderer.manifest.get_texture_id("shotgun_frames");
+        let (sprite, offset) = texture::load_sprite(&renderer.vfs, Path::new("textures/SHTFC0.png"));
+        let sprite = sprite.to_rgba8();
+        assert_eq!(offset, IVec2::new(2, -2));
+
+        // Doom's weapon position, (1, 32) from the top left
+        renderer.begin_frame();
+        renderer.draw_sprite_layer(texture_id, 0, Vec2::new(1.0, 168.0), 1.0);
+        // Layers the texture doesn't have are skipped
+        renderer.draw_sprite_layer(texture_id, 99, Vec2::new(1.0, 168.0), 1.0);
+        renderer.end_frame();
+
+        // The sprite's top left is 2 left of and 2 below the origin, so at (-1, 34)
+        let frame = renderer.read_frame();
+        let (x, y) = (0..sprite.height())
+            .flat_map(|y| (0..sprite.width()).map(move |x| (x, y)))
+            .find(|(x, y)| sprite.get_pixel(*x, *y)[3] == 255)
+            .unwrap();
+        assert_eq!(frame.get_pixel(x - 1, y + 34), sprite.get_pixel(x, y));
+        // The row above the sprite is untouched
+        let top = sprite.rows().position(|mut row| row.any(|pixel| pixel[3] > 0)).unwrap() as u32;
+        assert!((0..320).all(|x| frame.get_pixel(x, top + 33).0 == [204, 222, 242, 255]));
+    }
+
+    #[test]
+    fn sky_flat_ceilings_show_the_sky() {
+        let mut renderer = SoftwareRenderer::new(64, 48, Arc::new(Vfs::with_asset_root()));
+        // Looking up at the ceiling, 30 degrees over the horizon
+        let view = Mat4::look_at_rh(Vec3::ZERO, Vec3::new(0.0, 0.5, -0.866), Vec3::Y);
+
+        renderer.begin_frame();
+        renderer.draw_sky(view);
+        renderer.end_frame();
+        let sky = renderer.read_frame();
+
+        renderer.begin_frame();
+        renderer.draw_sector_ceiling(view, Vec3::new(0.0, 5.0, 0.0), Vec2::splat(100.0), "f_sky1", 255);
+        renderer.end_frame();
+        let ceiling = renderer.read_frame();
+
+        assert_eq!(ceiling.get_pixel(32, 24), sky.get_pixel(32, 24));
+
+        renderer.begin_frame();
+        renderer.draw_sector_ceiling(view, Vec3::new(0.0, 5.0, 0.0), Vec2::splat(100.0), "brick_wall", 255);
+        renderer.end_frame();
+        assert_ne!(renderer.read_frame().get_pixel(32, 24), sky.get_pixel(32, 24));
+    }
+
+    #[test]
+    fn cubemap_sky_uses_face_in_view_direction() {
+        let mut renderer = SoftwareRenderer::new(64, 48, Arc::new(Vfs::with_asset_root()));
+        renderer.sky_mode = SkyMode::Cubemap;
+
+        // Camera looks down -z, the back face
+        renderer.begin_frame();
+        renderer.draw_sky(Mat4::look_at_rh(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y));
+        renderer.end_frame();
+
+        let texture_id = renderer.manifest.get_mesh(renderer.meshes.skybox).texture.unwrap();
+        let back = &renderer.texture_layers[&texture_id][5];
+        let (x, y) = (back.width() / 2, back.height() / 2);
+        // The pixel center is a little off the view direction
+        let near_center: Vec<_> = (x - 2..x + 2).flat_map(|x| (y - 2..y + 2).map(move |y| *back.get_pixel(x, y))).collect();
+        assert!(near_center.contains(renderer.read_frame().get_pixel(32, 24)));
+    }
+
+    #[test]
+    fn colormap_darkens_with_distance() {
+        let renderer = SoftwareRenderer::new(4, 4, Arc::new(Vfs::with_asset_root()));
+        let color = Vec4::new(0.8, 0.6, 0.4, 1.0);
+        let light_index = colormap::get_light_index(160, 0);
+
+        let apply = |row| apply_colormap(&renderer.palette, &renderer.colormap, &renderer.index_lut, color, row);
+
+        let near = apply(colormap::get_colormap_row(light_index, 10.0));
+        let far = apply(colormap::get_colormap_row(light_index, 4000.0));
+        assert!(far.truncate().length() < near.truncate().length());
+    }
+
+    #[test]
+    fn projects_for_the_display_aspect() {
+        let mut renderer = SoftwareRenderer::new(320, 200, Arc::new(Vfs::with_asset_root()));
+        let corrected = Mat4::perspective_rh_gl(90.0_f32.to_radians(), 4.0 / 3.0, 0.1, 1000.0);
+        assert_ne!(renderer.perspective_projection, corrected);
+
+        // 320x200 shown at 4:3, kept when resized
+        renderer.set_display_aspect(4.0 / 3.0);
+        renderer.resize(640, 400);
+        assert_eq!(renderer.perspective_projection, corrected);
+    }
+
+    #[test]
+    fn equal_spot_cutoffs_give_a_hard_edge() {
+        let light = Light::spot(Vec3::ZERO, Vec3::NEG_Z, Vec3::ONE, 20.0, 30.0, 30.0);
+        let material = Material::plain();
+
+        let (inside, _) = calculate_light(&light, Vec3::new(0.0, 0.0, -5.0), Vec3::Z, Vec3::Z, &material, true);
+        assert!(inside.is_finite() && inside.x > 0.0);
+
+        let (outside, _) = calculate_light(&light, Vec3::new(5.0, 0.0, -1.0), Vec3::Z, Vec3::Z, &material, true);
+        assert_eq!(outside, Vec3::ZERO);
+
+        // Right on the edge of the cone was 0 / 0
+        let light = Light::spot(Vec3::ZERO, Vec3::NEG_Z, Vec3::ONE, 20.0, 0.0, 0.0);
+        let (edge, specular) = calculate_light(&light, Vec3::new(0.0, 0.0, -5.0), Vec3::Z, Vec3::Z, &material, true);
+        assert!(edge.is_finite() && specular.is_finite());
+    }
+}
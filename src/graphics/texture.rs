@@ -1,91 +1,529 @@
+use std::ffi::CStr;
 use std::path::Path;
 
-use gl::types::{GLint, GLuint};
-use image::GenericImageView;
+use gl::types::{GLenum, GLint};
+use glam::{IVec2, UVec2};
+use image::{DynamicImage, ImageResult};
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
 
-use crate::registry::texture_registry::{TEXTURE_PATH, TextureId};
+use crate::assets::manifest::{TextureEntry, TextureSource};
+use crate::assets::vfs::Vfs;
+use crate::graphics::gl_resource::GlObject;
+use crate::registry::asset_store::AssetMemory;
 
-#[derive(Copy, Clone)]
 pub struct Texture {
-    id: GLuint,
+    object: GlObject,
+    // TEXTURE_2D, TEXTURE_CUBE_MAP or TEXTURE_2D_ARRAY
+    target: GLenum,
+    size: UVec2,
+    // Before padding, one per array layer
+    layer_sizes: Vec<UVec2>,
+    // Sprite offset of each layer, see read_sprite_offset
+    layer_offsets: Vec<IVec2>,
+    // All faces and mip levels
+    memory_size: usize,
 }
 
+// Cubemap face files inside a cubemap folder, in GL face order (+x, -x, +y, -y, +z, -z)
+pub const CUBEMAP_FACES: [&str; 6] = ["right.png", "left.png", "top.png", "bottom.png", "front.png", "back.png"];
+
+const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+// PNG chunk holding Doom sprite offsets, as written by SLADE and read by ZDoom
+const GRAB_CHUNK: &[u8; 4] = b"grAb";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum TextureType {
     SPRITE,
     TEXTURE,
 }
 
-impl Texture {
-    pub fn new(id: TextureId, texture_type: TextureType) -> Texture {
-
-        // Open image
-        let path = Path::new(TEXTURE_PATH).join(id.get_path());
-        let mut image = image::open(path.as_path()).expect("Failed to load texture image");
-        image = image.flipv();
-
-        // Get raw data, figure out color channel format
-        let (data, format, height, width) = match image {
-            image::DynamicImage::ImageRgb8(_) => (image.to_rgb8().into_raw(), gl::RGB, image.height(), image.width()),
-            image::DynamicImage::ImageRgba8(_) => (image.to_rgba8().into_raw(), gl::RGBA, image.height(), image.width()),
-            _ => panic!("Unsupported image format")
-        };
+// Nearest keeps the pixel look of sprites and Doom textures
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureFilter {
+    #[default]
+    Nearest,
+    Linear,
+}
 
+impl TextureFilter {
+    fn get_gl_filter(&self) -> GLint {
+        match self {
+            TextureFilter::Nearest => gl::NEAREST as GLint,
+            TextureFilter::Linear => gl::LINEAR as GLint,
+        }
+    }
+}
 
-        let internal_format = if format == gl::RGB { gl::RGB8 } else { gl::RGBA8 };
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureWrap {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror,
+}
 
-        // Build the texture
-        let mut texture_id = 0;
-        unsafe {
-            gl::GenTextures(1, &mut texture_id);
+impl TextureWrap {
+    fn get_gl_wrap(&self) -> GLint {
+        match self {
+            TextureWrap::Repeat => gl::REPEAT as GLint,
+            TextureWrap::Clamp => gl::CLAMP_TO_EDGE as GLint,
+            TextureWrap::Mirror => gl::MIRRORED_REPEAT as GLint,
+        }
+    }
+}
+
+// How a texture is sampled, set per texture in the manifest
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SamplerSettings {
+    pub filter: TextureFilter,
+    pub wrap: TextureWrap,
+    // Builds mip levels and samples them when minified, off keeps distant textures crisp
+    pub mipmaps: bool,
+    // Samples along the view angle, 1 is off. Capped to what the driver supports.
+    pub anisotropy: f32,
+    // Colors are stored in sRGB and decoded to linear when sampled
+    pub srgb: bool,
+}
+
+impl Default for SamplerSettings {
+    fn default() -> Self {
+        SamplerSettings {
+            filter: TextureFilter::Nearest,
+            wrap: TextureWrap::Repeat,
+            mipmaps: false,
+            anisotropy: 1.0,
+            srgb: false,
+        }
+    }
+}
 
-            // Bind texture
-            gl::BindTexture(gl::TEXTURE_2D, texture_id);
+impl SamplerSettings {
+    fn get_min_filter(&self) -> GLint {
+        let filter = match (self.filter, self.mipmaps) {
+            (TextureFilter::Nearest, false) => gl::NEAREST,
+            (TextureFilter::Linear, false) => gl::LINEAR,
+            (TextureFilter::Nearest, true) => gl::NEAREST_MIPMAP_NEAREST,
+            (TextureFilter::Linear, true) => gl::LINEAR_MIPMAP_LINEAR,
+        };
+        filter as GLint
+    }
 
-            // Set wrapping
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as GLint);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as GLint);
+    fn get_internal_format(&self) -> GLenum {
+        if self.srgb { gl::SRGB8_ALPHA8 } else { gl::RGBA8 }
+    }
 
+    // Sets filtering and wrapping of the texture bound to the target
+    fn apply(&self, target: GLenum) {
+        unsafe {
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_S, self.wrap.get_gl_wrap());
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_T, self.wrap.get_gl_wrap());
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_R, self.wrap.get_gl_wrap());
 
-            // Set filtering
-            match texture_type {
-                TextureType::SPRITE => {
-                    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
-                    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
-                },
-                TextureType::TEXTURE => {
-                    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
-                    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
-                }
+            gl::TexParameteri(target, gl::TEXTURE_MIN_FILTER, self.get_min_filter());
+            gl::TexParameteri(target, gl::TEXTURE_MAG_FILTER, self.filter.get_gl_filter());
+
+            let max_anisotropy = get_max_anisotropy();
+            if self.anisotropy > 1.0 && max_anisotropy > 1.0 {
+                gl::TexParameterf(target, TEXTURE_MAX_ANISOTROPY, self.anisotropy.min(max_anisotropy));
             }
+        }
+    }
+}
+
+// Anisotropic filtering is an extension before GL 4.6, the bindings don't have its enums.
+// EXT, ARB and core use the same values.
+const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FF;
+const ANISOTROPY_EXTENSIONS: [&str; 2] = ["GL_EXT_texture_filter_anisotropic", "GL_ARB_texture_filter_anisotropic"];
+
+static MAX_ANISOTROPY: OnceCell<f32> = OnceCell::new();
+
+// 1 when the driver has no anisotropic filtering
+fn get_max_anisotropy() -> f32 {
+    *MAX_ANISOTROPY.get_or_init(|| unsafe {
+        let mut count = 0;
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+        let supported = (0..count.max(0) as u32).any(|i| {
+            let name = gl::GetStringi(gl::EXTENSIONS, i);
+            !name.is_null() && CStr::from_ptr(name.cast()).to_str().is_ok_and(|name| ANISOTROPY_EXTENSIONS.contains(&name))
+        });
+        if !supported {
+            return 1.0;
+        }
+
+        let mut max = 1.0;
+        gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max);
+        max
+    })
+}
 
-            // Load image data
+// Largest alignment GL accepts that the row length is a multiple of
+fn get_unpack_alignment(row_bytes: usize) -> GLint {
+    [8, 4, 2, 1].into_iter()
+        .find(|alignment| row_bytes.is_multiple_of(*alignment))
+        .unwrap_or(1) as GLint
+}
+
+// RGBA8 pixels of one image, rows bottom first for 2D textures
+struct ImageData {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+impl ImageData {
+    // Any color type and bit depth, 16 bit and float channels are scaled down, gray is spread over rgb
+    fn from_image(image: DynamicImage) -> Self {
+        let image = image.into_rgba8();
+        ImageData {
+            width: image.width(),
+            height: image.height(),
+            data: image.into_raw(),
+        }
+    }
+
+    fn get_size(&self) -> UVec2 {
+        UVec2::new(self.width, self.height)
+    }
+
+    // Transparent pixels right of and above the image
+    fn padded(self, size: UVec2) -> Self {
+        if self.get_size() == size {
+            return self;
+        }
+
+        let (row_bytes, padded_row_bytes) = (self.width as usize * 4, size.x as usize * 4);
+        let mut data = vec![0; padded_row_bytes * size.y as usize];
+        for (row, pixels) in self.data.chunks_exact(row_bytes).enumerate() {
+            data[row * padded_row_bytes..row * padded_row_bytes + row_bytes].copy_from_slice(pixels);
+        }
+        ImageData {
+            data,
+            width: size.x,
+            height: size.y,
+        }
+    }
+
+    // Into the bound texture, target is the 2D target or a cubemap face
+    fn upload(&self, target: GLenum, internal_format: GLenum) {
+        self.with_unpack_alignment(|| unsafe {
             gl::TexImage2D(
-                gl::TEXTURE_2D,
+                target,
                 0,
                 internal_format as i32,
-                width as i32,
-                height as i32,
+                self.width as i32,
+                self.height as i32,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                self.data.as_ptr().cast());
+        });
+    }
+
+    // Into a layer of the bound array texture, its storage must exist already
+    fn upload_layer(&self, layer: usize) {
+        self.with_unpack_alignment(|| unsafe {
+            gl::TexSubImage3D(
+                gl::TEXTURE_2D_ARRAY,
+                0,
                 0,
-                format,
+                0,
+                layer as i32,
+                self.width as i32,
+                self.height as i32,
+                1,
+                gl::RGBA,
                 gl::UNSIGNED_BYTE,
-                data.as_ptr().cast());
+                self.data.as_ptr().cast());
+        });
+    }
+
+    fn with_unpack_alignment(&self, upload: impl FnOnce()) {
+        unsafe {
+            // Rows are tightly packed
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, get_unpack_alignment(self.width as usize * 4));
+        }
+        upload();
+        unsafe {
+            // Back to the GL default for other uploads
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+        }
+    }
+}
+
+// Decoded texture, made on any thread and uploaded on the GL thread
+pub struct TextureData {
+    // One image, the six cubemap faces in GL face order, or the array layers
+    images: Vec<ImageData>,
+    // TEXTURE_2D, TEXTURE_CUBE_MAP or TEXTURE_2D_ARRAY
+    target: GLenum,
+    // Size of each image before padding
+    layer_sizes: Vec<UVec2>,
+    layer_offsets: Vec<IVec2>,
+}
+
+impl TextureData {
+    pub fn decode(vfs: &Vfs, entry: &TextureEntry) -> TextureData {
+        match &entry.source {
+            TextureSource::Image(path) => {
+                let bytes = vfs.read(path).unwrap_or_else(|error| panic!("{}", error));
+                TextureData::from_memory(&bytes)
+                    .unwrap_or_else(|error| panic!("Failed to load texture image {}: {}", path.display(), error))
+            }
+            TextureSource::Cubemap(folder) => {
+                // Cubemaps expect the first row at the top, so no flip here
+                let images: Vec<ImageData> = CUBEMAP_FACES.iter()
+                    .map(|face| ImageData::from_image(load_image(vfs, &folder.join(face))))
+                    .collect();
+                TextureData {
+                    layer_sizes: images.iter().map(ImageData::get_size).collect(),
+                    layer_offsets: vec![IVec2::ZERO; images.len()],
+                    images,
+                    target: gl::TEXTURE_CUBE_MAP,
+                }
+            }
+            TextureSource::Layers(paths) => {
+                TextureData::from_layers(paths.iter().map(|path| load_sprite(vfs, path)).collect())
+            }
+        }
+    }
+
+    // 2D texture from an encoded image in memory, e.g. a lump read from a WAD
+    pub fn from_memory(bytes: &[u8]) -> ImageResult<TextureData> {
+        image::load_from_memory(bytes).map(TextureData::from_image)
+    }
+
+    // 2D texture, flipped so the first row ends up at the bottom like GL expects
+    pub fn from_image(image: DynamicImage) -> TextureData {
+        let image = ImageData::from_image(image.flipv());
+        TextureData {
+            layer_sizes: vec![image.get_size()],
+            layer_offsets: vec![IVec2::ZERO],
+            images: vec![image],
+            target: gl::TEXTURE_2D,
+        }
+    }
+
+    // Array texture with a layer per sprite image and its offset. Smaller images are padded with
+    // transparent pixels to the largest one, keeping their bottom left corner at the origin.
+    pub fn from_layers(layers: Vec<(DynamicImage, IVec2)>) -> TextureData {
+        assert!(!layers.is_empty(), "Array texture needs at least one layer");
+
+        let (images, layer_offsets): (Vec<ImageData>, Vec<IVec2>) = layers.into_iter()
+            .map(|(image, offset)| (ImageData::from_image(image.flipv()), offset))
+            .unzip();
+        let layer_sizes: Vec<UVec2> = images.iter().map(ImageData::get_size).collect();
+        let size = layer_sizes.iter().fold(UVec2::ZERO, |size, layer_size| size.max(*layer_size));
+
+        TextureData {
+            images: images.into_iter().map(|image| image.padded(size)).collect(),
+            target: gl::TEXTURE_2D_ARRAY,
+            layer_sizes,
+            layer_offsets,
+        }
+    }
+}
+
+impl Texture {
+    // Decodes and uploads in one go, blocks the GL thread while decoding
+    pub fn new(vfs: &Vfs, entry: &TextureEntry) -> Texture {
+        Texture::upload(&TextureData::decode(vfs, entry), &entry.sampler)
+    }
 
-            gl::GenerateMipmap(gl::TEXTURE_2D);
+    pub fn upload(data: &TextureData, sampler: &SamplerSettings) -> Texture {
+        let target = data.target;
+        let size = data.images[0].get_size();
 
-            // Unbind texture
-            gl::BindTexture(gl::TEXTURE_2D, 0);
+        let object = GlObject::texture();
+        unsafe {
+            gl::BindTexture(target, object.id());
+        }
+
+        match target {
+            gl::TEXTURE_2D_ARRAY => {
+                unsafe {
+                    // Storage for every layer, filled one layer at a time
+                    gl::TexImage3D(
+                        target,
+                        0,
+                        sampler.get_internal_format() as i32,
+                        size.x as i32,
+                        size.y as i32,
+                        data.images.len() as i32,
+                        0,
+                        gl::RGBA,
+                        gl::UNSIGNED_BYTE,
+                        std::ptr::null());
+                }
+                for (layer, image) in data.images.iter().enumerate() {
+                    image.upload_layer(layer);
+                }
+            }
+            gl::TEXTURE_CUBE_MAP => {
+                for (i, image) in data.images.iter().enumerate() {
+                    image.upload(gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as GLenum, sampler.get_internal_format());
+                }
+            }
+            _ => data.images[0].upload(target, sampler.get_internal_format()),
         }
+        sampler.apply(target);
 
+        unsafe {
+            if sampler.mipmaps {
+                gl::GenerateMipmap(target);
+            }
+            gl::BindTexture(target, 0);
+        }
+
+        let pixel_bytes: usize = data.images.iter().map(|image| image.data.len()).sum();
         Texture {
-            id: texture_id
+            object,
+            target,
+            size,
+            layer_sizes: data.layer_sizes.clone(),
+            layer_offsets: data.layer_offsets.clone(),
+            // Mipmaps add a third
+            memory_size: if sampler.mipmaps { pixel_bytes * 4 / 3 } else { pixel_bytes },
         }
     }
 
+    // Size of every layer or face, array layers are padded to it
+    pub fn get_size(&self) -> UVec2 {
+        self.size
+    }
+
+    // Size of the image a layer was made from, before padding
+    pub fn get_layer_size(&self, layer: usize) -> UVec2 {
+        self.layer_sizes[layer]
+    }
+
+    pub fn get_layer_offset(&self, layer: usize) -> IVec2 {
+        self.layer_offsets[layer]
+    }
+
+    pub fn get_layer_count(&self) -> usize {
+        self.layer_sizes.len()
+    }
+
     // Paramaterize what texture unit to bind to
     pub fn bind(&self, unit: u32) {
         unsafe {
             gl::ActiveTexture(gl::TEXTURE0 + unit);
-            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            gl::BindTexture(self.target, self.object.id());
         }
     }
 }
+
+impl AssetMemory for Texture {
+    fn get_memory_size(&self) -> usize {
+        self.memory_size
+    }
+}
+
+// Decodes an image from the VFS, the format is guessed from the contents
+pub fn load_image(vfs: &Vfs, path: &Path) -> DynamicImage {
+    load_sprite(vfs, path).0
+}
+
+// Decodes an image and its sprite offset, zero when it has none
+pub fn load_sprite(vfs: &Vfs, path: &Path) -> (DynamicImage, IVec2) {
+    let bytes = vfs.read(path).unwrap_or_else(|error| panic!("{}", error));
+    let image = image::load_from_memory(&bytes)
+        .unwrap_or_else(|error| panic!("Failed to load texture image {}: {}", path.display(), error));
+    (image, read_sprite_offset(&bytes).unwrap_or(IVec2::ZERO))
+}
+
+// Offset from a PNG's grAb chunk, like a Doom patch's left and top offset: how far the sprite's
+// origin is right of and below its top left pixel. None for images without one.
+pub fn read_sprite_offset(bytes: &[u8]) -> Option<IVec2> {
+    let mut chunks = bytes.strip_prefix(PNG_SIGNATURE)?;
+    // Length, type, data and CRC
+    while chunks.len() >= 12 {
+        let length = u32::from_be_bytes(chunks[0..4].try_into().unwrap()) as usize;
+        let data = chunks.get(8..8 + length)?;
+        if &chunks[4..8] == GRAB_CHUNK && length == 8 {
+            return Some(IVec2::new(i32::from_be_bytes(data[0..4].try_into().unwrap()),
+                                   i32::from_be_bytes(data[4..8].try_into().unwrap())));
+        }
+        chunks = chunks.get(12 + length..)?;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{GrayImage, ImageBuffer, ImageFormat, Luma, Rgb, Rgba, RgbaImage};
+
+    use super::*;
+
+    fn encode_png(image: DynamicImage) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn converts_any_format_to_rgba8() {
+        // Gray, top row black and bottom row white
+        let gray = GrayImage::from_fn(3, 2, |_, y| Luma([if y == 0 { 0 } else { 255 }]));
+        let data = TextureData::from_memory(&encode_png(DynamicImage::ImageLuma8(gray))).unwrap();
+        let image = &data.images[0];
+        assert_eq!((image.width, image.height), (3, 2));
+        assert_eq!(image.data.len(), 3 * 2 * 4);
+        // Flipped, the bottom row comes first
+        assert_eq!(&image.data[..4], &[255, 255, 255, 255]);
+
+        let deep: ImageBuffer<Rgb<u16>, Vec<u16>> = ImageBuffer::from_pixel(1, 1, Rgb([65535, 0, 32896]));
+        let data = TextureData::from_memory(&encode_png(DynamicImage::ImageRgb16(deep))).unwrap();
+        assert_eq!(data.images[0].data, vec![255, 0, 128, 255]);
+
+        assert!(TextureData::from_memory(b"not an image").is_err());
+    }
+
+    #[test]
+    fn reads_grab_offsets() {
+        let png = encode_png(DynamicImage::ImageRgba8(RgbaImage::new(2, 2)));
+        assert_eq!(read_sprite_offset(&png), None);
+        assert_eq!(read_sprite_offset(b"not an image"), None);
+
+        // After the signature and IHDR, the CRC is not checked
+        let mut grab = 8u32.to_be_bytes().to_vec();
+        grab.extend_from_slice(GRAB_CHUNK);
+        grab.extend_from_slice(&14i32.to_be_bytes());
+        grab.extend_from_slice(&(-31i32).to_be_bytes());
+        grab.extend_from_slice(&[0; 4]);
+        let mut with_grab = png.clone();
+        with_grab.splice(33..33, grab);
+        assert_eq!(read_sprite_offset(&with_grab), Some(IVec2::new(14, -31)));
+    }
+
+    #[test]
+    fn pads_layers_to_the_largest() {
+        let small = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([255, 0, 0, 255])));
+        let large = DynamicImage::ImageRgba8(RgbaImage::from_pixel(2, 3, Rgba([0, 0, 255, 255])));
+        let data = TextureData::from_layers(vec![(small, IVec2::ZERO), (large, IVec2::new(2, -2))]);
+
+        assert_eq!(data.target, gl::TEXTURE_2D_ARRAY);
+        assert_eq!(data.layer_sizes, vec![UVec2::new(1, 1), UVec2::new(2, 3)]);
+        assert_eq!(data.layer_offsets, vec![IVec2::ZERO, IVec2::new(2, -2)]);
+        assert!(data.images.iter().all(|image| image.get_size() == UVec2::new(2, 3)));
+
+        // Bottom left keeps the pixel, the rest is transparent
+        let small = &data.images[0].data;
+        assert_eq!(&small[..4], &[255, 0, 0, 255]);
+        assert!(small[4..].iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn unpack_alignment_fits_row_length() {
+        assert_eq!(get_unpack_alignment(3 * 4), 4);
+        assert_eq!(get_unpack_alignment(2 * 4), 8);
+        assert_eq!(get_unpack_alignment(6), 2);
+        assert_eq!(get_unpack_alignment(7), 1);
+    }
+}
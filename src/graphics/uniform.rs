@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use gl::types::GLenum;
+use glam::{IVec2, IVec3, IVec4, Mat3, Mat4, Vec2, Vec3, Vec4};
+use once_cell::sync::Lazy;
+
+// Interned uniform name, programs cache their locations by id at link time
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct UniformId(u32);
+
+struct UniformNames {
+    ids: HashMap<String, UniformId>,
+    names: Vec<String>,
+}
+
+static UNIFORM_NAMES: Lazy<RwLock<UniformNames>> = Lazy::new(|| {
+    RwLock::new(UniformNames {
+        ids: HashMap::new(),
+        names: Vec::new(),
+    })
+});
+
+impl UniformId {
+    // Only allocates the first time a name is seen
+    pub fn get(name: &str) -> UniformId {
+        if let Some(id) = UNIFORM_NAMES.read().unwrap().ids.get(name) {
+            return *id;
+        }
+
+        let mut uniform_names = UNIFORM_NAMES.write().unwrap();
+        if let Some(id) = uniform_names.ids.get(name) {
+            return *id;
+        }
+        let id = UniformId(uniform_names.names.len() as u32);
+        uniform_names.names.push(name.to_string());
+        uniform_names.ids.insert(name.to_string(), id);
+        id
+    }
+
+    pub fn get_name(&self) -> String {
+        UNIFORM_NAMES.read().unwrap().names[self.0 as usize].clone()
+    }
+}
+
+// Names the built-in shaders use, interned once so draws skip the lookup
+pub struct BuiltinUniforms {
+    pub model: UniformId,
+    pub normal_matrix: UniformId,
+    pub diffuse: UniformId,
+    pub texture1: UniformId,
+    pub tex_width_scale: UniformId,
+    pub layer: UniformId,
+    pub use_texture: UniformId,
+    pub use_vertex_light: UniformId,
+    pub sector_light: UniformId,
+    pub palette_index_lut: UniformId,
+    pub colormap: UniformId,
+    pub palette: UniformId,
+    pub lut_size: UniformId,
+    pub light_mode: UniformId,
+    pub light_contrast: UniformId,
+    pub light_distance_scale: UniformId,
+    pub skybox: UniformId,
+    pub sky: UniformId,
+    pub sky_repeat: UniformId,
+    pub sky_height: UniformId,
+    pub background: UniformId,
+    pub material_ambient: UniformId,
+    pub material_diffuse: UniformId,
+    pub material_specular: UniformId,
+    pub material_shininess: UniformId,
+}
+
+pub static BUILTIN_UNIFORMS: Lazy<BuiltinUniforms> = Lazy::new(|| BuiltinUniforms {
+    model: UniformId::get("model"),
+    normal_matrix: UniformId::get("normalMatrix"),
+    diffuse: UniformId::get("diffuse"),
+    texture1: UniformId::get("texture1"),
+    tex_width_scale: UniformId::get("texWidthScale"),
+    layer: UniformId::get("layer"),
+    use_texture: UniformId::get("useTexture"),
+    use_vertex_light: UniformId::get("useVertexLight"),
+    sector_light: UniformId::get("sectorLight"),
+    palette_index_lut: UniformId::get("paletteIndexLut"),
+    colormap: UniformId::get("colormap"),
+    palette: UniformId::get("palette"),
+    lut_size: UniformId::get("lutSize"),
+    light_mode: UniformId::get("lightMode"),
+    light_contrast: UniformId::get("lightContrast"),
+    light_distance_scale: UniformId::get("lightDistanceScale"),
+    skybox: UniformId::get("skybox"),
+    sky: UniformId::get("sky"),
+    sky_repeat: UniformId::get("skyRepeat"),
+    sky_height: UniformId::get("skyHeight"),
+    background: UniformId::get("background"),
+    material_ambient: UniformId::get("material.ambient"),
+    material_diffuse: UniformId::get("material.diffuse"),
+    material_specular: UniformId::get("material.specular"),
+    material_shininess: UniformId::get("material.shininess"),
+});
+
+// Active uniform of a linked program
+#[derive(Debug, Clone, Copy)]
+pub struct UniformInfo {
+    pub location: i32,
+    pub gl_type: GLenum,
+    // Array length, 1 for single values
+    pub size: i32,
+}
+
+// Every GLSL type programs can declare
+#[derive(Clone)]
+pub enum Uniform {
+    Bool(UniformId, bool),
+    Int(UniformId, i32),
+    Float(UniformId, f32),
+    Vec2(UniformId, Vec2),
+    Vec3(UniformId, Vec3),
+    Vec4(UniformId, Vec4),
+    // For palette indices and tile coordinates, no built-in shader takes integer vectors yet
+    #[allow(dead_code)]
+    IVec2(UniformId, IVec2),
+    #[allow(dead_code)]
+    IVec3(UniformId, IVec3),
+    #[allow(dead_code)]
+    IVec4(UniformId, IVec4),
+    Matrix3f(UniformId, Mat3),
+    Matrix4f(UniformId, Mat4),
+    // Texture unit the sampler reads from
+    Sampler(UniformId, u32),
+    // Arrays start at element 0 and may be shorter than the GLSL array. Lights go through the
+    // frame uniform block, nothing passes arrays until skinning lands.
+    #[allow(dead_code)]
+    FloatArray(UniformId, Vec<f32>),
+    #[allow(dead_code)]
+    Vec3Array(UniformId, Vec<Vec3>),
+    #[allow(dead_code)]
+    Vec4Array(UniformId, Vec<Vec4>),
+    // e.g. bone palettes for skinning
+    #[allow(dead_code)]
+    Matrix4fArray(UniformId, Vec<Mat4>),
+}
+
+impl Uniform {
+    pub fn get_id(&self) -> UniformId {
+        match self {
+            Uniform::Bool(id, _)
+            | Uniform::Int(id, _)
+            | Uniform::Float(id, _)
+            | Uniform::Vec2(id, _)
+            | Uniform::Vec3(id, _)
+            | Uniform::Vec4(id, _)
+            | Uniform::IVec2(id, _)
+            | Uniform::IVec3(id, _)
+            | Uniform::IVec4(id, _)
+            | Uniform::Matrix3f(id, _)
+            | Uniform::Matrix4f(id, _)
+            | Uniform::Sampler(id, _)
+            | Uniform::FloatArray(id, _)
+            | Uniform::Vec3Array(id, _)
+            | Uniform::Vec4Array(id, _)
+            | Uniform::Matrix4fArray(id, _) => *id,
+        }
+    }
+
+    // Number of elements written, 1 for single values
+    fn get_count(&self) -> usize {
+        match self {
+            Uniform::FloatArray(_, values) => values.len(),
+            Uniform::Vec3Array(_, values) => values.len(),
+            Uniform::Vec4Array(_, values) => values.len(),
+            Uniform::Matrix4fArray(_, values) => values.len(),
+            _ => 1,
+        }
+    }
+
+    // GLSL types this value can be written to
+    fn get_gl_types(&self) -> &'static [GLenum] {
+        match self {
+            Uniform::Bool(..) => &[gl::BOOL],
+            Uniform::Int(..) => &[gl::INT],
+            Uniform::Float(..) | Uniform::FloatArray(..) => &[gl::FLOAT],
+            Uniform::Vec2(..) => &[gl::FLOAT_VEC2],
+            Uniform::Vec3(..) | Uniform::Vec3Array(..) => &[gl::FLOAT_VEC3],
+            Uniform::Vec4(..) | Uniform::Vec4Array(..) => &[gl::FLOAT_VEC4],
+            Uniform::IVec2(..) => &[gl::INT_VEC2],
+            Uniform::IVec3(..) => &[gl::INT_VEC3],
+            Uniform::IVec4(..) => &[gl::INT_VEC4],
+            Uniform::Matrix3f(..) => &[gl::FLOAT_MAT3],
+            Uniform::Matrix4f(..) | Uniform::Matrix4fArray(..) => &[gl::FLOAT_MAT4],
+            Uniform::Sampler(..) => &[gl::SAMPLER_2D,
+                                      gl::SAMPLER_3D,
+                                      gl::SAMPLER_CUBE,
+                                      gl::SAMPLER_2D_ARRAY,
+                                      gl::SAMPLER_2D_SHADOW,
+                                      gl::INT_SAMPLER_2D,
+                                      gl::UNSIGNED_INT_SAMPLER_2D],
+        }
+    }
+
+    // Why this value can't be written to the reflected uniform, None when it can
+    pub fn check(&self, info: &UniformInfo) -> Option<String> {
+        let gl_types = self.get_gl_types();
+        if !gl_types.contains(&info.gl_type) {
+            return Some(format!("is a {}, got {}", get_gl_type_name(info.gl_type), get_gl_type_name(gl_types[0])));
+        }
+        if self.get_count() > info.size as usize {
+            return Some(format!("holds {} elements, got {}", info.size, self.get_count()));
+        }
+        None
+    }
+
+    // Writes the value to the program in use
+    pub fn set(&self, location: i32) {
+        unsafe {
+            match self {
+                Uniform::Bool(_, value) => gl::Uniform1i(location, *value as i32),
+                Uniform::Int(_, value) => gl::Uniform1i(location, *value),
+                Uniform::Float(_, value) => gl::Uniform1f(location, *value),
+                Uniform::Vec2(_, vec2) => gl::Uniform2fv(location, 1, vec2.as_ref().as_ptr()),
+                Uniform::Vec3(_, vec3) => gl::Uniform3fv(location, 1, vec3.as_ref().as_ptr()),
+                Uniform::Vec4(_, vec4) => gl::Uniform4fv(location, 1, vec4.as_ref().as_ptr()),
+                Uniform::IVec2(_, ivec2) => gl::Uniform2iv(location, 1, ivec2.as_ref().as_ptr()),
+                Uniform::IVec3(_, ivec3) => gl::Uniform3iv(location, 1, ivec3.as_ref().as_ptr()),
+                Uniform::IVec4(_, ivec4) => gl::Uniform4iv(location, 1, ivec4.as_ref().as_ptr()),
+                Uniform::Matrix3f(_, matrix) => gl::UniformMatrix3fv(location, 1, gl::FALSE, matrix.to_cols_array().as_ptr()),
+                Uniform::Matrix4f(_, matrix) => gl::UniformMatrix4fv(location, 1, gl::FALSE, matrix.to_cols_array().as_ptr()),
+                Uniform::Sampler(_, unit) => gl::Uniform1i(location, *unit as i32),
+                Uniform::FloatArray(_, values) => gl::Uniform1fv(location, values.len() as i32, values.as_ptr()),
+                Uniform::Vec3Array(_, values) => {
+                    let data: Vec<f32> = values.iter().flat_map(|value| value.to_array()).collect();
+                    gl::Uniform3fv(location, values.len() as i32, data.as_ptr());
+                }
+                Uniform::Vec4Array(_, values) => {
+                    let data: Vec<f32> = values.iter().flat_map(|value| value.to_array()).collect();
+                    gl::Uniform4fv(location, values.len() as i32, data.as_ptr());
+                }
+                Uniform::Matrix4fArray(_, values) => {
+                    let data: Vec<f32> = values.iter().flat_map(|value| value.to_cols_array()).collect();
+                    gl::UniformMatrix4fv(location, values.len() as i32, gl::FALSE, data.as_ptr());
+                }
+            }
+        }
+    }
+}
+
+// GLSL spelling for error messages
+fn get_gl_type_name(gl_type: GLenum) -> &'static str {
+    match gl_type {
+        gl::BOOL => "bool",
+        gl::INT => "int",
+        gl::FLOAT => "float",
+        gl::FLOAT_VEC2 => "vec2",
+        gl::FLOAT_VEC3 => "vec3",
+        gl::FLOAT_VEC4 => "vec4",
+        gl::INT_VEC2 => "ivec2",
+        gl::INT_VEC3 => "ivec3",
+        gl::INT_VEC4 => "ivec4",
+        gl::FLOAT_MAT3 => "mat3",
+        gl::FLOAT_MAT4 => "mat4",
+        gl::SAMPLER_2D => "sampler2D",
+        gl::SAMPLER_3D => "sampler3D",
+        gl::SAMPLER_CUBE => "samplerCube",
+        gl::SAMPLER_2D_ARRAY => "sampler2DArray",
+        gl::SAMPLER_2D_SHADOW => "sampler2DShadow",
+        gl::INT_SAMPLER_2D => "isampler2D",
+        gl::UNSIGNED_INT_SAMPLER_2D => "usampler2D",
+        _ => "unsupported type",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interns_names_once() {
+        let model = UniformId::get("interns_names_once.model");
+        assert_eq!(UniformId::get("interns_names_once.model"), model);
+        assert_ne!(UniformId::get("interns_names_once.view"), model);
+        assert_eq!(model.get_name(), "interns_names_once.model");
+    }
+
+    #[test]
+    fn resolves_builtin_names() {
+        assert_eq!(BUILTIN_UNIFORMS.normal_matrix, UniformId::get("normalMatrix"));
+        assert_eq!(BUILTIN_UNIFORMS.material_shininess.get_name(), "material.shininess");
+    }
+
+    fn info(gl_type: GLenum, size: i32) -> UniformInfo {
+        UniformInfo {
+            location: 0,
+            gl_type,
+            size,
+        }
+    }
+
+    #[test]
+    fn checks_reflected_types() {
+        let id = UniformId::get("checks_reflected_types");
+        assert_eq!(Uniform::Vec3(id, Vec3::ONE).check(&info(gl::FLOAT_VEC3, 1)), None);
+        assert_eq!(Uniform::Sampler(id, 2).check(&info(gl::SAMPLER_3D, 1)), None);
+        assert_eq!(Uniform::Int(id, 0).check(&info(gl::SAMPLER_2D, 1)),
+                   Some("is a sampler2D, got int".to_string()));
+        assert_eq!(Uniform::Bool(id, true).check(&info(gl::FLOAT, 1)),
+                   Some("is a float, got bool".to_string()));
+    }
+
+    #[test]
+    fn checks_array_length() {
+        let id = UniformId::get("checks_array_length");
+        let bones = vec![Mat4::IDENTITY; 3];
+        assert_eq!(Uniform::Matrix4fArray(id, bones.clone()).check(&info(gl::FLOAT_MAT4, 4)), None);
+        assert_eq!(Uniform::Matrix4fArray(id, bones).check(&info(gl::FLOAT_MAT4, 2)),
+                   Some("holds 2 elements, got 3".to_string()));
+    }
+}
@@ -0,0 +1,252 @@
+use gl::types::{GLenum, GLuint};
+
+// What an attribute means. Each kind has a fixed shader location so every shader agrees on
+// where to find it, e.g. `layout (location = 1) in vec2 textCoord;`
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum AttributeKind {
+    Position,
+    Uv,
+    Normal,
+    // Vertex colors, normal mapping and skinning, no built-in mesh has them yet
+    #[allow(dead_code)]
+    Color,
+    #[allow(dead_code)]
+    Tangent,
+    LightLevel,
+    #[allow(dead_code)]
+    BoneIndices,
+    #[allow(dead_code)]
+    BoneWeights,
+}
+
+impl AttributeKind {
+    pub fn location(&self) -> GLuint {
+        match self {
+            AttributeKind::Position => 0,
+            AttributeKind::Uv => 1,
+            AttributeKind::Normal => 2,
+            AttributeKind::Color => 3,
+            AttributeKind::Tangent => 4,
+            AttributeKind::LightLevel => 5,
+            AttributeKind::BoneIndices => 6,
+            AttributeKind::BoneWeights => 7,
+        }
+    }
+}
+
+// Component type of an attribute as stored in the vertex buffer
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum AttributeType {
+    Float,
+    // For packed colors and bone indices, built-in meshes are all floats
+    #[allow(dead_code)]
+    Byte,
+    #[allow(dead_code)]
+    UnsignedByte,
+    #[allow(dead_code)]
+    Short,
+    #[allow(dead_code)]
+    UnsignedShort,
+    #[allow(dead_code)]
+    Int,
+    #[allow(dead_code)]
+    UnsignedInt,
+}
+
+impl AttributeType {
+    pub fn size(&self) -> usize {
+        match self {
+            AttributeType::Float => std::mem::size_of::<f32>(),
+            AttributeType::Byte => std::mem::size_of::<i8>(),
+            AttributeType::UnsignedByte => std::mem::size_of::<u8>(),
+            AttributeType::Short => std::mem::size_of::<i16>(),
+            AttributeType::UnsignedShort => std::mem::size_of::<u16>(),
+            AttributeType::Int => std::mem::size_of::<i32>(),
+            AttributeType::UnsignedInt => std::mem::size_of::<u32>(),
+        }
+    }
+
+    pub fn gl_type(&self) -> GLenum {
+        match self {
+            AttributeType::Float => gl::FLOAT,
+            AttributeType::Byte => gl::BYTE,
+            AttributeType::UnsignedByte => gl::UNSIGNED_BYTE,
+            AttributeType::Short => gl::SHORT,
+            AttributeType::UnsignedShort => gl::UNSIGNED_SHORT,
+            AttributeType::Int => gl::INT,
+            AttributeType::UnsignedInt => gl::UNSIGNED_INT,
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        *self != AttributeType::Float
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct VertexAttribute {
+    pub kind: AttributeKind,
+    pub components: i32,
+    pub attribute_type: AttributeType,
+    // Integer types are converted to floats in [0, 1] / [-1, 1] when normalized, otherwise
+    // they are passed to the shader as integers (e.g. bone indices)
+    pub normalized: bool,
+    // Byte offset from the start of the vertex
+    pub offset: usize,
+}
+
+impl VertexAttribute {
+    pub fn size(&self) -> usize {
+        self.components as usize * self.attribute_type.size()
+    }
+}
+
+// Describes the interleaved layout of a single vertex in a vertex buffer
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
+pub struct VertexLayout {
+    attributes: Vec<VertexAttribute>,
+    stride: usize,
+}
+
+impl VertexLayout {
+    pub fn new() -> Self {
+        VertexLayout {
+            attributes: Vec::new(),
+            stride: 0,
+        }
+    }
+
+    // Appends an attribute directly after the previous one
+    pub fn with(self, kind: AttributeKind, components: i32, attribute_type: AttributeType) -> Self {
+        self.with_attribute(kind, components, attribute_type, false)
+    }
+
+    // Appends an integer attribute the shader reads as a normalized float, e.g. RGBA8 colors
+    #[allow(dead_code)]
+    pub fn with_normalized(self, kind: AttributeKind, components: i32, attribute_type: AttributeType) -> Self {
+        self.with_attribute(kind, components, attribute_type, true)
+    }
+
+    fn with_attribute(mut self, kind: AttributeKind, components: i32, attribute_type: AttributeType, normalized: bool) -> Self {
+        if !(1..=4).contains(&components) {
+            panic!("Vertex attribute {:?} must have 1 to 4 components, got {}", kind, components);
+        }
+
+        if self.attributes.iter().any(|attribute| attribute.kind == kind) {
+            panic!("Vertex attribute {:?} already in layout", kind);
+        }
+
+        let attribute = VertexAttribute {
+            kind,
+            components,
+            attribute_type,
+            normalized,
+            offset: self.stride,
+        };
+        self.stride += attribute.size();
+        self.attributes.push(attribute);
+        self
+    }
+
+    // x, y, z
+    pub fn position() -> Self {
+        VertexLayout::new()
+            .with(AttributeKind::Position, 3, AttributeType::Float)
+    }
+
+    // x, y, z, u, v
+    pub fn position_uv() -> Self {
+        VertexLayout::position()
+            .with(AttributeKind::Uv, 2, AttributeType::Float)
+    }
+
+    // x, y, z, u, v, nx, ny, nz
+    pub fn position_uv_normal() -> Self {
+        VertexLayout::position_uv()
+            .with(AttributeKind::Normal, 3, AttributeType::Float)
+    }
+
+    // x, y, z, u, v, sector light (0-255), for level geometry
+    pub fn position_uv_light() -> Self {
+        VertexLayout::position_uv()
+            .with(AttributeKind::LightLevel, 1, AttributeType::Float)
+    }
+
+    pub fn attributes(&self) -> &[VertexAttribute] {
+        &self.attributes
+    }
+
+    pub fn get(&self, kind: AttributeKind) -> Option<&VertexAttribute> {
+        self.attributes.iter().find(|attribute| attribute.kind == kind)
+    }
+
+    pub fn has(&self, kind: AttributeKind) -> bool {
+        self.get(kind).is_some()
+    }
+
+    // Size of a single vertex in bytes
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    // Sets up attribute pointers for the currently bound VAO and VBO
+    pub fn configure(&self) {
+        if self.attributes.is_empty() {
+            panic!("Vertex layout has no attributes");
+        }
+
+        for attribute in self.attributes() {
+            let location = attribute.kind.location();
+            unsafe {
+                // attribute index, number of components per attribute, type, (is normalized), size of stride, offset
+                if attribute.attribute_type.is_integer() && !attribute.normalized {
+                    gl::VertexAttribIPointer(location,
+                                             attribute.components,
+                                             attribute.attribute_type.gl_type(),
+                                             self.stride as i32,
+                                             attribute.offset as *const _);
+                } else {
+                    gl::VertexAttribPointer(location,
+                                            attribute.components,
+                                            attribute.attribute_type.gl_type(),
+                                            if attribute.normalized { gl::TRUE } else { gl::FALSE },
+                                            self.stride as i32,
+                                            attribute.offset as *const _);
+                }
+                gl::EnableVertexAttribArray(location);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_attributes_one_after_another() {
+        let layout = VertexLayout::position_uv_normal()
+            .with_normalized(AttributeKind::Color, 4, AttributeType::UnsignedByte)
+            .with(AttributeKind::BoneIndices, 4, AttributeType::UnsignedShort)
+            .with(AttributeKind::BoneWeights, 4, AttributeType::Float);
+
+        let offsets: Vec<usize> = layout.attributes().iter().map(|attribute| attribute.offset).collect();
+        assert_eq!(offsets, [0, 12, 20, 32, 36, 44]);
+        assert_eq!(layout.stride(), 60);
+
+        let color = layout.get(AttributeKind::Color).unwrap();
+        assert!(color.normalized);
+        assert_eq!(color.size(), 4);
+        assert!(!layout.get(AttributeKind::BoneIndices).unwrap().normalized);
+        assert!(layout.has(AttributeKind::Normal));
+        assert!(!layout.has(AttributeKind::Tangent));
+    }
+
+    #[test]
+    fn builds_the_mesh_layouts() {
+        assert_eq!(VertexLayout::position().stride(), 12);
+        assert_eq!(VertexLayout::position_uv().stride(), 20);
+        assert_eq!(VertexLayout::position_uv_light().get(AttributeKind::LightLevel).unwrap().offset, 20);
+        assert_eq!(VertexLayout::position_uv_light().stride(), 24);
+    }
+}
@@ -0,0 +1,130 @@
+use std::error::Error;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use glam::UVec2;
+use glfw::{Glfw, PWindow};
+use image::RgbaImage;
+
+use crate::assets::vfs::Vfs;
+use crate::game::camera::Camera;
+use crate::game::scene::{MainScene, Scene};
+use crate::game_window::{self, WindowError, WindowSettings};
+use crate::graphics::display::DisplaySettings;
+use crate::graphics::render::Renderer;
+use crate::graphics::render_backend::{Backend, RenderBackend};
+use crate::graphics::software_renderer::SoftwareRenderer;
+use crate::registry::Assets;
+use crate::registry::asset_store::AssetScope;
+
+// Renders into an offscreen target of an invisible window and reads the frames back, so the
+// renderer can be used without a display, e.g. in tests
+pub struct HeadlessRenderer {
+    pub renderer: Renderer,
+    // Keep the context alive
+    _window: PWindow,
+    _glfw: Glfw,
+}
+
+impl HeadlessRenderer {
+    #[cfg(test)]
+    pub fn new(width: u32, height: u32) -> Result<HeadlessRenderer, WindowError> {
+        HeadlessRenderer::with_vfs(width, height, Vfs::with_asset_root())
+    }
+
+    pub fn with_vfs(width: u32, height: u32, vfs: Vfs) -> Result<HeadlessRenderer, WindowError> {
+        let settings = WindowSettings {
+            width,
+            height,
+            title: "Headless".to_string(),
+            headless: true,
+            ..WindowSettings::default()
+        };
+
+        let mut glfw = game_window::init_glfw(&settings)?;
+        let (window, _) = game_window::create_window(&mut glfw, &settings)?;
+
+        // Assets hold GL objects of this context, the renderer drops them with it
+        let assets = Rc::new(Assets::load(vfs));
+        assets.load_scope(AssetScope::Level);
+        let mut renderer = Renderer::new(&window, assets);
+        renderer.set_offscreen(true);
+
+        Ok(HeadlessRenderer {
+            renderer,
+            _window: window,
+            _glfw: glfw,
+        })
+    }
+
+    // Draws a single frame and returns it, top row first
+    pub fn render<F: FnOnce(&mut Renderer)>(&mut self, draw: F) -> RgbaImage {
+        self.renderer.begin_frame();
+        draw(&mut self.renderer);
+        self.renderer.end_frame();
+
+        unsafe {
+            gl::Finish();
+        }
+        self.renderer.read_frame()
+    }
+}
+
+// Draws the first frame of the main scene and saves it, for `--render <file>`. GL frames are the
+// size of the window in the settings, software frames are at the internal resolution without
+// post processing and need no OpenGL context.
+pub fn render_main_scene(settings: &WindowSettings, path: &Path) -> Result<(), Box<dyn Error>> {
+    let camera = Camera::new();
+    let frame = match settings.backend {
+        Backend::Gl => {
+            let mut headless = HeadlessRenderer::with_vfs(settings.width, settings.height, Vfs::with_files(&settings.files)?)?;
+            headless.renderer.set_display_settings(DisplaySettings::with_resolution(settings.resolution));
+
+            let mut scene = MainScene::new(&headless.renderer.get_assets().manifest);
+            scene.load_level(&mut headless.renderer);
+            headless.render(|renderer| {
+                renderer.set_lights(scene.lights());
+                scene.draw(&camera, renderer);
+            })
+        }
+        Backend::Software => {
+            let window_size = UVec2::new(settings.width, settings.height);
+            let size = settings.resolution.get_size(window_size);
+            let mut renderer = SoftwareRenderer::new(size.x, size.y, Arc::new(Vfs::with_files(&settings.files)?));
+            // Drawn for the aspect the GL renderer shows it at
+            renderer.set_display_aspect(DisplaySettings::with_resolution(settings.resolution).get_display_aspect(window_size));
+
+            let mut scene = MainScene::new(renderer.get_manifest());
+            scene.load_level(&mut renderer);
+            renderer.begin_frame();
+            renderer.set_lights(scene.lights());
+            scene.draw(&camera, &mut renderer);
+            renderer.end_frame();
+            renderer.read_frame()
+        }
+    };
+    frame.save(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec4};
+
+    use super::*;
+
+    #[test]
+    #[ignore = "needs a display, a GPU or OSMesa, run with --ignored"]
+    fn renders_rect_offscreen() {
+        let mut headless = HeadlessRenderer::new(64, 64).unwrap_or_else(|e| panic!("{}", e));
+
+        let frame = headless.render(|renderer| {
+            renderer.draw_rect(Vec2::new(16.0, 16.0), Vec2::new(32.0, 32.0), 0.0, Vec4::new(1.0, 0.0, 0.0, 1.0));
+        });
+
+        assert_eq!(frame.dimensions(), (64, 64));
+        assert_eq!(frame.get_pixel(32, 32).0, [255, 0, 0, 255]);
+        assert_ne!(frame.get_pixel(2, 2).0, [255, 0, 0, 255]);
+    }
+}
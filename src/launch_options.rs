@@ -0,0 +1,141 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::graphics::display::InternalResolution;
+use crate::graphics::render_backend::Backend;
+
+// `--resolution <native|WxH>`, the internal resolution to start with
+pub const RESOLUTION_FLAG: &str = "--resolution";
+// `--backend <gl|software>`, what the scene is drawn with
+pub const BACKEND_FLAG: &str = "--backend";
+// `--render <file>` saves the first frame of the scene without opening a window
+pub const RENDER_FLAG: &str = "--render";
+// `--file <path>...` mounts folders, WADs and PK3s over the asset root
+pub const FILE_FLAG: &str = "--file";
+
+// Command line options besides the asset root, which is resolved on its own
+#[derive(Debug, PartialEq, Clone)]
+pub struct LaunchOptions {
+    pub resolution: InternalResolution,
+    pub backend: Backend,
+    // Image to render to instead of running the game
+    pub render: Option<PathBuf>,
+    // In mount order, later files win
+    pub files: Vec<PathBuf>,
+}
+
+impl Default for LaunchOptions {
+    fn default() -> Self {
+        LaunchOptions {
+            resolution: InternalResolution::Native,
+            backend: Backend::Gl,
+            render: None,
+            files: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LaunchError {
+    MissingValue(&'static str),
+    InvalidValue(&'static str, String),
+}
+
+impl fmt::Display for LaunchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LaunchError::MissingValue(flag) => write!(f, "{} needs a value", flag),
+            LaunchError::InvalidValue(flag, value) => write!(f, "Invalid value '{}' for {}", value, flag),
+        }
+    }
+}
+
+impl std::error::Error for LaunchError {}
+
+impl LaunchOptions {
+    // Accepts `--flag value` and `--flag=value`, unknown arguments are left to others.
+    // `--file` takes every value up to the next flag.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<LaunchOptions, LaunchError> {
+        let mut options = LaunchOptions::default();
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+
+            match flag.as_str() {
+                RESOLUTION_FLAG => {
+                    let value = get_value(RESOLUTION_FLAG, inline_value, &mut args)?;
+                    options.resolution = InternalResolution::parse(&value)
+                        .ok_or(LaunchError::InvalidValue(RESOLUTION_FLAG, value))?;
+                }
+                BACKEND_FLAG => {
+                    let value = get_value(BACKEND_FLAG, inline_value, &mut args)?;
+                    options.backend = Backend::parse(&value).ok_or(LaunchError::InvalidValue(BACKEND_FLAG, value))?;
+                }
+                RENDER_FLAG => options.render = Some(PathBuf::from(get_value(RENDER_FLAG, inline_value, &mut args)?)),
+                FILE_FLAG => {
+                    let is_value = |arg: &String| !arg.starts_with("--");
+                    let first = inline_value.or_else(|| args.next_if(is_value)).ok_or(LaunchError::MissingValue(FILE_FLAG))?;
+                    options.files.push(PathBuf::from(first));
+                    while let Some(file) = args.next_if(is_value) {
+                        options.files.push(PathBuf::from(file));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(options)
+    }
+}
+
+// Value after `=` or the next argument
+fn get_value(flag: &'static str, inline_value: Option<String>, args: &mut impl Iterator<Item = String>) -> Result<String, LaunchError> {
+    inline_value.or_else(|| args.next()).ok_or(LaunchError::MissingValue(flag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_render_file() {
+        let options = LaunchOptions::parse(args(&["--render", "frame.png", "--resolution=native"])).unwrap();
+        assert_eq!(options.render, Some(PathBuf::from("frame.png")));
+        assert_eq!(options.resolution, InternalResolution::Native);
+    }
+
+    #[test]
+    fn parses_files_up_to_the_next_flag() {
+        let options = LaunchOptions::parse(args(&["--file", "doom2.wad", "mod.pk3", "--backend", "software", "--file=extra"])).unwrap();
+        assert_eq!(options.files, [PathBuf::from("doom2.wad"), PathBuf::from("mod.pk3"), PathBuf::from("extra")]);
+        assert_eq!(options.backend, Backend::Software);
+        assert_eq!(LaunchOptions::parse(args(&["--file", "--render", "a.png"])), Err(LaunchError::MissingValue(FILE_FLAG)));
+    }
+
+    #[test]
+    fn parses_backend() {
+        assert_eq!(LaunchOptions::parse(args(&["--backend", "software"])).unwrap().backend, Backend::Software);
+        assert_eq!(LaunchOptions::parse(args(&["--backend=GL"])).unwrap().backend, Backend::Gl);
+        assert_eq!(LaunchOptions::parse(args(&["--backend", "vulkan"])),
+                   Err(LaunchError::InvalidValue(BACKEND_FLAG, "vulkan".to_string())));
+    }
+
+    #[test]
+    fn parses_resolution() {
+        assert_eq!(LaunchOptions::parse(args(&["--assets", "/flag"])).unwrap(), LaunchOptions::default());
+        assert_eq!(LaunchOptions::parse(args(&["--resolution", "320x200"])).unwrap().resolution,
+                   InternalResolution::Low);
+        assert_eq!(LaunchOptions::parse(args(&["--resolution=400x300"])).unwrap().resolution,
+                   InternalResolution::Custom(400, 300));
+
+        assert_eq!(LaunchOptions::parse(args(&["--resolution"])), Err(LaunchError::MissingValue(RESOLUTION_FLAG)));
+        assert_eq!(LaunchOptions::parse(args(&["--resolution", "0x200"])),
+                   Err(LaunchError::InvalidValue(RESOLUTION_FLAG, "0x200".to_string())));
+    }
+}
@@ -1,13 +1,38 @@
-use crate::game_window::GameWindow;
+use once_cell::sync::Lazy;
+
+use crate::assets::asset_root::ASSET_ROOT;
+use crate::game_window::{GameWindow, WindowSettings};
+use crate::launch_options::LaunchOptions;
 
 mod game_window;
-mod mesh;
+mod headless;
 mod graphics;
 mod assets;
 mod registry;
 mod game;
+mod launch_options;
+#[cfg(test)]
+mod golden_tests;
 
 fn main() {
-    let mut game_window = GameWindow::new();
+    // Logs where assets are loaded from before anything loads
+    Lazy::force(&ASSET_ROOT);
+
+    let options = LaunchOptions::parse(std::env::args().skip(1)).unwrap_or_else(|e| panic!("{}", e));
+
+    let settings = WindowSettings {
+        resolution: options.resolution,
+        backend: options.backend,
+        files: options.files,
+        ..WindowSettings::default()
+    };
+    if let Some(path) = &options.render {
+        headless::render_main_scene(&settings, path).unwrap_or_else(|e| panic!("{}", e));
+        println!("Saved {}", path.display());
+        return;
+    }
+
+    let mut game_window = GameWindow::new(settings).expect("Failed to create game window");
     game_window.run_loop();
+    game_window.shutdown();
 }
@@ -1,3 +1,323 @@
+pub mod asset_store;
 pub mod mesh_registry;
+pub mod model_registry;
 pub mod shader_registry;
 pub mod texture_registry;
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::assets::asset_root::MANIFEST_FILE;
+use crate::assets::manifest::Manifest;
+use crate::assets::vfs::Vfs;
+use crate::assets::worker_pool::WorkerPool;
+use crate::graphics::mesh::Mesh;
+use crate::graphics::model::{Model, ModelGeometry};
+use crate::graphics::program::{ShaderError, ShaderProgram};
+use crate::graphics::texture::{Texture, TextureData};
+use crate::registry::asset_store::{AssetMemory, AssetScope, AssetStore, AssetUsage, Handle};
+use crate::registry::mesh_registry::{LevelMeshId, MeshId};
+use crate::registry::model_registry::ModelId;
+use crate::registry::shader_registry::ShaderProgramId;
+use crate::registry::texture_registry::TextureId;
+
+// Loaded assets per type
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryReport {
+    pub programs: AssetUsage,
+    pub textures: AssetUsage,
+    pub meshes: AssetUsage,
+    pub models: AssetUsage,
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Programs: {}", self.programs)?;
+        writeln!(f, "Textures: {}", self.textures)?;
+        writeln!(f, "Meshes: {}", self.meshes)?;
+        write!(f, "Models: {}", self.models)
+    }
+}
+
+// Assets queued for loading and how many of them are in
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LoadProgress {
+    pub done: usize,
+    pub total: usize,
+}
+
+impl LoadProgress {
+    // 0 to 1, for a loading bar
+    pub fn get_fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.done as f32 / self.total as f32
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done >= self.total
+    }
+}
+
+// Read and decoded on a worker, waiting for its GL upload
+enum Decoded {
+    Texture(TextureId, TextureData),
+    Model(ModelId, Vec<ModelGeometry>),
+}
+
+// Assets listed in the manifest, loaded per scope. Handles index straight into the stores, so
+// lookups don't lock. Holds GL objects: create it once the context is current and drop it
+// before the window.
+pub struct Assets {
+    // Shared with the workers
+    pub vfs: Arc<Vfs>,
+    pub manifest: Manifest,
+    programs: AssetStore<ShaderProgram>,
+    textures: AssetStore<Texture>,
+    meshes: AssetStore<Mesh>,
+    models: AssetStore<Model>,
+    // Built from level data instead of the manifest, dropped with the level scope
+    level_meshes: RefCell<Vec<Handle<Mesh>>>,
+
+    // Textures and models are decoded in the background, see queue_scope
+    workers: WorkerPool<(AssetScope, Decoded)>,
+    pending: RefCell<BTreeMap<AssetScope, LoadProgress>>,
+}
+
+impl Assets {
+    // Loads the global scope, decoding on all workers. A broken manifest, image or shader is fatal,
+    // the error names the file at fault.
+    pub fn load(vfs: Vfs) -> Assets {
+        let manifest = Manifest::load(&vfs, MANIFEST_FILE).unwrap_or_else(|error| panic!("{}", error));
+
+        let assets = Assets {
+            programs: AssetStore::new(manifest.program_ids().count()),
+            textures: AssetStore::new(manifest.texture_ids().count()),
+            meshes: AssetStore::new(manifest.mesh_ids().count()),
+            models: AssetStore::new(manifest.model_ids().count()),
+            level_meshes: RefCell::new(Vec::new()),
+            vfs: Arc::new(vfs),
+            manifest,
+            workers: WorkerPool::with_available_parallelism(),
+            pending: RefCell::new(BTreeMap::new()),
+        };
+        assets.queue_scope(AssetScope::Global);
+        assets.finish_loading();
+        assets
+    }
+
+    // Starts decoding the textures and models of the scope on the workers, update_loading uploads
+    // them. Programs and meshes are built once everything else is in.
+    pub fn queue_scope(&self, scope: AssetScope) {
+        let mut queued = 0;
+        for id in self.manifest.texture_ids() {
+            let entry = self.manifest.get_texture(id);
+            if entry.scope == scope && self.textures.get(id.0).is_none() {
+                let (vfs, entry) = (self.vfs.clone(), entry.clone());
+                self.workers.spawn(move || (scope, Decoded::Texture(id, TextureData::decode(&vfs, &entry))));
+                queued += 1;
+            }
+        }
+        for id in self.manifest.model_ids() {
+            let entry = self.manifest.get_model(id);
+            if entry.scope == scope && self.models.get(id.0).is_none() {
+                let (vfs, path) = (self.vfs.clone(), entry.path.clone());
+                self.workers.spawn(move || (scope, Decoded::Model(id, Model::load_geometry(&vfs, path))));
+                queued += 1;
+            }
+        }
+
+        // Finishing the scope counts as one step, it compiles the programs
+        self.pending.borrow_mut()
+            .entry(scope)
+            .or_insert(LoadProgress { done: 0, total: 1 })
+            .total += queued;
+    }
+
+    // Call once per frame on the GL thread. Uploads decoded assets until the budget is spent, and
+    // finishes scopes whose assets are all in. The last upload may overrun the budget.
+    pub fn update_loading(&self, budget: Duration) -> LoadProgress {
+        let start = Instant::now();
+        while start.elapsed() < budget && self.is_decoding() {
+            match self.workers.try_recv() {
+                Some(result) => self.upload(result),
+                None => break,
+            }
+        }
+        self.finish_decoded_scopes();
+        self.get_progress()
+    }
+
+    // Uploads everything queued, blocking until the workers are done
+    pub fn finish_loading(&self) {
+        while self.is_decoding() {
+            self.upload(self.workers.recv());
+        }
+        self.finish_decoded_scopes();
+    }
+
+    pub fn get_progress(&self) -> LoadProgress {
+        self.pending.borrow()
+            .values()
+            .fold(LoadProgress::default(), |progress, scope| LoadProgress {
+                done: progress.done + scope.done,
+                total: progress.total + scope.total,
+            })
+    }
+
+    pub fn is_loading(&self) -> bool {
+        !self.pending.borrow().is_empty()
+    }
+
+    // Decoded assets still on their way, the finishing step of each scope is not one of them
+    fn is_decoding(&self) -> bool {
+        self.pending.borrow().values().any(|progress| progress.done + 1 < progress.total)
+    }
+
+    fn upload(&self, result: Result<(AssetScope, Decoded), String>) {
+        let (scope, decoded) = result.unwrap_or_else(|error| panic!("{}", error));
+        match decoded {
+            Decoded::Texture(id, data) => {
+                self.textures.acquire(id.0, scope, || Texture::upload(&data, &self.manifest.get_texture(id).sampler));
+            }
+            Decoded::Model(id, geometry) => {
+                let program = self.acquire_program(self.manifest.get_model(id).program, scope);
+                self.models.acquire(id.0, scope, || Model::upload(geometry, program));
+            }
+        }
+        if let Some(progress) = self.pending.borrow_mut().get_mut(&scope) {
+            progress.done += 1;
+        }
+    }
+
+    fn finish_decoded_scopes(&self) {
+        let decoded: Vec<AssetScope> = self.pending.borrow()
+            .iter()
+            .filter(|(_, progress)| progress.done + 1 >= progress.total)
+            .map(|(scope, _)| *scope)
+            .collect();
+        for scope in decoded {
+            self.load_scope(scope);
+            self.pending.borrow_mut().remove(&scope);
+        }
+    }
+
+    // Loads every manifest entry of the scope on this thread. What they refer to joins the scope
+    // too, so a level mesh keeps its texture loaded even when the texture is listed for another scope.
+    pub fn load_scope(&self, scope: AssetScope) {
+        for id in self.manifest.program_ids().filter(|id| self.manifest.get_program(*id).scope == scope) {
+            self.acquire_program(id, scope);
+        }
+        for id in self.manifest.texture_ids().filter(|id| self.manifest.get_texture(*id).scope == scope) {
+            self.acquire_texture(id, scope);
+        }
+        for id in self.manifest.mesh_ids().filter(|id| self.manifest.get_mesh(*id).scope == scope) {
+            self.acquire_mesh(id, scope);
+        }
+        for id in self.manifest.model_ids().filter(|id| self.manifest.get_model(*id).scope == scope) {
+            self.acquire_model(id, scope);
+        }
+    }
+
+    // Unloads what only the scope held, returns how many assets were unloaded.
+    // GL objects are freed once the last handle to them is dropped.
+    pub fn unload_scope(&self, scope: AssetScope) -> usize {
+        let level_meshes = if scope == AssetScope::Level { self.level_meshes.take().len() } else { 0 };
+        // Dependents first, they hold handles to the rest
+        level_meshes
+            + self.models.unload_scope(scope)
+            + self.meshes.unload_scope(scope)
+            + self.textures.unload_scope(scope)
+            + self.programs.unload_scope(scope)
+    }
+
+    pub fn acquire_program(&self, id: ShaderProgramId, scope: AssetScope) -> Handle<ShaderProgram> {
+        self.programs.acquire(id.0, scope, || shader_registry::load_program(&self.vfs, &self.manifest, id))
+    }
+
+    pub fn acquire_texture(&self, id: TextureId, scope: AssetScope) -> Handle<Texture> {
+        self.textures.acquire(id.0, scope, || texture_registry::load_texture(&self.vfs, &self.manifest, id))
+    }
+
+    pub fn acquire_mesh(&self, id: MeshId, scope: AssetScope) -> Handle<Mesh> {
+        self.meshes.acquire(id.0, scope, || {
+            let entry = self.manifest.get_mesh(id);
+            let program = self.acquire_program(entry.program, scope);
+            let texture = entry.texture.map(|texture_id| self.acquire_texture(texture_id, scope));
+            mesh_registry::load_mesh(entry, program, texture)
+        })
+    }
+
+    pub fn acquire_model(&self, id: ModelId, scope: AssetScope) -> Handle<Model> {
+        self.models.acquire(id.0, scope, || {
+            let program = self.acquire_program(self.manifest.get_model(id).program, scope);
+            model_registry::load_model(&self.vfs, &self.manifest, id, program)
+        })
+    }
+
+    // Keeps a mesh built from level data until the level scope is unloaded. Its program and
+    // textures should be acquired for the level scope, so they can be unloaded with it.
+    pub fn add_level_mesh(&self, mesh: Mesh) -> LevelMeshId {
+        let mut level_meshes = self.level_meshes.borrow_mut();
+        level_meshes.push(Handle::new(mesh));
+        LevelMeshId(level_meshes.len() - 1)
+    }
+
+    // None once the level scope is unloaded
+    pub fn get_level_mesh(&self, id: LevelMeshId) -> Option<Handle<Mesh>> {
+        self.level_meshes.borrow().get(id.0).cloned()
+    }
+
+    // Lookups panic when the asset's scope is not loaded
+
+    pub fn get_program(&self, id: ShaderProgramId) -> Handle<ShaderProgram> {
+        self.programs.get(id.0)
+            .unwrap_or_else(|| panic!("Program '{}' is not loaded", self.manifest.get_program_key(id)))
+    }
+
+    pub fn get_texture(&self, id: TextureId) -> Handle<Texture> {
+        self.textures.get(id.0)
+            .unwrap_or_else(|| panic!("Texture '{}' is not loaded", self.manifest.get_texture_key(id)))
+    }
+
+    pub fn get_mesh(&self, id: MeshId) -> Handle<Mesh> {
+        self.meshes.get(id.0)
+            .unwrap_or_else(|| panic!("Mesh '{}' is not loaded", self.manifest.get_mesh_key(id)))
+    }
+
+    pub fn get_model(&self, id: ModelId) -> Handle<Model> {
+        self.models.get(id.0)
+            .unwrap_or_else(|| panic!("Model '{}' is not loaded", self.manifest.get_model_key(id)))
+    }
+
+    pub fn get_memory_report(&self) -> MemoryReport {
+        MemoryReport {
+            programs: self.programs.get_usage(),
+            textures: self.textures.get_usage(),
+            meshes: self.level_meshes.borrow()
+                .iter()
+                .fold(self.meshes.get_usage(), |usage, mesh| AssetUsage {
+                    count: usage.count + 1,
+                    bytes: usage.bytes + mesh.get_memory_size(),
+                }),
+            models: self.models.get_usage(),
+        }
+    }
+
+    // Recompiles loaded programs whose source files changed on disk, returns the outcome of every
+    // reload. Programs that fail keep running their previous version.
+    pub fn reload_changed_programs(&self) -> Vec<(ShaderProgramId, Result<(), ShaderError>)> {
+        self.programs.loaded()
+            .into_iter()
+            .map(ShaderProgramId)
+            .filter_map(|program_id| {
+                self.get_program(program_id).reload_if_changed(&self.vfs).map(|result| (program_id, result))
+            })
+            .collect()
+    }
+}
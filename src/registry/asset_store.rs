@@ -0,0 +1,197 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+// What an asset is loaded for. Unloading a scope frees the assets no other scope or handle holds.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssetScope {
+    // Loaded at startup and kept until shutdown
+    #[default]
+    Global,
+    Menu,
+    // The current map, unloaded when switching levels
+    Level,
+}
+
+impl fmt::Display for AssetScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetScope::Global => write!(f, "global"),
+            AssetScope::Menu => write!(f, "menu"),
+            AssetScope::Level => write!(f, "level"),
+        }
+    }
+}
+
+// Counted reference to a loaded asset. The asset is freed once the scopes holding it are
+// unloaded and the last handle is dropped.
+pub struct Handle<T>(Arc<T>);
+
+impl<T> Handle<T> {
+    pub fn new(asset: T) -> Self {
+        Handle(Arc::new(asset))
+    }
+
+    // Handles alive, including the one the store keeps while the asset is loaded
+    #[cfg(test)]
+    pub fn ref_count(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Handle(self.0.clone())
+    }
+}
+
+impl<T> Deref for Handle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+// Memory an asset takes on the GPU, for usage reports
+pub trait AssetMemory {
+    fn get_memory_size(&self) -> usize;
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AssetUsage {
+    pub count: usize,
+    pub bytes: usize,
+}
+
+impl fmt::Display for AssetUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} loaded, {:.1} KiB", self.count, self.bytes as f32 / 1024.0)
+    }
+}
+
+struct StoreEntry<T> {
+    asset: Handle<T>,
+    scopes: BTreeSet<AssetScope>,
+}
+
+// Loaded assets of one type, indexed like their manifest section. Not thread safe, it lives on
+// the thread with the GL context.
+pub struct AssetStore<T> {
+    entries: RefCell<Vec<Option<StoreEntry<T>>>>,
+}
+
+impl<T: AssetMemory> AssetStore<T> {
+    pub fn new(len: usize) -> Self {
+        AssetStore {
+            entries: RefCell::new((0..len).map(|_| None).collect()),
+        }
+    }
+
+    // Loads the asset unless it already is, and keeps it until `scope` is unloaded
+    pub fn acquire(&self, index: usize, scope: AssetScope, load: impl FnOnce() -> T) -> Handle<T> {
+        if let Some(entry) = self.entries.borrow_mut()[index].as_mut() {
+            entry.scopes.insert(scope);
+            return entry.asset.clone();
+        }
+
+        // Not borrowed while loading, loaders acquire their dependencies from other stores
+        let asset = Handle::new(load());
+        self.entries.borrow_mut()[index] = Some(StoreEntry {
+            asset: asset.clone(),
+            scopes: BTreeSet::from([scope]),
+        });
+        asset
+    }
+
+    // None when the asset is not loaded
+    pub fn get(&self, index: usize) -> Option<Handle<T>> {
+        self.entries.borrow()[index].as_ref().map(|entry| entry.asset.clone())
+    }
+
+    // Indices of the loaded assets
+    pub fn loaded(&self) -> Vec<usize> {
+        self.entries.borrow()
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.is_some())
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    // Drops the scope from every asset and unloads the ones no scope holds anymore, returns how
+    // many were unloaded. Outstanding handles keep their asset alive until they are dropped.
+    pub fn unload_scope(&self, scope: AssetScope) -> usize {
+        let mut unloaded = 0;
+        for slot in self.entries.borrow_mut().iter_mut() {
+            let Some(entry) = slot else {
+                continue;
+            };
+            if entry.scopes.remove(&scope) && entry.scopes.is_empty() {
+                *slot = None;
+                unloaded += 1;
+            }
+        }
+        unloaded
+    }
+
+    pub fn get_usage(&self) -> AssetUsage {
+        self.entries.borrow()
+            .iter()
+            .flatten()
+            .fold(AssetUsage::default(), |usage, entry| AssetUsage {
+                count: usage.count + 1,
+                bytes: usage.bytes + entry.asset.get_memory_size(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Blob(usize);
+
+    impl AssetMemory for Blob {
+        fn get_memory_size(&self) -> usize {
+            self.0
+        }
+    }
+
+    #[test]
+    fn loads_once_and_counts_handles() {
+        let store = AssetStore::new(2);
+        let mut loads = 0;
+        let first = store.acquire(0, AssetScope::Global, || { loads += 1; Blob(100) });
+        let second = store.acquire(0, AssetScope::Level, || { loads += 1; Blob(100) });
+
+        assert_eq!(loads, 1);
+        // Both handles and the store
+        assert_eq!(first.ref_count(), 3);
+        assert!(store.get(1).is_none());
+        assert_eq!(store.get_usage(), AssetUsage { count: 1, bytes: 100 });
+        drop(second);
+    }
+
+    #[test]
+    fn unloads_assets_only_held_by_the_scope() {
+        let store = AssetStore::new(3);
+        store.acquire(0, AssetScope::Global, || Blob(10));
+        store.acquire(1, AssetScope::Level, || Blob(20));
+        store.acquire(0, AssetScope::Level, || Blob(10));
+        let held = store.acquire(2, AssetScope::Level, || Blob(40));
+
+        assert_eq!(store.unload_scope(AssetScope::Level), 2);
+        assert_eq!(store.loaded(), vec![0]);
+        assert_eq!(store.get_usage(), AssetUsage { count: 1, bytes: 10 });
+
+        // Unloaded from the store, but alive until the last handle goes
+        assert_eq!(held.ref_count(), 1);
+        assert_eq!(held.get_memory_size(), 40);
+    }
+}
@@ -1,94 +1,136 @@
-use std::collections::HashMap;
-use std::sync::RwLock;
-
-use once_cell::sync::Lazy;
+use serde::Deserialize;
 
+use crate::assets::manifest::{Manifest, MeshEntry};
 use crate::graphics::mesh::Mesh;
-use crate::registry::shader_registry::{SHADER_REGISTRY, ShaderProgramId};
-use crate::registry::texture_registry::TextureId;
+use crate::graphics::program::ShaderProgram;
+use crate::graphics::texture::Texture;
+use crate::graphics::vertex_layout::VertexLayout;
+use crate::registry::asset_store::Handle;
+
+// Mesh listed in the asset manifest
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct MeshId(pub(crate) usize);
 
+// Mesh built from level data, e.g. batched sector walls, kept by the level scope
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
-pub enum MeshId {
-    Rect,
-    TexturedRect,
+pub struct LevelMeshId(pub(crate) usize);
+
+// Geometry built into the engine, manifest meshes pick one
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeshShape {
+    // Unit quad facing +z with uvs
     Quad,
+    // Quad with normals
     Wall,
+    // Quad with a light level per vertex
+    SectorWall,
+    // Cube around the camera, for skies
+    Cube,
+    // Horizontal quad facing down
+    Ceiling,
 }
 
-
-impl MeshId {
+impl MeshShape {
     pub fn get_vbo(&self) -> Vec<f32> {
         match self {
-            MeshId::Quad | MeshId::Rect | MeshId::TexturedRect | MeshId::Wall => vec![
+            MeshShape::Quad => vec![
                 -0.5, -0.5, 0.0, 0.0, 0.0,  // Bottom-left
                 0.5, -0.5, 0.0, 1.0, 0.0,  // Bottom-right
                 0.5, 0.5, 0.0, 1.0, 1.0,  // Top-right
                 -0.5, 0.5, 0.0, 0.0, 1.0,   // Top-left
+            ],
+            // Facing +z
+            MeshShape::Wall => vec![
+                -0.5, -0.5, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0,  // Bottom-left
+                0.5, -0.5, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0,  // Bottom-right
+                0.5, 0.5, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0,  // Top-right
+                -0.5, 0.5, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0,   // Top-left
+            ],
+            // Light is only used when drawn with per vertex light
+            MeshShape::SectorWall => vec![
+                -0.5, -0.5, 0.0, 0.0, 0.0, 255.0,  // Bottom-left
+                0.5, -0.5, 0.0, 1.0, 0.0, 255.0,  // Bottom-right
+                0.5, 0.5, 0.0, 1.0, 1.0, 255.0,  // Top-right
+                -0.5, 0.5, 0.0, 0.0, 1.0, 255.0,   // Top-left
+            ],
+            MeshShape::Cube => vec![
+                -1.0, -1.0, -1.0,
+                1.0, -1.0, -1.0,
+                1.0, 1.0, -1.0,
+                -1.0, 1.0, -1.0,
+                -1.0, -1.0, 1.0,
+                1.0, -1.0, 1.0,
+                1.0, 1.0, 1.0,
+                -1.0, 1.0, 1.0,
+            ],
+            // Lit like sector walls, the sky shader only reads the position
+            MeshShape::Ceiling => vec![
+                -0.5, 0.0, -0.5, 0.0, 0.0, 255.0,
+                0.5, 0.0, -0.5, 1.0, 0.0, 255.0,
+                0.5, 0.0, 0.5, 1.0, 1.0, 255.0,
+                -0.5, 0.0, 0.5, 0.0, 1.0, 255.0,
             ]
         }
     }
 
+    pub fn get_layout(&self) -> VertexLayout {
+        match self {
+            MeshShape::Quad => VertexLayout::position_uv(),
+            MeshShape::Wall => VertexLayout::position_uv_normal(),
+            MeshShape::SectorWall | MeshShape::Ceiling => VertexLayout::position_uv_light(),
+            MeshShape::Cube => VertexLayout::position(),
+        }
+    }
+
     pub fn get_ebo(&self) -> Vec<u32> {
         match self {
-            MeshId::Quad | MeshId::Rect | MeshId::TexturedRect | MeshId::Wall => vec![
+            MeshShape::Quad | MeshShape::Wall | MeshShape::SectorWall | MeshShape::Ceiling => vec![
                 0, 1, 2,
                 2, 3, 0,
+            ],
+            // Seen from the inside
+            MeshShape::Cube => vec![
+                0, 1, 2, 2, 3, 0, // Back
+                4, 7, 6, 6, 5, 4, // Front
+                0, 3, 7, 7, 4, 0, // Left
+                1, 5, 6, 6, 2, 1, // Right
+                3, 2, 6, 6, 7, 3, // Top
+                0, 4, 5, 5, 1, 0, // Bottom
             ]
         }
     }
 }
 
-pub(crate) static MESH_REGISTRY: Lazy<RwLock<HashMap<MeshId, Mesh>>> = Lazy::new(|| {
-    let mut registry = HashMap::new();
-
-    // Ortho ----
-    // Rect mesh
-    let ortho_program = SHADER_REGISTRY.read()
-        .unwrap()
-        .get(&ShaderProgramId::Ortho)
-        .expect("ShaderProgramId::ORTHO not found")
-        .to_owned();
-
-    let point_mesh = Mesh::new(MeshId::Rect.get_vbo(), MeshId::Rect.get_ebo(), ortho_program, None);
-    registry.insert(MeshId::Rect, point_mesh);
-
-
-    // Textured rect mesh
-    let texture_program = SHADER_REGISTRY.read()
-        .unwrap()
-        .get(&ShaderProgramId::TextureOrtho)
-        .expect("ShaderProgramId::TEXTURE_ORTHO not found")
-        .to_owned();
-
-    let texture_rect_mesh = Mesh::new(
-        MeshId::TexturedRect.get_vbo(),
-        MeshId::TexturedRect.get_ebo(),
-        texture_program,
-        Some(TextureId::Shotgun));
-    registry.insert(MeshId::TexturedRect, texture_rect_mesh);
-
-
-    // Perspective ----
-
-    // Quad mesh
-    let perspective_program = SHADER_REGISTRY.read()
-        .unwrap()
-        .get(&ShaderProgramId::Perspective)
-        .expect("ShaderProgramId::PERSPECTIVE not found")
-        .to_owned();
-
-    let perspective_mesh = Mesh::new(MeshId::Quad.get_vbo(), MeshId::Quad.get_ebo(), perspective_program, None);
-    registry.insert(MeshId::Quad, perspective_mesh);
-
-    // Wall mesh
-    let texture_perspective= SHADER_REGISTRY.read()
-        .unwrap()
-        .get(&ShaderProgramId::TexturePerspective)
-        .expect("ShaderProgramId::Texture perspective not found")
-        .to_owned();
+// Meshes the renderers draw with, resolved once from the manifest
+#[derive(Debug, Clone, Copy)]
+pub struct BuiltinMeshes {
+    pub rect: MeshId,
+    pub layered_rect: MeshId,
+    pub wall: MeshId,
+    pub sector_wall: MeshId,
+    pub skybox: MeshId,
+    pub sky_background: MeshId,
+    pub sky_ceiling: MeshId,
+    pub sector_ceiling: MeshId,
+}
 
-    let wall_mesh= Mesh::new(MeshId::Quad.get_vbo(), MeshId::Quad.get_ebo(), texture_perspective, Some(TextureId::BrickWall));
-    registry.insert(MeshId::Wall, wall_mesh);
+impl BuiltinMeshes {
+    pub fn resolve(manifest: &Manifest) -> Self {
+        Self {
+            rect: manifest.get_mesh_id("rect"),
+            layered_rect: manifest.get_mesh_id("layered_rect"),
+            wall: manifest.get_mesh_id("wall"),
+            sector_wall: manifest.get_mesh_id("sector_wall"),
+            skybox: manifest.get_mesh_id("skybox"),
+            sky_background: manifest.get_mesh_id("sky_background"),
+            sky_ceiling: manifest.get_mesh_id("sky_ceiling"),
+            sector_ceiling: manifest.get_mesh_id("sector_ceiling"),
+        }
+    }
+}
 
-    RwLock::new(registry)
-});
+pub fn load_mesh(entry: &MeshEntry, program: Handle<ShaderProgram>, texture: Option<Handle<Texture>>) -> Mesh {
+    let shape = entry.shape;
+    Mesh::new(shape.get_vbo(), shape.get_ebo(), shape.get_layout(), program, texture)
+}
@@ -0,0 +1,13 @@
+use crate::assets::manifest::Manifest;
+use crate::assets::vfs::Vfs;
+use crate::graphics::model::Model;
+use crate::graphics::program::ShaderProgram;
+use crate::registry::asset_store::Handle;
+
+// Model listed in the asset manifest
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ModelId(pub(crate) usize);
+
+pub fn load_model(vfs: &Vfs, manifest: &Manifest, id: ModelId, program: Handle<ShaderProgram>) -> Model {
+    Model::load(vfs, &manifest.get_model(id).path, program)
+}
@@ -1,85 +1,27 @@
-use std::collections::HashMap;
-use std::sync::RwLock;
-
-use once_cell::sync::Lazy;
-
+use crate::assets::manifest::Manifest;
+use crate::assets::vfs::Vfs;
 use crate::graphics::program::ShaderProgram;
 
-const ASSET_PATH: &str = "/home/lars/projects/rust/pocket-dimension/assets/shaders";
-
-// TODO redo this
-
-pub enum ShaderId {
-    VertexBasic,
-    FragmentBasic,
-    // Orthographic
-    VertexOrthographic,
-    FragmentOrthographic,
-    // Textured Orthographic
-    TextureVertexOrthographic,
-    TextureFragmentOrthographic,
-    // Perspective
-    VertexPerspective,
-    FragmentPerspective,
-    // Textured Perspective
-    TextureVertexPerspective,
-    TextureFragmentPerspective,
-
-}
-
-impl ShaderId {
-    pub(crate) fn get_path(&self) -> &'static str {
-        match self {
-            ShaderId::VertexBasic => "basic/vertex.glsl",
-            ShaderId::FragmentBasic => "basic/fragment.glsl",
-
-            ShaderId::VertexOrthographic => "ortho/vertex.glsl",
-            ShaderId::FragmentOrthographic => "ortho/fragment.glsl",
-
-            ShaderId::TextureVertexOrthographic => "texture_ortho/vertex.glsl",
-            ShaderId::TextureFragmentOrthographic => "texture_ortho/fragment.glsl",
-
-            ShaderId::VertexPerspective => "perspective/vertex.glsl",
-            ShaderId::FragmentPerspective => "perspective/fragment.glsl",
+// Shader includes resolve relative to this folder of the asset root
+const SHADER_FOLDER: &str = "shaders";
 
-            ShaderId::TextureVertexPerspective => "texture_perspective/vertex.glsl",
-            ShaderId::TextureFragmentPerspective => "texture_perspective/fragment.glsl",
-        }
-    }
-
-}
+// Shader file listed in the asset manifest
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ShaderId(pub(crate) usize);
 
+// Program listed in the asset manifest
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
-pub enum ShaderProgramId {
-    Basic,
-    Ortho,
-    TextureOrtho,
-    Perspective,
-    TexturePerspective,
+pub struct ShaderProgramId(pub(crate) usize);
+
+// A broken shader is fatal, the error names the file and line
+pub fn load_program(vfs: &Vfs, manifest: &Manifest, id: ShaderProgramId) -> ShaderProgram {
+    let entry = manifest.get_program(id);
+    let program = ShaderProgram::new(vfs,
+                                     manifest.get_root().join(SHADER_FOLDER),
+                                     manifest.get_shader_path(entry.vertex),
+                                     manifest.get_shader_path(entry.fragment),
+                                     &entry.defines)
+        .unwrap_or_else(|error| panic!("{}", error));
+    program.build().unwrap_or_else(|error| panic!("{}", error));
+    program
 }
-
-pub static SHADER_REGISTRY: Lazy<RwLock<HashMap<ShaderProgramId, ShaderProgram>>> = Lazy::new(|| {
-    let mut registry = HashMap::new();
-
-    // Orthographic shader
-    let mut ortho_program = ShaderProgram::new(ASSET_PATH, ShaderId::VertexOrthographic, ShaderId::FragmentOrthographic);
-    ortho_program.build().expect("Failed to build ortho program");
-    registry.insert(ShaderProgramId::Ortho, ortho_program);
-
-    // Texture orthographic shader
-    let mut texture_ortho_program = ShaderProgram::new(ASSET_PATH, ShaderId::TextureVertexOrthographic, ShaderId::TextureFragmentOrthographic);
-    texture_ortho_program.build().expect("Failed to build texture ortho program");
-    registry.insert(ShaderProgramId::TextureOrtho, texture_ortho_program);
-
-    // Perspective shader
-    let mut perspective_program = ShaderProgram::new(ASSET_PATH, ShaderId::VertexPerspective, ShaderId::FragmentPerspective);
-    perspective_program.build().expect("Failed to build perspective program");
-    registry.insert(ShaderProgramId::Perspective, perspective_program);
-
-    // Texture perspective shader
-    let mut texture_perspective_program = ShaderProgram::new(ASSET_PATH, ShaderId::TextureVertexPerspective, ShaderId::TextureFragmentPerspective);
-    texture_perspective_program.build().expect("Failed to build texture perspective program");
-    registry.insert(ShaderProgramId::TexturePerspective, texture_perspective_program);
-
-    RwLock::new(registry)
-});
\ No newline at end of file
@@ -1,35 +1,11 @@
-use std::collections::HashMap;
-use std::sync::RwLock;
-
-use once_cell::sync::Lazy;
-
-use crate::graphics::texture::{Texture, TextureType};
-
-pub const TEXTURE_PATH: &str = "/home/lars/projects/rust/pocket-dimension/assets/textures";
+use crate::assets::manifest::Manifest;
+use crate::assets::vfs::Vfs;
+use crate::graphics::texture::Texture;
 
+// Texture listed in the asset manifest
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
-pub(crate) enum TextureId {
-    BrickWall,
-    Shotgun,
-}
+pub struct TextureId(pub(crate) usize);
 
-impl TextureId {
-    pub fn get_path(&self) -> &'static str {
-        match self {
-            TextureId::BrickWall => "doom_wall.png",
-            TextureId::Shotgun => "SHTFC0.png",
-        }
-    }
+pub fn load_texture(vfs: &Vfs, manifest: &Manifest, id: TextureId) -> Texture {
+    Texture::new(vfs, manifest.get_texture(id))
 }
-
-pub static TEXTURE_REGISTRY: Lazy<RwLock<HashMap<TextureId, Texture>>> = Lazy::new(|| {
-    let mut registry = HashMap::new();
-
-    let brick_wall = Texture::new(TextureId::BrickWall, TextureType::SPRITE);
-    registry.insert(TextureId::BrickWall, brick_wall);
-
-    let shotgun = Texture::new(TextureId::Shotgun, TextureType::SPRITE);
-    registry.insert(TextureId::Shotgun, shotgun);
-
-    RwLock::new(registry)
-});
\ No newline at end of file